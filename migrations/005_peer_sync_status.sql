@@ -0,0 +1,10 @@
+-- Per-peer replication health, updated as remote catalog entries are ingested
+CREATE TABLE peer_sync_status (
+    node_id           TEXT NOT NULL,
+    sync_kind         TEXT NOT NULL,
+    last_attempt_at   TEXT,
+    last_success_at   TEXT,
+    last_error        TEXT,
+    items_transferred INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (node_id, sync_kind)
+);
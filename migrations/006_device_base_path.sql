@@ -0,0 +1,2 @@
+-- Path prefix a device's HTTP API is mounted under ('' for the root)
+ALTER TABLE devices ADD COLUMN base_path TEXT NOT NULL DEFAULT '';
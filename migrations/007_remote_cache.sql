@@ -0,0 +1,9 @@
+-- Local copies of content fetched from other nodes, evicted least recently used first
+CREATE TABLE remote_cache (
+    cid          TEXT PRIMARY KEY,
+    size         INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    cached_at    TEXT NOT NULL DEFAULT (datetime('now')),
+    last_access  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+CREATE INDEX idx_remote_cache_last_access ON remote_cache(last_access);
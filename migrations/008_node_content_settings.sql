@@ -0,0 +1,7 @@
+-- Per-node controls over catalog entries synced in from other nodes
+CREATE TABLE node_content_settings (
+    node_id    TEXT PRIMARY KEY,
+    muted      INTEGER NOT NULL DEFAULT 0,
+    blocked    INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
@@ -0,0 +1,9 @@
+-- Daemon starts and stops; a start with no stop before it means a crash
+CREATE TABLE node_lifecycle (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    event          TEXT NOT NULL,
+    version        TEXT NOT NULL,
+    at             TEXT NOT NULL,
+    -- crash_detected only: when the run that crashed had started
+    previous_start TEXT
+);
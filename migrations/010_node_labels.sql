@@ -0,0 +1,10 @@
+-- Free-form tags for grouping devices, e.g. "kids" or "infra".
+-- Labels are stored trimmed and lowercased (node_labels::normalize).
+CREATE TABLE node_labels (
+    node_id    TEXT NOT NULL,
+    label      TEXT NOT NULL COLLATE NOCASE,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (node_id, label)
+);
+
+CREATE INDEX idx_node_labels_label ON node_labels(label);
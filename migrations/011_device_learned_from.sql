@@ -0,0 +1,3 @@
+-- Peer that told this node about a device, for devices it has never seen
+-- itself (NULL for devices found by discovery or registered directly)
+ALTER TABLE devices ADD COLUMN learned_from TEXT;
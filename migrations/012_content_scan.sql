@@ -0,0 +1,5 @@
+-- Result of the optional content scanner for local files: pending_scan,
+-- clean or flagged. Files indexed before scanning existed count as clean.
+ALTER TABLE content_index ADD COLUMN scan_status TEXT NOT NULL DEFAULT 'clean';
+CREATE INDEX idx_content_index_scan_status ON content_index(scan_status)
+    WHERE scan_status != 'clean';
@@ -0,0 +1,10 @@
+-- What a migration's code step wants the owner to hear about, shown with
+-- the release notes after the upgrade. Lettered so it sorts before 013,
+-- the first migration to record one. IF NOT EXISTS because builds before
+-- this file created the table outside the migration list
+CREATE TABLE IF NOT EXISTS migration_notices (
+    migration    TEXT PRIMARY KEY,
+    message      TEXT NOT NULL,
+    created_at   TEXT NOT NULL DEFAULT (datetime('now')),
+    dismissed_at TEXT
+);
@@ -0,0 +1,25 @@
+-- Foreign keys were only enforced on the pool's first connection, so
+-- deleting catalog entries elsewhere left their thumbnails and previews
+-- behind. They are enforced on every connection from now on; what was
+-- orphaned meanwhile is removed (and counted) by db::remove_orphans
+-- before this runs.
+
+-- Labels belong to a device and go with it (e.g. when a pending device
+-- expires). SQLite can't add a foreign key in place, so the table is
+-- rebuilt.
+-- peer_sync_status and node_content_settings stay unconstrained: they
+-- track catalog origins, which needn't be in devices.
+CREATE TABLE node_labels_new (
+    node_id    TEXT NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    label      TEXT NOT NULL COLLATE NOCASE,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (node_id, label)
+);
+INSERT INTO node_labels_new (node_id, label, created_at)
+    SELECT node_id, label, created_at FROM node_labels;
+DROP TABLE node_labels;
+ALTER TABLE node_labels_new RENAME TO node_labels;
+CREATE INDEX idx_node_labels_label ON node_labels(label);
+
+-- Catalog "since" queries and recent-entry lists order by indexed_at
+CREATE INDEX idx_content_index_indexed_at ON content_index(indexed_at);
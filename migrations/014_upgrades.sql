@@ -0,0 +1,9 @@
+-- Version changes seen at startup, so the release notes in between can be
+-- shown until the owner dismisses them
+CREATE TABLE upgrades (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_version TEXT NOT NULL,
+    to_version   TEXT NOT NULL,
+    at           TEXT NOT NULL,
+    dismissed_at TEXT
+);
@@ -0,0 +1,18 @@
+-- Local catalog entries whose file changed, waiting to be deleted from the
+-- shared catalog document
+CREATE TABLE catalog_retractions (
+    cid        TEXT PRIMARY KEY,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Deletes received from peers, kept for a while so an entry from the same
+-- author that is not newer than the delete stays deleted
+CREATE TABLE catalog_tombstones (
+    cid         TEXT NOT NULL,
+    author      TEXT NOT NULL,
+    -- Catalog document timestamp of the delete, in microseconds
+    deleted_at  INTEGER NOT NULL,
+    recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (cid, author)
+);
+CREATE INDEX idx_catalog_tombstones_recorded_at ON catalog_tombstones(recorded_at);
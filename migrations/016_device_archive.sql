@@ -0,0 +1,3 @@
+-- When the owner set a device aside (NULL for active devices). Separate
+-- from status so discovery and registration keep updating it as usual
+ALTER TABLE devices ADD COLUMN archived_at TEXT;
@@ -0,0 +1,13 @@
+-- When a local file was last re-hashed and found to match its cid
+ALTER TABLE content_index ADD COLUMN last_verified_at TEXT;
+
+-- Progress of the integrity verification pass, which runs a budgeted
+-- batch at a time. The cursor is the last (dir, path) verified; NULL
+-- starts a new pass
+CREATE TABLE integrity_state (
+    id                INTEGER PRIMARY KEY CHECK (id = 1),
+    cursor_dir        TEXT,
+    cursor_path       TEXT,
+    last_full_pass_at TEXT
+);
+INSERT INTO integrity_state (id) VALUES (1);
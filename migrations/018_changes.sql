@@ -0,0 +1,59 @@
+-- Journal of catalog and device changes, for clients catching up after
+-- being offline. Filled by triggers so every writer is covered; seq is
+-- never reused, so a client's cursor stays meaningful across restarts
+CREATE TABLE changes (
+    seq  INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    op   TEXT NOT NULL,
+    id   TEXT NOT NULL,
+    at   TEXT NOT NULL DEFAULT (datetime('now'))
+);
+CREATE INDEX idx_changes_at ON changes(at);
+
+-- Highest seq dropped by retention; older cursors must resync
+CREATE TABLE changes_state (
+    id             INTEGER PRIMARY KEY CHECK (id = 1),
+    pruned_through INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO changes_state (id) VALUES (1);
+
+CREATE TRIGGER changes_file_insert AFTER INSERT ON content_index
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('file', 'upsert', NEW.cid);
+END;
+
+-- Re-indexing and verification touch timestamps only; those aren't changes
+CREATE TRIGGER changes_file_update AFTER UPDATE ON content_index
+WHEN OLD.dir IS NOT NEW.dir OR OLD.path IS NOT NEW.path
+  OR OLD.filename IS NOT NEW.filename OR OLD.size IS NOT NEW.size
+  OR OLD.mime IS NOT NEW.mime OR OLD.file_type IS NOT NEW.file_type
+  OR OLD.modified IS NOT NEW.modified OR OLD.is_local IS NOT NEW.is_local
+  OR OLD.origin_node IS NOT NEW.origin_node OR OLD.scan_status IS NOT NEW.scan_status
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('file', 'upsert', NEW.cid);
+END;
+
+CREATE TRIGGER changes_file_delete AFTER DELETE ON content_index
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('file', 'delete', OLD.cid);
+END;
+
+CREATE TRIGGER changes_node_insert AFTER INSERT ON devices
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('node', 'upsert', NEW.id);
+END;
+
+-- Announcements refresh last_seen constantly; only report what a client shows
+CREATE TRIGGER changes_node_update AFTER UPDATE ON devices
+WHEN OLD.name IS NOT NEW.name OR OLD.endpoint IS NOT NEW.endpoint
+  OR OLD.port IS NOT NEW.port OR OLD.base_path IS NOT NEW.base_path
+  OR OLD.status IS NOT NEW.status OR OLD.learned_from IS NOT NEW.learned_from
+  OR OLD.archived_at IS NOT NEW.archived_at
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('node', 'upsert', NEW.id);
+END;
+
+CREATE TRIGGER changes_node_delete AFTER DELETE ON devices
+BEGIN
+    INSERT INTO changes (kind, op, id) VALUES ('node', 'delete', OLD.id);
+END;
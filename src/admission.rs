@@ -0,0 +1,240 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::config::{ApprovalMode, MeshConfig};
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Device status for a peer found on the network but not yet approved.
+pub const PENDING: &str = "pending";
+
+/// Device status for a peer the owner turned away. Kept so the peer
+/// isn't asked about again each time it announces itself.
+pub const REJECTED: &str = "rejected";
+
+/// Devices in either status are known but not members of the mesh.
+pub const NOT_ADMITTED: &str = "status IN ('pending', 'rejected')";
+
+/// What to do with a peer that announced itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Already a member, or newly admitted: register it as online
+    Member,
+    /// Record it, but only as awaiting approval
+    Pending,
+    /// The owner rejected it; ignore it
+    Rejected,
+    /// Not known and the mesh is at `max_devices`
+    MeshFull,
+}
+
+/// Decide whether a peer may join. Members stay members whatever the
+/// policy says now; the policy only applies to devices seen for the
+/// first time (or again after their pending entry expired).
+pub fn admit(
+    conn: &Connection,
+    policy: &MeshConfig,
+    device_id: &str,
+) -> rusqlite::Result<Admission> {
+    expire_pending(conn, policy)?;
+
+    let status: Option<String> = conn
+        .query_row(
+            "SELECT status FROM devices WHERE id = ?1",
+            params![device_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match status.as_deref() {
+        Some(PENDING) => return Ok(Admission::Pending),
+        Some(REJECTED) => return Ok(Admission::Rejected),
+        Some(_) => return Ok(Admission::Member),
+        None => {}
+    }
+
+    if is_full(conn, policy)? {
+        return Ok(Admission::MeshFull);
+    }
+    Ok(match policy.approval {
+        ApprovalMode::Auto => Admission::Member,
+        ApprovalMode::Manual => Admission::Pending,
+    })
+}
+
+/// Admit a pending or rejected device. Fails with a `mesh_full` conflict
+/// when the mesh is already at `max_devices`.
+pub fn approve(conn: &Connection, policy: &MeshConfig, device_id: &str) -> AppResult<()> {
+    awaiting_decision(conn, device_id)?;
+    if is_full(conn, policy)? {
+        return Err(AppError::Conflict {
+            code: ErrorCode::MeshFull,
+            message: format!("the mesh already has {} devices", policy.max_devices),
+        });
+    }
+    // Online if it announced itself recently, else the next announcement
+    // brings it online
+    conn.execute(
+        "UPDATE devices SET status = CASE
+             WHEN last_seen >= datetime('now', '-5 minutes') THEN 'online'
+             ELSE 'offline'
+         END
+         WHERE id = ?1",
+        params![device_id],
+    )?;
+    Ok(())
+}
+
+/// Turn a pending device away.
+pub fn reject(conn: &Connection, device_id: &str) -> AppResult<()> {
+    awaiting_decision(conn, device_id)?;
+    conn.execute(
+        "UPDATE devices SET status = ?2 WHERE id = ?1",
+        params![device_id, REJECTED],
+    )?;
+    Ok(())
+}
+
+fn awaiting_decision(conn: &Connection, device_id: &str) -> AppResult<()> {
+    let status: String = conn
+        .query_row(
+            "SELECT status FROM devices WHERE id = ?1 AND is_self = 0",
+            params![device_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(AppError::NotFound)?;
+    if status != PENDING && status != REJECTED {
+        return Err(AppError::BadRequest(
+            "Device is already a member of the mesh".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether admitting one more peer would exceed `max_devices`.
+fn is_full(conn: &Connection, policy: &MeshConfig) -> rusqlite::Result<bool> {
+    if policy.max_devices == 0 {
+        return Ok(false);
+    }
+    let members: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM devices WHERE is_self = 0 AND NOT {NOT_ADMITTED}"),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(members as usize >= policy.max_devices)
+}
+
+/// Forget pending devices nobody decided on within the expiry window. A
+/// peer that is still around shows up as pending again on its next
+/// announcement.
+pub fn expire_pending(conn: &Connection, policy: &MeshConfig) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM devices WHERE status = ?1 AND created_at < datetime('now', ?2)",
+        params![PENDING, format!("-{} hours", policy.pending_expiry_hours)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    fn add_device(conn: &Connection, id: &str, status: &str) {
+        conn.execute(
+            "INSERT INTO devices (id, name, status, last_seen) VALUES (?1, ?1, ?2, datetime('now'))",
+            params![id, status],
+        )
+        .unwrap();
+    }
+
+    fn policy(max_devices: usize, approval: ApprovalMode) -> MeshConfig {
+        MeshConfig {
+            max_devices,
+            approval,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn auto_mode_admits_everyone_by_default() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add_device(&conn, "known", "offline");
+
+        let policy = MeshConfig::default();
+        assert_eq!(admit(&conn, &policy, "known").unwrap(), Admission::Member);
+        assert_eq!(admit(&conn, &policy, "new").unwrap(), Admission::Member);
+    }
+
+    #[test]
+    fn cap_turns_away_new_devices_only() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add_device(&conn, "a", "online");
+        add_device(&conn, "b", "offline");
+        add_device(&conn, "waiting", PENDING);
+
+        let policy = policy(2, ApprovalMode::Auto);
+        assert_eq!(admit(&conn, &policy, "c").unwrap(), Admission::MeshFull);
+        assert_eq!(admit(&conn, &policy, "a").unwrap(), Admission::Member);
+
+        let err = approve(&conn, &policy, "waiting").unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Conflict {
+                code: ErrorCode::MeshFull,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn manual_mode_holds_new_devices_for_approval() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        let policy = policy(0, ApprovalMode::Manual);
+
+        assert_eq!(admit(&conn, &policy, "phone").unwrap(), Admission::Pending);
+        add_device(&conn, "phone", PENDING);
+        add_device(&conn, "tablet", PENDING);
+        assert_eq!(admit(&conn, &policy, "phone").unwrap(), Admission::Pending);
+
+        approve(&conn, &policy, "phone").unwrap();
+        assert_eq!(admit(&conn, &policy, "phone").unwrap(), Admission::Member);
+        assert!(matches!(
+            approve(&conn, &policy, "phone"),
+            Err(AppError::BadRequest(_))
+        ));
+
+        reject(&conn, "tablet").unwrap();
+        assert_eq!(
+            admit(&conn, &policy, "tablet").unwrap(),
+            Admission::Rejected
+        );
+        assert!(matches!(reject(&conn, "nobody"), Err(AppError::NotFound)));
+    }
+
+    #[test]
+    fn stale_pending_devices_expire() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add_device(&conn, "fresh", PENDING);
+        add_device(&conn, "stale", PENDING);
+        add_device(&conn, "old-member", "offline");
+        conn.execute_batch(
+            "UPDATE devices SET created_at = datetime('now', '-4 days')
+             WHERE id IN ('stale', 'old-member')",
+        )
+        .unwrap();
+
+        let policy = MeshConfig::default();
+        assert_eq!(expire_pending(&conn, &policy).unwrap(), 1);
+        let remaining: Vec<String> = conn
+            .prepare("SELECT id FROM devices ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(remaining, vec!["fresh", "old-member"]);
+    }
+}
@@ -1,14 +1,43 @@
 use futures_lite::StreamExt;
+use iroh_blobs::api::blobs::BlobStatus;
+use iroh_blobs::api::tags::TagInfo;
+use iroh_blobs::api::Store;
 use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::Hash;
+use iroh_docs::api::Doc;
 use iroh_docs::engine::LiveEvent;
 use iroh_docs::protocol::Docs;
 use iroh_docs::store::Query;
-use iroh_docs::{AuthorId, DocTicket, NamespaceId};
+use iroh_docs::{AuthorId, DocTicket, Entry, NamespaceId};
 use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+use crate::content_controls;
 use crate::db::DbPool;
+use crate::files;
+use crate::node_archive;
+use crate::sync_status::{self, SyncKind};
+use crate::tombstones;
+
+/// Tag prefix for published thumbnails; the rest of the name is the file's cid.
+const THUMBNAIL_TAG_PREFIX: &str = "thumb/";
+
+/// Prefix of the tags thumbnails were published under before they were
+/// named by cid. Nothing else in salita creates tags.
+const LEGACY_TAG_PREFIX: &str = "auto-";
+
+/// Outcome of a blob garbage collection pass.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    pub tags_removed: u64,
+    pub blobs_released: u64,
+    /// Bytes the blob store frees on its next sweep.
+    pub bytes_released: u64,
+}
 
 /// Manages the shared iroh-docs document for mesh catalog replication.
 pub struct CatalogSync {
@@ -128,6 +157,7 @@ impl CatalogSync {
 
     /// Publish a local catalog entry to the iroh-docs document.
     /// Called by the indexer after hashing and storing a file.
+    #[allow(clippy::too_many_arguments)]
     pub async fn publish_entry(
         &self,
         cid: &str,
@@ -148,8 +178,12 @@ impl CatalogSync {
 
         // If there's a thumbnail, store it as a blob and reference its hash
         let thumbnail_cid = if let Some(thumb) = thumbnail_bytes {
-            let tag_info = self.blobs.add_slice(thumb).await?;
-            Some(tag_info.hash.to_hex().to_string())
+            let tagged = self
+                .blobs
+                .add_slice(thumb)
+                .with_named_tag(format!("{THUMBNAIL_TAG_PREFIX}{cid}"))
+                .await?;
+            Some(tagged.hash.to_hex().to_string())
         } else {
             None
         };
@@ -173,6 +207,42 @@ impl CatalogSync {
         Ok(())
     }
 
+    /// Delete a local entry from the catalog document. Peers drop their
+    /// copy when the delete reaches them.
+    pub async fn retract_entry(&self, cid: &str) -> anyhow::Result<()> {
+        let api = self.docs.api();
+        let doc = api
+            .open(self.namespace)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Catalog document not found"))?;
+        doc.del(self.author, cid.as_bytes().to_vec()).await?;
+        Ok(())
+    }
+
+    /// Retract the entries the indexer queued for files that changed.
+    /// Ones that fail stay queued for the next cycle.
+    pub async fn retract_stale(sync: &Arc<Mutex<CatalogSync>>, pool: &DbPool) {
+        let pending = pool
+            .get()
+            .map_err(anyhow::Error::from)
+            .and_then(|conn| Ok(tombstones::pending_retractions(&conn)?));
+        let pending = match pending {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::warn!("Failed to read pending catalog retractions: {e}");
+                return;
+            }
+        };
+
+        for cid in pending {
+            let result = sync.lock().await.retract_entry(&cid).await;
+            let result = result.and_then(|()| Ok(tombstones::retracted(&*pool.get()?, &cid)?));
+            if let Err(e) = result {
+                tracing::debug!("Failed to retract catalog entry {cid}: {e}");
+            }
+        }
+    }
+
     /// Subscribe to remote catalog changes and ingest them into the local DB.
     /// Runs as a background task.
     pub async fn subscribe_and_ingest(sync: Arc<Mutex<CatalogSync>>) -> anyhow::Result<()> {
@@ -180,6 +250,7 @@ impl CatalogSync {
             let s = sync.lock().await;
             (s.namespace, s.pool.clone(), s.docs.clone(), s.blobs.clone())
         };
+        let own_author = sync.lock().await.author;
 
         let api = docs.api();
         let doc = api
@@ -200,17 +271,23 @@ impl CatalogSync {
 
             // Process remote insert events
             if let LiveEvent::InsertRemote { entry, content_status, .. } = event {
-                // Only process if content is complete
-                if !matches!(content_status, iroh_docs::sync::ContentStatus::Complete) {
-                    continue;
-                }
-
                 let key = entry.key().to_vec();
                 let cid = match String::from_utf8(key) {
                     Ok(s) => s,
                     Err(_) => continue,
                 };
 
+                // An empty entry is its author deleting the key
+                if entry.content_len() == 0 {
+                    apply_remote_delete(&pool, &doc, &blobs, own_author, &cid, &entry).await;
+                    continue;
+                }
+
+                // Only process if content is complete
+                if !matches!(content_status, iroh_docs::sync::ContentStatus::Complete) {
+                    continue;
+                }
+
                 // Read entry content from blob store
                 let content_hash = entry.content_hash();
                 let content = match blobs.get_bytes(content_hash).await {
@@ -229,10 +306,16 @@ impl CatalogSync {
                     }
                 };
 
+                if origin_blocked(&pool, &meta.origin_node) || deleted_since(&pool, &cid, &entry) {
+                    continue;
+                }
+
                 // Ingest into local database
-                if let Err(e) = ingest_remote_entry(&pool, &cid, &meta, &blobs).await {
+                let result = ingest_remote_entry(&pool, &cid, &meta, &blobs).await;
+                if let Err(ref e) = result {
                     tracing::warn!("Failed to ingest remote catalog entry {cid}: {e}");
                 }
+                record_sync_result(&pool, &meta.origin_node, &result);
             }
         }
 
@@ -247,6 +330,25 @@ impl CatalogSync {
             .await?
             .ok_or_else(|| anyhow::anyhow!("Catalog document not found"))?;
 
+        // Apply deletes first so an entry another node still publishes
+        // for the same file isn't removed after being ingested
+        let deletes = doc.get_many(Query::all().include_empty()).await?;
+        let mut deletes = std::pin::pin!(deletes);
+        while let Some(Ok(entry)) = deletes.next().await {
+            if entry.content_len() > 0 || entry.author() == self.author {
+                continue;
+            }
+            if let Ok(cid) = std::str::from_utf8(entry.key()) {
+                let (author, at) = (entry.author().to_string(), entry.timestamp());
+                let applied = with_conn(&self.pool, |conn| {
+                    tombstones::apply_remote_delete(conn, cid, &author, at)
+                });
+                if let Err(e) = applied {
+                    tracing::debug!("Failed to apply catalog delete of {cid}: {e}");
+                }
+            }
+        }
+
         let entries = doc.get_many(Query::all()).await?;
         let mut entries = std::pin::pin!(entries);
         let mut count = 0u64;
@@ -269,11 +371,16 @@ impl CatalogSync {
                 Err(_) => continue,
             };
 
-            // Skip our own entries
-            if meta.origin_node == self.node_id {
+            // Skip our own entries, those from blocked nodes and deleted ones
+            if meta.origin_node == self.node_id
+                || origin_blocked(&self.pool, &meta.origin_node)
+                || deleted_since(&self.pool, &cid, &entry)
+            {
                 continue;
             }
 
+            // Replaying the local replica says nothing about whether the
+            // origin is reachable, so sync status is left to live inserts
             if let Err(e) = ingest_remote_entry(&self.pool, &cid, &meta, &self.blobs).await {
                 tracing::debug!("Failed to ingest entry {cid}: {e}");
                 continue;
@@ -284,9 +391,234 @@ impl CatalogSync {
         tracing::info!("Initial catalog sync: ingested {count} remote entries");
         Ok(count)
     }
+
+    /// Release thumbnail blobs that no local file references any more so
+    /// the blob store can reclaim their space. Callers hold the CatalogSync
+    /// lock, so a publish can't re-tag a thumbnail between the snapshot of
+    /// live thumbnails and the delete.
+    pub async fn gc(&self) -> anyhow::Result<GcReport> {
+        let live = {
+            let conn = self.pool.get()?;
+            live_thumbnail_hashes(&conn)?
+        };
+        release_unreferenced_tags(&self.blobs, &live).await
+    }
+
+    /// Bytes the next GC pass would release, without deleting anything.
+    pub async fn orphaned_bytes(&self) -> anyhow::Result<u64> {
+        let live = {
+            let conn = self.pool.get()?;
+            live_thumbnail_hashes(&conn)?
+        };
+        unreferenced_bytes(&self.blobs, &live).await
+    }
+}
+
+/// Spawn a background task that runs a GC pass every `interval`.
+pub fn spawn_gc(sync: Arc<Mutex<CatalogSync>>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let result = sync.lock().await.gc().await;
+            match result {
+                Ok(report) if report.tags_removed > 0 => {
+                    tracing::info!(
+                        "Blob GC released {} blobs ({} bytes)",
+                        report.blobs_released,
+                        report.bytes_released
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Blob GC failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Hashes of the thumbnails of local files, which must stay published.
+fn live_thumbnail_hashes(conn: &rusqlite::Connection) -> rusqlite::Result<HashSet<Hash>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.thumbnail FROM content_thumbnails t
+         JOIN content_index c ON c.cid = t.cid
+         WHERE c.is_local = 1",
+    )?;
+    let rows = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+
+    let mut live = HashSet::new();
+    for thumbnail in rows {
+        live.insert(Hash::new(thumbnail?));
+    }
+    Ok(live)
+}
+
+/// Thumbnail tags whose blob isn't in `live`.
+async fn unreferenced_tags(store: &Store, live: &HashSet<Hash>) -> anyhow::Result<Vec<TagInfo>> {
+    let mut stale = Vec::new();
+    for prefix in [THUMBNAIL_TAG_PREFIX, LEGACY_TAG_PREFIX] {
+        let mut tags = store.tags().list_prefix(prefix).await?;
+        while let Some(tag) = tags.next().await {
+            let tag = tag?;
+            if !live.contains(&tag.hash) {
+                stale.push(tag);
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Size of a fully stored blob, or 0 if the store only has part of it.
+async fn complete_size(store: &Store, hash: Hash) -> anyhow::Result<u64> {
+    match store.blobs().status(hash).await? {
+        BlobStatus::Complete { size } => Ok(size),
+        _ => Ok(0),
+    }
+}
+
+/// Total size of the blobs `release_unreferenced_tags` would release.
+async fn unreferenced_bytes(store: &Store, live: &HashSet<Hash>) -> anyhow::Result<u64> {
+    let mut bytes = 0;
+    let mut seen = HashSet::new();
+    for tag in unreferenced_tags(store, live).await? {
+        if seen.insert(tag.hash) {
+            bytes += complete_size(store, tag.hash).await?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Delete thumbnail tags whose blob isn't in `live`.
+async fn release_unreferenced_tags(
+    store: &Store,
+    live: &HashSet<Hash>,
+) -> anyhow::Result<GcReport> {
+    let stale = unreferenced_tags(store, live).await?;
+
+    let mut report = GcReport::default();
+    let mut released = HashSet::new();
+    for tag in stale {
+        report.tags_removed += store.tags().delete(&tag.name).await?;
+        if released.insert(tag.hash) {
+            report.blobs_released += 1;
+            report.bytes_released += complete_size(store, tag.hash).await?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Whether entries from `origin_node` have been blocked, or the device
+/// archived, so they aren't synced in.
+fn origin_blocked(pool: &DbPool, origin_node: &str) -> bool {
+    let blocked = pool.get().map_err(anyhow::Error::from).and_then(|conn| {
+        Ok(content_controls::is_blocked(&conn, origin_node)?
+            || node_archive::is_archived(&conn, origin_node)?)
+    });
+    match blocked {
+        Ok(blocked) => blocked,
+        Err(e) => {
+            tracing::warn!("Failed to check whether {origin_node} is blocked: {e}");
+            false
+        }
+    }
+}
+
+fn with_conn<T>(
+    pool: &DbPool,
+    f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+) -> anyhow::Result<T> {
+    Ok(f(&*pool.get()?)?)
+}
+
+/// Whether `entry` is older than a delete its author made of the same key.
+fn deleted_since(pool: &DbPool, cid: &str, entry: &Entry) -> bool {
+    let (author, at) = (entry.author().to_string(), entry.timestamp());
+    let deleted = with_conn(pool, |conn| tombstones::is_deleted(conn, cid, &author, at));
+    match deleted {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            tracing::warn!("Failed to check whether {cid} was deleted: {e}");
+            false
+        }
+    }
+}
+
+/// Apply a peer's delete of `cid`. Another node may still publish the same
+/// file under its own author; if so, its newest entry takes over.
+async fn apply_remote_delete(
+    pool: &DbPool,
+    doc: &Doc,
+    blobs: &FsStore,
+    own_author: AuthorId,
+    cid: &str,
+    delete: &Entry,
+) {
+    let (author, at) = (delete.author().to_string(), delete.timestamp());
+    let removed = with_conn(pool, |conn| {
+        tombstones::apply_remote_delete(conn, cid, &author, at)
+    });
+    match removed {
+        Ok(true) => tracing::debug!("Removed remote entry {cid}, deleted by its author"),
+        Ok(false) => {}
+        Err(e) => {
+            tracing::warn!("Failed to apply catalog delete of {cid}: {e}");
+            return;
+        }
+    }
+
+    let Ok(others) = doc.get_many(Query::key_exact(cid)).await else {
+        return;
+    };
+    let mut others = std::pin::pin!(others);
+    let mut newest: Option<Entry> = None;
+    while let Some(Ok(other)) = others.next().await {
+        let newer = newest
+            .as_ref()
+            .is_none_or(|n| other.timestamp() > n.timestamp());
+        if newer && other.author() != own_author {
+            newest = Some(other);
+        }
+    }
+    let Some(other) = newest else {
+        return;
+    };
+    let Ok(content) = blobs.get_bytes(other.content_hash()).await else {
+        return;
+    };
+    let Ok(meta) = serde_json::from_slice::<CatalogEntryMeta>(&content) else {
+        return;
+    };
+    if origin_blocked(pool, &meta.origin_node) {
+        return;
+    }
+    if let Err(e) = ingest_remote_entry(pool, cid, &meta, blobs).await {
+        tracing::debug!("Failed to restore catalog entry {cid}: {e}");
+    }
+}
+
+/// Update peer_sync_status for the node an ingested entry came from.
+fn record_sync_result(pool: &DbPool, origin_node: &str, result: &anyhow::Result<()>) {
+    let conn = match pool.get() {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::debug!("Failed to record sync status: {e}");
+            return;
+        }
+    };
+
+    let recorded = match result {
+        Ok(()) => sync_status::record_success(&conn, origin_node, SyncKind::Catalog, 1),
+        Err(e) => {
+            sync_status::record_failure(&conn, origin_node, SyncKind::Catalog, &e.to_string())
+        }
+    };
+    if let Err(e) = recorded {
+        tracing::debug!("Failed to record sync status for {origin_node}: {e}");
+    }
 }
 
 /// Ingest a single remote catalog entry into the local SQLite database.
+#[cfg_attr(not(feature = "media"), allow(unused_variables))]
 async fn ingest_remote_entry(
     pool: &DbPool,
     cid: &str,
@@ -314,7 +646,7 @@ async fn ingest_remote_entry(
             cid,
             meta.dir,
             meta.path,
-            meta.filename,
+            files::sanitize_filename(&meta.filename),
             meta.size,
             meta.mime,
             meta.file_type,
@@ -323,7 +655,9 @@ async fn ingest_remote_entry(
         ],
     )?;
 
-    // Fetch and store thumbnail if referenced
+    // Fetch and store thumbnail if referenced. Without the media feature
+    // there's no decoder to read its dimensions, so it isn't kept.
+    #[cfg(feature = "media")]
     if let Some(ref thumb_hex) = meta.thumbnail_cid {
         let has_thumb: bool = conn
             .query_row(
@@ -363,3 +697,75 @@ async fn ingest_remote_entry(
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh_blobs::store::mem::MemStore;
+
+    #[test]
+    fn live_thumbnails_are_local_only() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+
+        for (cid, is_local) in [("local", 1), ("remote", 0)] {
+            conn.execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, is_local)
+                 VALUES (?1, 'd', ?1, ?1, 1, ?2)",
+                params![cid, is_local],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO content_thumbnails (cid, thumbnail, width, height)
+                 VALUES (?1, ?2, 1, 1)",
+                params![cid, cid.as_bytes()],
+            )
+            .unwrap();
+        }
+
+        let live = live_thumbnail_hashes(&conn).unwrap();
+        assert_eq!(live, HashSet::from([Hash::new("local")]));
+    }
+
+    #[tokio::test]
+    async fn gc_releases_only_unreferenced_thumbnails() {
+        let store = MemStore::new();
+        let kept = store
+            .add_slice(b"kept")
+            .with_named_tag("thumb/kept")
+            .await
+            .unwrap();
+        store
+            .add_slice(b"deleted")
+            .with_named_tag("thumb/deleted")
+            .await
+            .unwrap();
+        store.add_slice(b"legacy").with_tag().await.unwrap();
+        store
+            .add_slice(b"unrelated")
+            .with_named_tag("other")
+            .await
+            .unwrap();
+
+        let live = HashSet::from([kept.hash]);
+        let orphaned = unreferenced_bytes(&store, &live).await.unwrap();
+        let report = release_unreferenced_tags(&store, &live).await.unwrap();
+        assert_eq!(orphaned, report.bytes_released);
+        assert_eq!(
+            report,
+            GcReport {
+                tags_removed: 2,
+                blobs_released: 2,
+                bytes_released: (b"deleted".len() + b"legacy".len()) as u64,
+            }
+        );
+
+        assert!(store.tags().get("thumb/kept").await.unwrap().is_some());
+        assert!(store.tags().get("thumb/deleted").await.unwrap().is_none());
+        assert!(store.tags().get("other").await.unwrap().is_some());
+
+        // A second pass has nothing left to release
+        let report = release_unreferenced_tags(&store, &live).await.unwrap();
+        assert_eq!(report, GcReport::default());
+    }
+}
@@ -0,0 +1,241 @@
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+/// Days of history kept. A client away for longer has to resync.
+const RETENTION_DAYS: i64 = 30;
+
+/// Changes returned per page unless the client asks for fewer.
+pub const DEFAULT_PAGE: usize = 500;
+/// Most changes returned per page.
+pub const MAX_PAGE: usize = 1000;
+
+/// One entry of the journal. Only identifies what changed; the client
+/// fetches the current state of anything upserted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// "file" (a catalog entry, by cid) or "node" (a device, by id)
+    pub kind: String,
+    /// "upsert" or "delete"
+    pub op: String,
+    pub id: String,
+    pub at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangesPage {
+    /// Oldest first
+    pub changes: Vec<Change>,
+    /// Opaque; present it next time to continue after these changes
+    pub cursor: String,
+    /// More changes follow; ask again with `cursor` right away
+    pub has_more: bool,
+    /// The cursor was missing, unknown or older than the retained
+    /// history: reload everything, then continue from `cursor`
+    pub resync_required: bool,
+}
+
+/// Up to `limit` changes (capped at `MAX_PAGE`) after `cursor`.
+pub fn since(
+    conn: &Connection,
+    cursor: Option<&str>,
+    limit: usize,
+) -> rusqlite::Result<ChangesPage> {
+    let limit = limit.clamp(1, MAX_PAGE);
+    // sqlite_sequence keeps the last seq handed out even once it's pruned
+    let (head, pruned_through): (i64, i64) = conn.query_row(
+        "SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'changes'), 0),
+                pruned_through
+         FROM changes_state WHERE id = 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    // A cursor from before the oldest retained change, or from another
+    // database, can't be continued from without missing something
+    let after = cursor
+        .and_then(|cursor| cursor.parse::<i64>().ok())
+        .filter(|seq| (pruned_through..=head).contains(seq));
+    let Some(after) = after else {
+        return Ok(ChangesPage {
+            changes: Vec::new(),
+            cursor: head.to_string(),
+            has_more: false,
+            resync_required: true,
+        });
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT seq, kind, op, id, at FROM changes WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+    )?;
+    let mut rows = stmt
+        .query_map(params![after, limit as i64 + 1], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                Change {
+                    kind: row.get(1)?,
+                    op: row.get(2)?,
+                    id: row.get(3)?,
+                    at: row.get(4)?,
+                },
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let has_more = rows.len() > limit;
+    rows.truncate(limit);
+    let cursor = rows.last().map_or(after, |(seq, _)| *seq);
+    Ok(ChangesPage {
+        changes: rows.into_iter().map(|(_, change)| change).collect(),
+        cursor: cursor.to_string(),
+        has_more,
+        resync_required: false,
+    })
+}
+
+/// Drop changes older than the retention period, remembering how far so
+/// cursors from before then are told to resync.
+pub fn prune(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE changes_state SET pruned_through = MAX(
+             pruned_through,
+             COALESCE((SELECT MAX(seq) FROM changes WHERE at < datetime('now', ?1)), 0)
+         )
+         WHERE id = 1",
+        params![format!("-{RETENTION_DAYS} days")],
+    )?;
+    conn.execute(
+        "DELETE FROM changes WHERE seq <= (SELECT pruned_through FROM changes_state WHERE id = 1)",
+        [],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DebugConfig;
+    use crate::db::{self, DbPool};
+
+    fn open(path: &std::path::Path) -> DbPool {
+        let pool = db::create_pool(path, &DebugConfig::default()).unwrap();
+        db::run_migrations(&pool).unwrap();
+        pool
+    }
+
+    fn add_file(conn: &Connection, cid: &str) {
+        conn.execute(
+            "INSERT INTO content_index (cid, dir, path, filename, size) VALUES (?1, 'd', ?1, ?1, 1)",
+            params![cid],
+        )
+        .unwrap();
+    }
+
+    fn summary(page: &ChangesPage) -> Vec<String> {
+        page.changes
+            .iter()
+            .map(|c| format!("{} {} {}", c.op, c.kind, c.id))
+            .collect()
+    }
+
+    #[test]
+    fn journal_records_what_a_client_shows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = open(&tmp.path().join("salita.db"));
+        let conn = pool.get().unwrap();
+        let start = since(&conn, None, DEFAULT_PAGE).unwrap();
+        assert!(start.resync_required);
+
+        add_file(&conn, "a");
+        conn.execute_batch(
+            "INSERT INTO devices (id, name, status) VALUES ('nas', 'nas', 'online');
+             UPDATE devices SET last_seen = datetime('now');
+             UPDATE content_index SET indexed_at = datetime('now'), last_verified_at = datetime('now');
+             UPDATE devices SET status = 'offline';
+             UPDATE content_index SET scan_status = 'corrupt';
+             DELETE FROM content_index;",
+        )
+        .unwrap();
+
+        let page = since(&conn, Some(&start.cursor), DEFAULT_PAGE).unwrap();
+        assert!(!page.resync_required && !page.has_more);
+        assert_eq!(
+            summary(&page),
+            vec![
+                "upsert file a",
+                "upsert node nas",
+                "upsert node nas",
+                "upsert file a",
+                "delete file a",
+            ]
+        );
+        let caught_up = since(&conn, Some(&page.cursor), DEFAULT_PAGE).unwrap();
+        assert!(caught_up.changes.is_empty());
+        assert_eq!(caught_up.cursor, page.cursor);
+    }
+
+    #[test]
+    fn pages_follow_on_without_gaps_across_restarts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("salita.db");
+        let pool = open(&path);
+        let mut cursor = since(&pool.get().unwrap(), None, 2).unwrap().cursor;
+        for cid in ["a", "b", "c", "d", "e"] {
+            add_file(&pool.get().unwrap(), cid);
+        }
+
+        let page = since(&pool.get().unwrap(), Some(&cursor), 2).unwrap();
+        assert_eq!(summary(&page), vec!["upsert file a", "upsert file b"]);
+        assert!(page.has_more);
+        cursor = page.cursor;
+        drop(pool);
+
+        let pool = open(&path);
+        let mut seen = Vec::new();
+        loop {
+            let page = since(&pool.get().unwrap(), Some(&cursor), 2).unwrap();
+            assert!(!page.resync_required);
+            seen.extend(summary(&page));
+            cursor = page.cursor;
+            if !page.has_more {
+                break;
+            }
+        }
+        assert_eq!(
+            seen,
+            vec!["upsert file c", "upsert file d", "upsert file e"]
+        );
+    }
+
+    #[test]
+    fn cursors_from_before_pruned_history_must_resync() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = open(&tmp.path().join("salita.db"));
+        let conn = pool.get().unwrap();
+        let old = since(&conn, None, DEFAULT_PAGE).unwrap().cursor;
+        add_file(&conn, "a");
+        add_file(&conn, "b");
+        let recent = since(&conn, Some(&old), 1).unwrap().cursor;
+        conn.execute(
+            "UPDATE changes SET at = datetime('now', '-31 days') WHERE id = 'a'",
+            [],
+        )
+        .unwrap();
+        add_file(&conn, "c");
+
+        assert_eq!(prune(&conn).unwrap(), 1);
+        assert!(
+            since(&conn, Some(&old), DEFAULT_PAGE)
+                .unwrap()
+                .resync_required
+        );
+        // Still within retained history
+        let page = since(&conn, Some(&recent), DEFAULT_PAGE).unwrap();
+        assert_eq!(summary(&page), vec!["upsert file b", "upsert file c"]);
+
+        // Unknown cursors, e.g. from a database that was reset
+        for bad in ["garbage", "999", "-1"] {
+            let page = since(&conn, Some(bad), DEFAULT_PAGE).unwrap();
+            assert!(page.resync_required, "{bad}");
+            assert_eq!(page.cursor, page.cursor.parse::<i64>().unwrap().to_string());
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use clap::{Parser, Subcommand};
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use toml_edit::DocumentMut;
+
+use crate::scan::ScanFailurePolicy;
 
 #[derive(Parser, Debug)]
 #[command(name = "salita", about = "A home device mesh with MCP interface")]
@@ -31,34 +37,241 @@ pub enum Command {
     },
     /// Run the MCP stdio server
     Mcp,
+    /// Copy or move photos and videos from a folder into a shared directory
+    ImportMedia {
+        /// Folder to import from
+        #[arg(long)]
+        path: PathBuf,
+
+        /// Label of the shared directory to import into
+        #[arg(long)]
+        into: String,
+
+        /// Include subfolders
+        #[arg(long)]
+        recursive: bool,
+
+        /// Remove files from the source folder once imported
+        #[arg(long = "move")]
+        move_files: bool,
+    },
+    /// Inspect the resolved configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the fully-resolved config as TOML and exit
+    Show,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
     pub server: ServerConfig,
     pub directories: Vec<DirectoryConfig>,
     pub max_read_bytes: usize,
+    pub storage: StorageConfig,
+    pub updates: UpdatesConfig,
+    pub mesh: MeshConfig,
+    pub debug: DebugConfig,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// Content-Security-Policy sent with every response (empty to disable)
+    pub content_security_policy: String,
+    /// Path prefix all routes are mounted under, e.g. "/salita" behind a
+    /// reverse proxy (empty for the root)
+    pub base_path: String,
+    /// Name announced over mDNS as `<name>.local` (empty for the machine's
+    /// hostname)
+    pub advertised_hostname: String,
+    /// Extra Host header values to accept, e.g. a reverse proxy's domain.
+    /// "*" turns Host validation off.
+    pub allowed_hosts: Vec<String>,
+    /// gzip/brotli-encode text and JSON responses for clients that accept it
+    pub compression: bool,
+    /// Recent log lines kept in memory for GET /api/v1/logs (0 to disable)
+    pub log_buffer_lines: usize,
+    /// Seconds before a log filter set through the API reverts to the
+    /// default (0 to keep it until restart)
+    pub log_level_revert_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Seconds between blob garbage collection passes (0 to disable)
+    pub gc_interval_secs: u64,
+    /// Disk budget for content fetched from other nodes (0 to disable caching)
+    pub remote_cache_bytes: u64,
+    /// Larger remote files are passed through without being cached
+    pub remote_cache_max_item_bytes: u64,
+    /// Command run against each new local file before it can be downloaded,
+    /// e.g. "clamdscan --no-summary %f" (empty to disable scanning)
+    pub scan_command: String,
+    /// Seconds a scan may take before it counts as failed
+    pub scan_timeout_secs: u64,
+    /// Whether a failed or timed-out scan lets the file through ("open")
+    /// or quarantines it ("closed")
+    pub scan_failure: ScanFailurePolicy,
+    /// Seconds between integrity verification runs (0 to disable)
+    pub verify_interval_secs: u64,
+    /// Most local files re-hashed in one run
+    pub verify_max_files: usize,
+    /// Most bytes read in one run
+    pub verify_max_bytes: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            gc_interval_secs: 60 * 60,
+            remote_cache_bytes: 1024 * 1024 * 1024, // 1GB
+            remote_cache_max_item_bytes: 256 * 1024 * 1024, // 256MB
+            scan_command: String::new(),
+            scan_timeout_secs: 60,
+            scan_failure: ScanFailurePolicy::Closed,
+            verify_interval_secs: 60 * 60,
+            verify_max_files: 1000,
+            verify_max_bytes: 1024 * 1024 * 1024, // 1GB
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn gc_interval(&self) -> Option<std::time::Duration> {
+        (self.gc_interval_secs > 0).then(|| std::time::Duration::from_secs(self.gc_interval_secs))
+    }
+
+    pub fn verify_interval(&self) -> Option<std::time::Duration> {
+        (self.verify_interval_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.verify_interval_secs))
+    }
+}
+
+/// Opt-in check for new releases. The manifest must be signed with the
+/// release key built into salita; builds without one don't check.
+/// Nothing is downloaded or installed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    pub check: bool,
+    /// URL serving the signed release manifest
+    pub manifest_url: String,
+}
+
+/// Whether peers found on the network join the mesh on their own.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalMode {
+    /// Every discovered peer becomes a member
+    #[default]
+    Auto,
+    /// New peers wait as "pending" until approved over the API
+    Manual,
+}
+
+/// Who may join the mesh. Only applies to peers seen for the first time;
+/// existing members are never dropped by a policy change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MeshConfig {
+    /// Most peers (not counting this node) the mesh admits (0 for no limit)
+    pub max_devices: usize,
+    pub approval: ApprovalMode,
+    /// Pending peers nobody approved or rejected are forgotten after this
+    pub pending_expiry_hours: u64,
+    /// Seconds between pulls of each online peer's member list (0 to
+    /// disable)
+    pub gossip_interval_secs: u64,
+}
+
+impl Default for MeshConfig {
+    fn default() -> Self {
+        Self {
+            max_devices: 0,
+            approval: ApprovalMode::Auto,
+            pending_expiry_hours: 72,
+            gossip_interval_secs: 5 * 60,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The name this node goes by on the LAN, without `.local`: the
+    /// configured override, else the machine's hostname.
+    pub fn advertised_hostname(&self) -> Option<String> {
+        if !self.advertised_hostname.is_empty() {
+            return Some(self.advertised_hostname.clone());
+        }
+        hostname::get().ok().and_then(|h| h.into_string().ok())
+    }
+
+    pub fn log_level_revert(&self) -> Option<std::time::Duration> {
+        (self.log_level_revert_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.log_level_revert_secs))
+    }
+}
+
+impl MeshConfig {
+    pub fn gossip_interval(&self) -> Option<std::time::Duration> {
+        (self.gossip_interval_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.gossip_interval_secs))
+    }
+}
+
+/// Diagnostics that cost something at runtime and are off by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Log every SQL statement with its duration (at debug level)
+    pub log_queries: bool,
+    /// With log_queries on, statements taking at least this long are
+    /// logged at warn level
+    pub slow_query_ms: u64,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            log_queries: false,
+            slow_query_ms: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DirectoryConfig {
     pub label: String,
     pub path: String,
 }
 
+/// Default policy for everything the daemon serves. Shared files are
+/// returned inline, so an HTML or SVG file must not be able to run scripts
+/// on this origin; images and media stay viewable when opened directly.
+pub const DEFAULT_CSP: &str = "default-src 'none'; img-src 'self' data: blob:; \
+     media-src 'self'; style-src 'unsafe-inline'; sandbox";
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             host: "0.0.0.0".to_string(),
             port: 6969,
+            content_security_policy: DEFAULT_CSP.to_string(),
+            base_path: String::new(),
+            advertised_hostname: String::new(),
+            allowed_hosts: Vec::new(),
+            compression: true,
+            log_buffer_lines: crate::log_buffer::DEFAULT_CAPACITY,
+            log_level_revert_secs: 3600,
         }
     }
 }
@@ -69,36 +282,129 @@ impl Default for Config {
             server: ServerConfig::default(),
             directories: Vec::new(),
             max_read_bytes: 10 * 1024 * 1024, // 10MB
+            storage: StorageConfig::default(),
+            updates: UpdatesConfig::default(),
+            mesh: MeshConfig::default(),
+            debug: DebugConfig::default(),
+        }
+    }
+}
+
+/// Where a resolved config value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File,
+    Cli,
+}
+
+impl ConfigSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File => "file",
+            ConfigSource::Cli => "cli",
+        }
+    }
+}
+
+/// How `Config::load` arrived at its values.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    /// Config file that was read, or None when running on defaults.
+    pub file: Option<PathBuf>,
+    /// Source of every setting, keyed by dotted path (e.g. "server.port").
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+impl Provenance {
+    /// Log where the config came from and every setting not left at its default.
+    pub fn log_summary(&self, config: &Config, data_dir: &Path, db_path: &Path) {
+        match &self.file {
+            Some(path) => tracing::info!("Config file: {}", path.display()),
+            None => tracing::info!("Config file: none, using defaults"),
+        }
+        tracing::info!("Data dir: {}", data_dir.display());
+        tracing::info!("Database: {}", db_path.display());
+        tracing::info!(
+            "Listening on {}:{} ({} directories shared)",
+            config.server.host,
+            config.server.port,
+            config.directories.len()
+        );
+        for (key, source) in &self.sources {
+            if *source != ConfigSource::Default {
+                tracing::info!("  {key} set by {}", source.as_str());
+            }
         }
     }
 }
 
 impl Config {
     pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        Ok(Self::load_with_provenance(cli)?.0)
+    }
+
+    /// Load the config and record which settings came from the file and
+    /// which from CLI overrides.
+    pub fn load_with_provenance(cli: &Cli) -> anyhow::Result<(Self, Provenance)> {
         let data_dir = Self::data_dir(cli);
         let config_path = cli
             .config
             .clone()
             .unwrap_or_else(|| data_dir.join("config.toml"));
 
-        let mut config: Config = if config_path.exists() {
-            let content = std::fs::read_to_string(&config_path)?;
-            toml::from_str(&content)?
+        let (mut config, file_table, file) = if config_path.exists() {
+            let (config, table) = read_or_recover(&config_path)?;
+            (config, table, Some(config_path))
         } else {
-            Config::default()
+            (Config::default(), toml::Table::new(), None)
         };
 
+        let mut sources = BTreeMap::new();
+        let defaults = toml::Table::try_from(Config::default())?;
+        for key in setting_keys(&defaults) {
+            let source = if lookup(&file_table, &key).is_some() {
+                ConfigSource::File
+            } else {
+                ConfigSource::Default
+            };
+            sources.insert(key, source);
+        }
+        for key in file_table.keys() {
+            sources.entry(key.clone()).or_insert(ConfigSource::File);
+        }
+
         // CLI overrides for serve command
         if let Command::Serve { ref host, ref port } = cli.command {
             if let Some(ref h) = host {
                 config.server.host = h.clone();
+                sources.insert("server.host".into(), ConfigSource::Cli);
             }
             if let Some(p) = port {
                 config.server.port = *p;
+                sources.insert("server.port".into(), ConfigSource::Cli);
             }
         }
 
-        Ok(config)
+        config.server.base_path = normalize_base_path(&config.server.base_path)?;
+        config.server.advertised_hostname =
+            normalize_advertised_hostname(&config.server.advertised_hostname)?;
+
+        // A file that loaded is the one to fall back on if a later edit
+        // breaks it
+        if let Some(ref path) = file {
+            if let Err(e) = keep_backup(path) {
+                tracing::warn!("Failed to back up {}: {e:#}", path.display());
+            }
+        }
+
+        Ok((config, Provenance { file, sources }))
+    }
+
+    /// Render the resolved config as TOML.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
     }
 
     pub fn data_dir(cli: &Cli) -> PathBuf {
@@ -122,11 +428,261 @@ impl Config {
     }
 }
 
+/// Serializes `persist` calls so each read-merge-write sees the result
+/// of the one before it.
+static PERSIST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Write `config` to `path` so that a crash at any point leaves either the
+/// old file or the new one in place, never a truncated mix.
+///
+/// The new contents go to a temp file in the same directory, are fsynced
+/// and then renamed over `path`. The version being replaced is kept as a
+/// backup (see `keep_backup`) for `load` to fall back on.
+///
+/// Hand edits survive: settings are merged into the existing document, so
+/// keys this version doesn't know and comments around unchanged settings
+/// are kept. A changed value keeps the comment on its line, but arrays of
+/// tables such as `[[directories]]` are rewritten whole and lose any
+/// comments inside them.
+pub fn persist(config: &Config, path: &Path) -> anyhow::Result<()> {
+    let _guard = PERSIST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let fresh: DocumentMut = toml::to_string_pretty(config)?.parse()?;
+    let existing = match std::fs::read_to_string(path) {
+        Ok(content) => content.parse::<DocumentMut>().ok(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    // A corrupt file is replaced rather than merged into, and isn't backed
+    // up over the last good backup
+    let is_valid = existing.is_some();
+    let mut doc = existing.unwrap_or_default();
+    merge_table(doc.as_table_mut(), fresh.as_table());
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(doc.to_string().as_bytes())?;
+    tmp.as_file().sync_all()?;
+
+    if is_valid {
+        keep_backup(path)?;
+    }
+    tmp.persist(path)?;
+    // Make the rename itself durable
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Keep a copy of `path`, known to be valid, as `<name>.<timestamp>.bak`
+/// and drop older copies. Nothing is written when the newest backup
+/// already has the same contents.
+fn keep_backup(path: &Path) -> anyhow::Result<()> {
+    let content = std::fs::read(path)?;
+    let existing = backups(path)?;
+    if let Some(newest) = existing.last() {
+        if std::fs::read(newest).ok().as_ref() == Some(&content) {
+            return Ok(());
+        }
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let backup = sibling(path, &format!("{}.bak", timestamp()));
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(&content)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(&backup)?;
+    for old in existing {
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Copy settings from `fresh` into `doc`, leaving keys `fresh` lacks and
+/// the formatting of unchanged values alone.
+fn merge_table(doc: &mut toml_edit::Table, fresh: &toml_edit::Table) {
+    for (key, item) in fresh.iter() {
+        match (doc.get_mut(key), item) {
+            (Some(toml_edit::Item::Table(old)), toml_edit::Item::Table(new)) => {
+                merge_table(old, new)
+            }
+            (Some(toml_edit::Item::Value(old)), toml_edit::Item::Value(new)) => {
+                let mut bare = new.clone();
+                bare.decor_mut().clear();
+                let mut old_bare = old.clone();
+                old_bare.decor_mut().clear();
+                if bare.to_string() != old_bare.to_string() {
+                    let decor = old.decor().clone();
+                    *old = bare;
+                    *old.decor_mut() = decor;
+                }
+            }
+            _ => {
+                doc.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Parse the config file. When it is corrupt, e.g. a write cut short or a
+/// bad hand edit, fall back to the newest backup: the broken file is moved
+/// aside as `<name>.corrupt-<timestamp>` and the backup restored in its
+/// place, so the daemon starts and the broken file is there to inspect.
+fn read_or_recover(path: &Path) -> anyhow::Result<(Config, toml::Table)> {
+    let err = match parse_file(path) {
+        Ok(parsed) => return Ok(parsed),
+        Err(e) => e,
+    };
+    let Some(backup) = backups(path)?.pop() else {
+        return Err(err.context(format!(
+            "{} is invalid and there is no backup to recover from",
+            path.display()
+        )));
+    };
+    let parsed = parse_file(&backup).map_err(|e| {
+        e.context(format!(
+            "{} is invalid and so is its backup {}",
+            path.display(),
+            backup.display()
+        ))
+    })?;
+
+    let aside = sibling(path, &format!("corrupt-{}", timestamp()));
+    std::fs::rename(path, &aside)?;
+    std::fs::copy(&backup, path)?;
+    tracing::warn!(
+        "Config {} was invalid ({err:#}); moved it to {} and restored {}",
+        path.display(),
+        aside.display(),
+        backup.display()
+    );
+    Ok(parsed)
+}
+
+fn parse_file(path: &Path) -> anyhow::Result<(Config, toml::Table)> {
+    let content = std::fs::read_to_string(path)?;
+    let table: toml::Table = toml::from_str(&content)?;
+    Ok((toml::from_str(&content)?, table))
+}
+
+/// Backups of `path` kept by `keep_backup`, oldest first.
+fn backups(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with(&prefix) && file_name.ends_with(".bak") {
+            found.push(entry.path());
+        }
+    }
+    // Timestamps sort lexically
+    found.sort();
+    Ok(found)
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f").to_string()
+}
+
+/// Validate a route prefix. It must start with '/' and must not end with
+/// one; "" and "/" both mean the root and normalize to "".
+pub fn normalize_base_path(path: &str) -> anyhow::Result<String> {
+    if path.is_empty() || path == "/" {
+        return Ok(String::new());
+    }
+    if !path.starts_with('/') {
+        anyhow::bail!("base_path must start with '/': {path:?}");
+    }
+    if path.ends_with('/') {
+        anyhow::bail!("base_path must not end with '/': {path:?}");
+    }
+    let valid_segment = |s: &str| {
+        !s.is_empty()
+            && s != "."
+            && s != ".."
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+    };
+    if !path[1..].split('/').all(valid_segment) {
+        anyhow::bail!("base_path has an invalid segment: {path:?}");
+    }
+    Ok(path.to_string())
+}
+
+/// Validate an mDNS host name. It must be a single DNS label (letters,
+/// digits and inner hyphens, at most 63 long); a `.local` suffix is
+/// accepted and dropped, and the result is lowercase.
+pub fn normalize_advertised_hostname(name: &str) -> anyhow::Result<String> {
+    let name = name.trim().trim_end_matches('.').to_ascii_lowercase();
+    let label = name.strip_suffix(".local").unwrap_or(&name);
+    if name.is_empty() {
+        return Ok(String::new());
+    }
+    let valid = (1..=63).contains(&label.len())
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid {
+        anyhow::bail!("advertised_hostname must be a single DNS label like \"den-mac\": {name:?}");
+    }
+    Ok(label.to_string())
+}
+
+/// Dotted paths of every setting in `table`; nested tables are walked,
+/// anything else (including arrays) is a setting.
+fn setting_keys(table: &toml::Table) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (name, value) in table {
+        match value {
+            toml::Value::Table(inner) => {
+                for key in setting_keys(inner) {
+                    keys.push(format!("{name}.{key}"));
+                }
+            }
+            _ => keys.push(name.clone()),
+        }
+    }
+    keys
+}
+
+/// Look up a dotted path in a parsed TOML table.
+fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let mut parts = key.split('.');
+    let mut value = table.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
 /// Expand ~ to the user's home directory
 fn expand_tilde(path: &str) -> PathBuf {
-    if path.starts_with('~') {
+    if let Some(rest) = path.strip_prefix('~') {
         if let Some(home) = dirs::home_dir() {
-            return home.join(path.strip_prefix("~/").unwrap_or(&path[1..]));
+            return home.join(rest.strip_prefix('/').unwrap_or(rest));
         }
     }
     PathBuf::from(path)
@@ -143,6 +699,238 @@ mod tests {
         assert_eq!(config.server.port, 6969);
         assert_eq!(config.max_read_bytes, 10 * 1024 * 1024);
         assert!(config.directories.is_empty());
+        assert_eq!(
+            config.storage.gc_interval(),
+            Some(std::time::Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn zero_gc_interval_disables_gc() {
+        let config: Config = toml::from_str("[storage]\ngc_interval_secs = 0").unwrap();
+        assert_eq!(config.storage.gc_interval(), None);
+    }
+
+    #[test]
+    fn provenance_tracks_file_and_cli_values() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "max_read_bytes = 42\n[server]\nhost = \"127.0.0.1\"\nport = 7000\n",
+        )
+        .unwrap();
+        let cli = Cli::parse_from([
+            "salita",
+            "--config",
+            path.to_str().unwrap(),
+            "serve",
+            "--port",
+            "8000",
+        ]);
+
+        let (config, provenance) = Config::load_with_provenance(&cli).unwrap();
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 8000);
+        assert_eq!(provenance.file, Some(path));
+
+        let source = |key: &str| provenance.sources[key];
+        assert_eq!(source("server.host"), ConfigSource::File);
+        assert_eq!(source("server.port"), ConfigSource::Cli);
+        assert_eq!(source("max_read_bytes"), ConfigSource::File);
+        assert_eq!(source("storage.gc_interval_secs"), ConfigSource::Default);
+        assert_eq!(source("directories"), ConfigSource::Default);
+    }
+
+    #[test]
+    fn missing_file_means_defaults() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cli = Cli::parse_from(["salita", "--data-dir", tmp.path().to_str().unwrap(), "mcp"]);
+
+        let (_, provenance) = Config::load_with_provenance(&cli).unwrap();
+        assert_eq!(provenance.file, None);
+        assert!(provenance
+            .sources
+            .values()
+            .all(|s| *s == ConfigSource::Default));
+    }
+
+    #[test]
+    fn to_toml_round_trips() {
+        let config = Config {
+            directories: vec![DirectoryConfig {
+                label: "docs".to_string(),
+                path: "~/Documents".to_string(),
+            }],
+            ..Config::default()
+        };
+
+        let parsed: Config = toml::from_str(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(parsed.server.port, config.server.port);
+        assert_eq!(
+            parsed.server.content_security_policy,
+            config.server.content_security_policy
+        );
+        assert_eq!(parsed.directories[0].label, "docs");
+        assert_eq!(parsed.max_read_bytes, config.max_read_bytes);
+    }
+
+    #[test]
+    fn truncated_write_is_recovered_from_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        let mut config = Config::default();
+        config.server.port = 7000;
+        persist(&config, &path).unwrap();
+        config.server.port = 7001;
+        persist(&config, &path).unwrap();
+
+        // Simulate a write cut short by a crash
+        std::fs::write(&path, "[server\nport = ").unwrap();
+
+        let cli = Cli::parse_from(["salita", "--config", path.to_str().unwrap(), "mcp"]);
+        let config = Config::load(&cli).unwrap();
+        assert_eq!(config.server.port, 7000);
+
+        let restored: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(restored.server.port, 7000);
+        let names: Vec<String> = std::fs::read_dir(tmp.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().any(|n| n.starts_with("config.toml.corrupt-")));
+    }
+
+    #[test]
+    fn broken_hand_edit_falls_back_to_the_last_loaded_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        std::fs::write(&path, "# mine\n[server]\nport = 7000\n").unwrap();
+        let cli = Cli::parse_from(["salita", "--config", path.to_str().unwrap(), "mcp"]);
+
+        Config::load(&cli).unwrap();
+        // Loading it again doesn't pile up backups
+        Config::load(&cli).unwrap();
+        assert_eq!(backups(&path).unwrap().len(), 1);
+
+        std::fs::write(&path, "[server]\nport = 70 00\n").unwrap();
+        let config = Config::load(&cli).unwrap();
+        assert_eq!(config.server.port, 7000);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# mine\n[server]\nport = 7000\n"
+        );
+    }
+
+    #[test]
+    fn invalid_file_without_backup_is_an_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        std::fs::write(&path, "[server\n").unwrap();
+
+        let cli = Cli::parse_from(["salita", "--config", path.to_str().unwrap(), "mcp"]);
+        let err = Config::load(&cli).unwrap_err();
+        assert!(err.to_string().contains("no backup"));
+    }
+
+    #[test]
+    fn persist_keeps_unknown_keys_and_comments() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "# my node\nfuture_setting = \"keep\"\n\n[server]\n# web port\nport = 7000 # not 80\nextra = 1\n",
+        )
+        .unwrap();
+
+        let mut config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        config.server.port = 7100;
+        persist(&config, &path).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("# my node"));
+        assert!(written.contains("future_setting = \"keep\""));
+        assert!(written.contains("# web port"));
+        assert!(written.contains("port = 7100 # not 80"));
+        assert!(written.contains("extra = 1"));
+        let reloaded: Config = toml::from_str(&written).unwrap();
+        assert_eq!(reloaded.server.port, 7100);
+        assert_eq!(backups(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn concurrent_persists_leave_one_complete_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        persist(&Config::default(), &path).unwrap();
+
+        std::thread::scope(|s| {
+            for port in 7000..7008 {
+                let path = &path;
+                s.spawn(move || {
+                    let mut config = Config::default();
+                    config.server.port = port;
+                    persist(&config, path).unwrap();
+                });
+            }
+        });
+
+        let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!((7000..7008).contains(&config.server.port));
+        assert_eq!(backups(&path).unwrap().len(), 1);
+        // No temp files left behind
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn base_path_validation() {
+        assert_eq!(normalize_base_path("").unwrap(), "");
+        assert_eq!(normalize_base_path("/").unwrap(), "");
+        assert_eq!(normalize_base_path("/salita").unwrap(), "/salita");
+        assert_eq!(
+            normalize_base_path("/apps/salita-1").unwrap(),
+            "/apps/salita-1"
+        );
+
+        for bad in [
+            "salita", "/salita/", "//salita", "/a//b", "/../x", "/sa lita", "/{x}",
+        ] {
+            assert!(
+                normalize_base_path(bad).is_err(),
+                "{bad} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn advertised_hostname_validation() {
+        assert_eq!(normalize_advertised_hostname("").unwrap(), "");
+        assert_eq!(normalize_advertised_hostname("den-mac").unwrap(), "den-mac");
+        assert_eq!(
+            normalize_advertised_hostname(" Den-Mac.local. ").unwrap(),
+            "den-mac"
+        );
+
+        for bad in [
+            ".local",
+            "-mac",
+            "mac-",
+            "den.mac",
+            "den mac",
+            "dén",
+            &"a".repeat(64),
+        ] {
+            assert!(
+                normalize_advertised_hostname(bad).is_err(),
+                "{bad} should be rejected"
+            );
+        }
+
+        let server = ServerConfig {
+            advertised_hostname: "photos".into(),
+            ..Default::default()
+        };
+        assert_eq!(server.advertised_hostname().as_deref(), Some("photos"));
     }
 
     #[test]
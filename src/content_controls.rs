@@ -0,0 +1,255 @@
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+
+/// Remote entries deleted per transaction when purging a blocked node.
+const PURGE_BATCH: usize = 500;
+
+/// SQL condition excluding catalog entries (aliased `ci`) from muted nodes.
+pub const NOT_MUTED: &str = "(ci.origin_node IS NULL OR ci.origin_node NOT IN
+     (SELECT node_id FROM node_content_settings WHERE muted = 1))";
+
+/// How this node treats catalog entries from another node. Muted entries
+/// keep syncing but are hidden from the catalog; blocked ones aren't synced.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NodeContentSettings {
+    pub node_id: String,
+    pub muted: bool,
+    pub blocked: bool,
+    pub updated_at: Option<String>,
+}
+
+pub fn get(conn: &Connection, node_id: &str) -> rusqlite::Result<NodeContentSettings> {
+    let settings = conn
+        .query_row(
+            "SELECT muted, blocked, updated_at FROM node_content_settings WHERE node_id = ?1",
+            params![node_id],
+            |row| {
+                Ok(NodeContentSettings {
+                    node_id: node_id.to_string(),
+                    muted: row.get(0)?,
+                    blocked: row.get(1)?,
+                    updated_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+
+    Ok(settings.unwrap_or_else(|| NodeContentSettings {
+        node_id: node_id.to_string(),
+        ..Default::default()
+    }))
+}
+
+pub fn set(conn: &Connection, node_id: &str, muted: bool, blocked: bool) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO node_content_settings (node_id, muted, blocked) VALUES (?1, ?2, ?3)
+         ON CONFLICT(node_id) DO UPDATE SET
+           muted = excluded.muted,
+           blocked = excluded.blocked,
+           updated_at = datetime('now')",
+        params![node_id, muted, blocked],
+    )?;
+    Ok(())
+}
+
+pub fn is_blocked(conn: &Connection, node_id: &str) -> rusqlite::Result<bool> {
+    Ok(get(conn, node_id)?.blocked)
+}
+
+/// Apply new settings for `node_id`, returning whether it was blocked
+/// before. Blocking doesn't delete anything itself; follow it with
+/// `purge_blocked`.
+pub fn update(
+    conn: &mut Connection,
+    node_id: &str,
+    muted: bool,
+    blocked: bool,
+) -> rusqlite::Result<bool> {
+    // Immediate, so two toggles racing each other see each other's write
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let was_blocked = is_blocked(&tx, node_id)?;
+    set(&tx, node_id, muted, blocked)?;
+    tx.commit()?;
+    Ok(was_blocked)
+}
+
+/// Delete what was synced in from `node_id` while it stays blocked, in
+/// bounded transactions so a large catalog doesn't hold the write lock
+/// for long. The block is saved before the first batch, so a purge that
+/// is cut short is finished by the next one. Returns the cids deleted.
+pub fn purge_blocked(conn: &mut Connection, node_id: &str) -> rusqlite::Result<Vec<String>> {
+    purge_in_batches(conn, node_id, PURGE_BATCH)
+}
+
+/// Finish the purges of every blocked node, e.g. ones a restart cut
+/// short. Returns the cids deleted.
+pub fn resume_purges(conn: &mut Connection) -> rusqlite::Result<Vec<String>> {
+    let nodes: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT node_id FROM node_content_settings s WHERE blocked = 1 AND EXISTS
+               (SELECT 1 FROM content_index WHERE origin_node = s.node_id AND is_local = 0)",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let mut purged = Vec::new();
+    for node_id in nodes {
+        purged.append(&mut purge_blocked(conn, &node_id)?);
+    }
+    Ok(purged)
+}
+
+fn purge_in_batches(
+    conn: &mut Connection,
+    node_id: &str,
+    batch: usize,
+) -> rusqlite::Result<Vec<String>> {
+    let mut purged = Vec::new();
+    loop {
+        let tx = conn.transaction()?;
+        // Unblocked since; what is left stays
+        if !is_blocked(&tx, node_id)? {
+            return Ok(purged);
+        }
+        let mut cids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT cid FROM content_index WHERE origin_node = ?1 AND is_local = 0
+                 ORDER BY cid LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![node_id, batch as i64], |row| row.get(0))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        if cids.is_empty() {
+            return Ok(purged);
+        }
+        // Thumbnails and previews go with their entries
+        tx.execute(
+            "DELETE FROM content_index WHERE cid IN
+               (SELECT cid FROM content_index WHERE origin_node = ?1 AND is_local = 0
+                ORDER BY cid LIMIT ?2)",
+            params![node_id, batch as i64],
+        )?;
+        tx.commit()?;
+        purged.append(&mut cids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    fn insert_entry(conn: &Connection, cid: &str, origin: Option<&str>, is_local: bool) {
+        conn.execute(
+            "INSERT INTO content_index (cid, dir, path, filename, size, origin_node, is_local)
+             VALUES (?1, 'd', ?1, ?1, 1, ?2, ?3)",
+            params![cid, origin, is_local],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO content_thumbnails (cid, thumbnail, width, height) VALUES (?1, x'00', 1, 1)",
+            params![cid],
+        )
+        .unwrap();
+    }
+
+    fn cids(conn: &Connection, sql: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(sql).unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn settings_default_to_allowed() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        assert_eq!(get(&conn, "peer").unwrap().node_id, "peer");
+        assert!(!is_blocked(&conn, "peer").unwrap());
+
+        set(&conn, "peer", true, true).unwrap();
+        let settings = get(&conn, "peer").unwrap();
+        assert!(settings.muted && settings.blocked);
+        assert!(settings.updated_at.is_some());
+    }
+
+    #[test]
+    fn muted_entries_are_filtered() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        insert_entry(&conn, "mine", None, true);
+        insert_entry(&conn, "loud", Some("noisy"), false);
+        insert_entry(&conn, "fine", Some("quiet"), false);
+        set(&conn, "noisy", true, false).unwrap();
+
+        let visible = cids(
+            &conn,
+            &format!("SELECT cid FROM content_index ci WHERE {NOT_MUTED} ORDER BY cid"),
+        );
+        assert_eq!(visible, ["fine", "mine"]);
+    }
+
+    #[test]
+    fn blocking_removes_only_that_nodes_remote_entries() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        for i in 0..5 {
+            insert_entry(&conn, &format!("noisy-{i}"), Some("noisy"), false);
+        }
+        insert_entry(&conn, "quiet-0", Some("quiet"), false);
+        insert_entry(&conn, "mine", Some("noisy"), true);
+
+        assert!(!update(&mut conn, "noisy", false, true).unwrap());
+        assert_eq!(purge_in_batches(&mut conn, "noisy", 2).unwrap().len(), 5);
+        assert!(update(&mut conn, "noisy", true, true).unwrap());
+        // Already purged; nothing more to do
+        assert!(purge_blocked(&mut conn, "noisy").unwrap().is_empty());
+        assert_eq!(
+            cids(&conn, "SELECT cid FROM content_index ORDER BY cid"),
+            ["mine", "quiet-0"]
+        );
+        assert_eq!(
+            cids(&conn, "SELECT cid FROM content_thumbnails ORDER BY cid"),
+            ["mine", "quiet-0"]
+        );
+    }
+
+    #[test]
+    fn interrupted_purge_is_finished_later() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        for i in 0..3 {
+            insert_entry(&conn, &format!("noisy-{i}"), Some("noisy"), false);
+        }
+        update(&mut conn, "noisy", false, true).unwrap();
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER fail_purge BEFORE DELETE ON content_index
+             WHEN OLD.cid = 'noisy-2' BEGIN SELECT RAISE(ABORT, 'disk I/O error'); END;",
+        )
+        .unwrap();
+
+        // The first batch is kept and the block stays saved
+        assert!(purge_in_batches(&mut conn, "noisy", 2).is_err());
+        assert!(is_blocked(&conn, "noisy").unwrap());
+        assert_eq!(
+            cids(&conn, "SELECT cid FROM content_thumbnails"),
+            ["noisy-2"]
+        );
+
+        conn.execute_batch("DROP TRIGGER fail_purge").unwrap();
+        assert_eq!(resume_purges(&mut conn).unwrap(), ["noisy-2"]);
+        assert!(resume_purges(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unblocking_stops_a_purge() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        insert_entry(&conn, "noisy-0", Some("noisy"), false);
+        update(&mut conn, "noisy", false, true).unwrap();
+        assert!(update(&mut conn, "noisy", false, false).unwrap());
+
+        assert!(purge_blocked(&mut conn, "noisy").unwrap().is_empty());
+        assert_eq!(cids(&conn, "SELECT cid FROM content_index"), ["noisy-0"]);
+    }
+}
@@ -1,8 +1,12 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::params;
+use rusqlite::{params, Connection};
 use std::path::Path;
 
+use crate::config::DebugConfig;
+use crate::query_log;
+
 pub type DbPool = Pool<SqliteConnectionManager>;
 
 pub const MIGRATIONS: &[(&str, &str)] = &[
@@ -19,23 +23,141 @@ pub const MIGRATIONS: &[(&str, &str)] = &[
         "004_content_previews",
         include_str!("../migrations/004_content_previews.sql"),
     ),
+    (
+        "005_peer_sync_status",
+        include_str!("../migrations/005_peer_sync_status.sql"),
+    ),
+    (
+        "006_device_base_path",
+        include_str!("../migrations/006_device_base_path.sql"),
+    ),
+    (
+        "007_remote_cache",
+        include_str!("../migrations/007_remote_cache.sql"),
+    ),
+    (
+        "008_node_content_settings",
+        include_str!("../migrations/008_node_content_settings.sql"),
+    ),
+    (
+        "009_node_lifecycle",
+        include_str!("../migrations/009_node_lifecycle.sql"),
+    ),
+    (
+        "010_node_labels",
+        include_str!("../migrations/010_node_labels.sql"),
+    ),
+    (
+        "011_device_learned_from",
+        include_str!("../migrations/011_device_learned_from.sql"),
+    ),
+    (
+        "012_content_scan",
+        include_str!("../migrations/012_content_scan.sql"),
+    ),
+    (
+        "012a_migration_notices",
+        include_str!("../migrations/012a_migration_notices.sql"),
+    ),
+    (
+        "013_foreign_keys",
+        include_str!("../migrations/013_foreign_keys.sql"),
+    ),
+    (
+        "014_upgrades",
+        include_str!("../migrations/014_upgrades.sql"),
+    ),
+    (
+        "015_catalog_tombstones",
+        include_str!("../migrations/015_catalog_tombstones.sql"),
+    ),
+    (
+        "016_device_archive",
+        include_str!("../migrations/016_device_archive.sql"),
+    ),
+    (
+        "017_integrity",
+        include_str!("../migrations/017_integrity.sql"),
+    ),
+    ("018_changes", include_str!("../migrations/018_changes.sql")),
 ];
 
-pub fn create_pool(db_path: &Path) -> anyhow::Result<DbPool> {
+/// Work a migration does in code, in its transaction before its SQL runs,
+/// e.g. to count and log what it removes. Returns what the owner should
+/// hear about, if anything; that is kept in `migration_notices` (created
+/// by 012a, so only later migrations can have a step) and shown with the
+/// release notes after the upgrade.
+type MigrationStep = fn(&Connection) -> rusqlite::Result<Option<String>>;
+
+const MIGRATION_STEPS: &[(&str, MigrationStep)] = &[("013_foreign_keys", remove_orphans)];
+
+/// For 013: rows left behind by what they belonged to while foreign keys
+/// weren't enforced everywhere.
+fn remove_orphans(conn: &Connection) -> rusqlite::Result<Option<String>> {
+    let thumbnails = conn.execute(
+        "DELETE FROM content_thumbnails WHERE cid NOT IN (SELECT cid FROM content_index)",
+        [],
+    )?;
+    let previews = conn.execute(
+        "DELETE FROM content_previews WHERE cid NOT IN (SELECT cid FROM content_index)",
+        [],
+    )?;
+    let labels = conn.execute(
+        "DELETE FROM node_labels WHERE node_id NOT IN (SELECT id FROM devices)",
+        [],
+    )?;
+    tracing::info!(
+        "Removed orphaned rows: {thumbnails} thumbnails, {previews} previews, {labels} labels"
+    );
+    if thumbnails + previews + labels == 0 {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "Removed {thumbnails} thumbnails and {previews} previews of files no longer in \
+         the index, and {labels} labels on devices this node no longer knows."
+    )))
+}
+
+/// Format of every timestamp the database records itself (`indexed_at`,
+/// `last_seen`, `created_at`, ...): UTC text as written by SQLite's
+/// `datetime('now')`, so values compare as strings and work with SQLite's
+/// date functions. Timestamps from clients go through `parse_ts` and
+/// `format_ts` before reaching SQL. File `modified` times are RFC 3339.
+pub const TS_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Parse a stored timestamp, or an RFC 3339 one from a client.
+pub fn parse_ts(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    match NaiveDateTime::parse_from_str(s, TS_FORMAT) {
+        Ok(naive) => Ok(naive.and_utc()),
+        Err(_) => DateTime::parse_from_rfc3339(s).map(|dt| dt.with_timezone(&Utc)),
+    }
+}
+
+/// Format a timestamp the way the database stores it.
+pub fn format_ts(ts: DateTime<Utc>) -> String {
+    ts.format(TS_FORMAT).to_string()
+}
+
+pub fn create_pool(db_path: &Path, debug: &DebugConfig) -> anyhow::Result<DbPool> {
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
 
-    let manager = SqliteConnectionManager::file(db_path);
+    let debug = debug.clone();
+    let manager = SqliteConnectionManager::file(db_path).with_init(move |conn| {
+        // These settings are per connection, so every pooled one needs them
+        conn.execute_batch(
+            "PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;
+             PRAGMA busy_timeout = 5000;",
+        )?;
+        query_log::install(conn, &debug);
+        Ok(())
+    });
     let pool = Pool::builder().max_size(8).build(manager)?;
 
-    let conn = pool.get()?;
-    conn.execute_batch(
-        "PRAGMA journal_mode = WAL;
-         PRAGMA synchronous = NORMAL;
-         PRAGMA foreign_keys = ON;
-         PRAGMA busy_timeout = 5000;",
-    )?;
+    // WAL is a property of the database file; setting it once is enough
+    pool.get()?.execute_batch("PRAGMA journal_mode = WAL;")?;
 
     Ok(pool)
 }
@@ -49,7 +171,6 @@ pub fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
             applied_at TEXT NOT NULL DEFAULT (datetime('now'))
         );",
     )?;
-
     for (name, sql) in MIGRATIONS {
         let already_applied: bool = conn.query_row(
             "SELECT COUNT(*) > 0 FROM schema_version WHERE name = ?1",
@@ -59,11 +180,26 @@ pub fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
 
         if !already_applied {
             tracing::info!("Applying migration: {}", name);
-            conn.execute_batch(sql)?;
-            conn.execute(
+            // A migration that fails halfway (e.g. a table rebuild) must not
+            // leave the schema half changed
+            let tx = conn.unchecked_transaction()?;
+            let notice = match MIGRATION_STEPS.iter().find(|(n, _)| n == name) {
+                Some((_, step)) => step(&tx)?,
+                None => None,
+            };
+            tx.execute_batch(sql)?;
+            tx.execute(
                 "INSERT INTO schema_version (name) VALUES (?1)",
                 params![name],
             )?;
+            if let Some(message) = notice {
+                tx.execute(
+                    "INSERT OR IGNORE INTO migration_notices (migration, message)
+                     VALUES (?1, ?2)",
+                    params![name, message],
+                )?;
+            }
+            tx.commit()?;
         }
     }
 
@@ -71,11 +207,24 @@ pub fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// In-memory database with every migration applied, for tests. A single
+/// connection, so every `get` sees the same database, enforcing foreign
+/// keys like the pools `create_pool` builds.
+#[cfg(test)]
+pub(crate) fn test_pool() -> DbPool {
+    let manager = SqliteConnectionManager::memory()
+        .with_init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON;"));
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    run_migrations(&pool).unwrap();
+    pool
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn test_pool() -> DbPool {
+    /// Not yet migrated, for tests of the migrations themselves.
+    fn empty_pool() -> DbPool {
         let manager = SqliteConnectionManager::memory();
         let pool = Pool::builder().max_size(1).build(manager).unwrap();
         let conn = pool.get().unwrap();
@@ -91,7 +240,7 @@ mod tests {
     fn create_pool_creates_db_file() {
         let tmp = tempfile::tempdir().unwrap();
         let db_path = tmp.path().join("sub/dir/test.db");
-        let pool = create_pool(&db_path).unwrap();
+        let pool = create_pool(&db_path, &DebugConfig::default()).unwrap();
         assert!(db_path.exists());
         let conn = pool.get().unwrap();
         let mode: String = conn
@@ -102,7 +251,7 @@ mod tests {
 
     #[test]
     fn migrations_run_successfully() {
-        let pool = test_pool();
+        let pool = empty_pool();
         run_migrations(&pool).unwrap();
 
         let conn = pool.get().unwrap();
@@ -122,11 +271,44 @@ mod tests {
         };
         assert!(tables.contains(&"devices".to_string()));
         assert!(tables.contains(&"current_node".to_string()));
+
+        // Nothing to tell a fresh install
+        let notices: i64 = conn
+            .query_row("SELECT COUNT(*) FROM migration_notices", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(notices, 0);
+    }
+
+    #[test]
+    fn parse_ts_accepts_stored_and_rfc3339() {
+        let stored = parse_ts("2024-03-01 12:30:00").unwrap();
+        assert_eq!(parse_ts("2024-03-01T12:30:00Z").unwrap(), stored);
+        assert_eq!(parse_ts("2024-03-01T14:30:00+02:00").unwrap(), stored);
+        assert_eq!(format_ts(stored), "2024-03-01 12:30:00");
+    }
+
+    #[test]
+    fn parse_ts_rejects_garbage() {
+        assert!(parse_ts("").is_err());
+        assert!(parse_ts("yesterday").is_err());
+        assert!(parse_ts("2024-13-01 00:00:00").is_err());
+    }
+
+    #[test]
+    fn format_ts_matches_sqlite_datetime() {
+        let pool = empty_pool();
+        let conn = pool.get().unwrap();
+        let now: String = conn
+            .query_row("SELECT datetime('now')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(format_ts(parse_ts(&now).unwrap()), now);
     }
 
     #[test]
     fn migrations_are_idempotent() {
-        let pool = test_pool();
+        let pool = empty_pool();
         run_migrations(&pool).unwrap();
         run_migrations(&pool).unwrap();
 
@@ -136,4 +318,163 @@ mod tests {
             .unwrap();
         assert_eq!(count, MIGRATIONS.len() as i64);
     }
+
+    #[test]
+    fn notices_table_created_outside_the_list_is_kept() {
+        let pool = empty_pool();
+        run_migrations(&pool).unwrap();
+        // As left by a build that created the table before running the list
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "DELETE FROM schema_version WHERE name = '012a_migration_notices';
+                 INSERT INTO migration_notices (migration, message) VALUES ('013_foreign_keys', 'hi');",
+            )
+            .unwrap();
+
+        run_migrations(&pool).unwrap();
+        let notices: i64 = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM migration_notices", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(notices, 1);
+    }
+
+    #[test]
+    fn every_pooled_connection_enforces_foreign_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = create_pool(&tmp.path().join("test.db"), &DebugConfig::default()).unwrap();
+        let (a, b) = (pool.get().unwrap(), pool.get().unwrap());
+        for conn in [&a, &b] {
+            let enabled: bool = conn
+                .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+                .unwrap();
+            assert!(enabled);
+        }
+    }
+
+    /// Apply the migrations before `name`, as an older release would have.
+    fn migrate_up_to(conn: &Connection, name: &str) {
+        let (before, _) = MIGRATIONS.split_at(
+            MIGRATIONS
+                .iter()
+                .position(|(migration, _)| *migration == name)
+                .unwrap(),
+        );
+        conn.execute_batch(
+            "CREATE TABLE schema_version (
+                name TEXT PRIMARY KEY,
+                applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );",
+        )
+        .unwrap();
+        for (name, sql) in before {
+            conn.execute_batch(sql).unwrap();
+            conn.execute("INSERT INTO schema_version (name) VALUES (?1)", [name])
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn foreign_key_migration_drops_orphans_and_keeps_the_rest() {
+        let pool = empty_pool();
+        let conn = pool.get().unwrap();
+        migrate_up_to(&conn, "013_foreign_keys");
+
+        // Seed the orphans the old pool settings let through
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO devices (id, name) VALUES ('nas', 'nas');
+             INSERT INTO node_labels (node_id, label, created_at)
+               VALUES ('nas', 'Infra', '2024-01-01 00:00:00'), ('gone', 'kids', '2024-01-02 00:00:00');
+             INSERT INTO content_index (cid, dir, path, filename, size) VALUES ('a', 'd', 'a', 'a', 1);
+             INSERT INTO content_thumbnails (cid, thumbnail, width, height)
+               VALUES ('a', x'00ff10', 1, 1), ('deleted', x'01', 1, 1);
+             INSERT INTO content_previews (cid, preview, width, height) VALUES ('deleted', x'02', 1, 1);
+             PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+        drop(conn);
+
+        run_migrations(&pool).unwrap();
+        let conn = pool.get().unwrap();
+        let labels: Vec<(String, String, String)> = conn
+            .prepare("SELECT node_id, label, created_at FROM node_labels")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            labels,
+            vec![("nas".into(), "Infra".into(), "2024-01-01 00:00:00".into())]
+        );
+        let thumbnail: Vec<u8> = conn
+            .query_row("SELECT thumbnail FROM content_thumbnails", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(thumbnail, vec![0x00, 0xff, 0x10]);
+        let previews: i64 = conn
+            .query_row("SELECT COUNT(*) FROM content_previews", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(previews, 0);
+
+        // The constraint now holds, and labels follow their device
+        assert!(conn
+            .execute(
+                "INSERT INTO node_labels (node_id, label) VALUES ('gone', 'x')",
+                []
+            )
+            .is_err());
+        conn.execute("DELETE FROM devices WHERE id = 'nas'", [])
+            .unwrap();
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM node_labels", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+
+        // The owner is told what was removed
+        let (migration, message): (String, String) = conn
+            .query_row(
+                "SELECT migration, message FROM migration_notices",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(migration, "013_foreign_keys");
+        assert!(
+            message.starts_with("Removed 1 thumbnails and 1 previews"),
+            "{message}"
+        );
+        assert!(message.contains("1 labels"), "{message}");
+    }
+
+    #[test]
+    fn upgrade_without_orphans_has_nothing_to_tell() {
+        let pool = empty_pool();
+        let conn = pool.get().unwrap();
+        migrate_up_to(&conn, "013_foreign_keys");
+        conn.execute_batch(
+            "INSERT INTO devices (id, name) VALUES ('nas', 'nas');
+             INSERT INTO node_labels (node_id, label) VALUES ('nas', 'infra');",
+        )
+        .unwrap();
+        drop(conn);
+
+        run_migrations(&pool).unwrap();
+        let notices: i64 = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM migration_notices", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(notices, 0);
+    }
 }
@@ -1,4 +1,7 @@
+use crate::admission::{self, Admission};
+use crate::config::{MeshConfig, ServerConfig};
 use crate::db::DbPool;
+use crate::registration::{self, DeviceRegistration};
 use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
 use rusqlite::params;
 use std::collections::HashMap;
@@ -15,8 +18,9 @@ impl MdnsDiscovery {
     pub fn start(
         node_id: &str,
         node_name: &str,
-        port: u16,
+        server: &ServerConfig,
         pool: DbPool,
+        mesh: MeshConfig,
         shutdown_rx: watch::Receiver<bool>,
     ) -> anyhow::Result<Self> {
         let daemon = ServiceDaemon::new()?;
@@ -24,10 +28,10 @@ impl MdnsDiscovery {
         let mut properties = HashMap::new();
         properties.insert("id".to_string(), node_id.to_string());
         properties.insert("name".to_string(), node_name.to_string());
+        properties.insert("path".to_string(), server.base_path.clone());
 
-        let hostname = hostname::get()
-            .ok()
-            .and_then(|h| h.into_string().ok())
+        let hostname = server
+            .advertised_hostname()
             .unwrap_or_else(|| "salita-node".to_string());
         let host_label = format!("{}.local.", hostname);
 
@@ -42,7 +46,7 @@ impl MdnsDiscovery {
             &instance_name,
             &host_label,
             "",
-            port,
+            server.port,
             properties,
         )?
         .enable_addr_auto();
@@ -56,7 +60,7 @@ impl MdnsDiscovery {
         let my_node_id = node_id.to_string();
 
         tokio::spawn(async move {
-            Self::discovery_loop(browse_receiver, pool, my_node_id, shutdown_rx).await;
+            Self::discovery_loop(browse_receiver, pool, mesh, my_node_id, shutdown_rx).await;
         });
 
         Ok(Self {
@@ -66,17 +70,27 @@ impl MdnsDiscovery {
     }
 
     pub fn shutdown(self) {
-        if let Err(e) = self.daemon.unregister(&self.instance_fullname) {
-            tracing::warn!("mDNS: failed to unregister: {}", e);
+        // Wait for each acknowledgement; dropping the receiver early makes
+        // the daemon log an error about the closed channel
+        let ack_timeout = std::time::Duration::from_secs(1);
+        match self.daemon.unregister(&self.instance_fullname) {
+            Ok(ack) => {
+                let _ = ack.recv_timeout(ack_timeout);
+            }
+            Err(e) => tracing::warn!("mDNS: failed to unregister: {}", e),
         }
-        if let Err(e) = self.daemon.shutdown() {
-            tracing::warn!("mDNS: failed to shut down daemon: {}", e);
+        match self.daemon.shutdown() {
+            Ok(ack) => {
+                let _ = ack.recv_timeout(ack_timeout);
+            }
+            Err(e) => tracing::warn!("mDNS: failed to shut down daemon: {}", e),
         }
     }
 
     async fn discovery_loop(
         receiver: flume::Receiver<ServiceEvent>,
         pool: DbPool,
+        mesh: MeshConfig,
         my_node_id: String,
         mut shutdown_rx: watch::Receiver<bool>,
     ) {
@@ -91,7 +105,7 @@ impl MdnsDiscovery {
                 event = receiver.recv_async() => {
                     match event {
                         Ok(ServiceEvent::ServiceResolved(info)) => {
-                            Self::handle_resolved(&pool, &info, &my_node_id);
+                            Self::handle_resolved(&pool, &mesh, &info, &my_node_id);
                         }
                         Ok(ServiceEvent::ServiceRemoved(_ty, fullname)) => {
                             Self::handle_removed(&pool, &fullname);
@@ -107,7 +121,7 @@ impl MdnsDiscovery {
         }
     }
 
-    fn handle_resolved(pool: &DbPool, info: &ServiceInfo, my_node_id: &str) {
+    fn handle_resolved(pool: &DbPool, mesh: &MeshConfig, info: &ServiceInfo, my_node_id: &str) {
         let peer_id = match info.get_property_val_str("id") {
             Some(id) => id.to_string(),
             None => return,
@@ -124,6 +138,16 @@ impl MdnsDiscovery {
 
         let port = info.get_port();
 
+        // Peers without the property predate base_path and serve at the root
+        let base_path = info.get_property_val_str("path").unwrap_or("");
+        let base_path = match crate::config::normalize_base_path(base_path) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::warn!("mDNS: ignoring peer {peer_id}: {e}");
+                return;
+            }
+        };
+
         let endpoint = info
             .get_addresses_v4()
             .iter()
@@ -136,7 +160,7 @@ impl MdnsDiscovery {
             return;
         }
 
-        let conn = match pool.get() {
+        let mut conn = match pool.get() {
             Ok(c) => c,
             Err(e) => {
                 tracing::warn!("mDNS: db error: {}", e);
@@ -144,26 +168,34 @@ impl MdnsDiscovery {
             }
         };
 
-        let result = conn.execute(
-            "INSERT INTO devices (id, name, endpoint, port, status, last_seen, is_self)
-             VALUES (?1, ?2, ?3, ?4, 'online', datetime('now'), 0)
-             ON CONFLICT(id) DO UPDATE SET
-               name = excluded.name,
-               endpoint = excluded.endpoint,
-               port = excluded.port,
-               status = 'online',
-               last_seen = datetime('now')",
-            params![peer_id, peer_name, endpoint, port],
-        );
-
-        match result {
-            Ok(_) => tracing::info!(
+        let device = DeviceRegistration {
+            id: &peer_id,
+            name: &peer_name,
+            endpoint: &endpoint,
+            port,
+            base_path: &base_path,
+        };
+        match registration::register_peer(&mut conn, mesh, &device) {
+            Ok(Admission::Member) => tracing::info!(
                 "mDNS: peer online — {} ({}) at {}:{}",
                 peer_name,
                 peer_id,
                 endpoint,
                 port
             ),
+            Ok(Admission::Pending) => tracing::info!(
+                "mDNS: peer awaiting approval — {} ({}) at {}:{}",
+                peer_name,
+                peer_id,
+                endpoint,
+                port
+            ),
+            Ok(Admission::Rejected) => {
+                tracing::debug!("mDNS: ignoring rejected peer {peer_name} ({peer_id})")
+            }
+            Ok(Admission::MeshFull) => tracing::warn!(
+                "mDNS: not adding peer {peer_name} ({peer_id}): mesh.max_devices reached"
+            ),
             Err(e) => tracing::warn!("mDNS: failed to upsert peer {}: {}", peer_id, e),
         }
     }
@@ -182,7 +214,11 @@ impl MdnsDiscovery {
             .unwrap_or(fullname);
 
         let result = conn.execute(
-            "UPDATE devices SET status = 'offline' WHERE name = ?1 AND is_self = 0",
+            &format!(
+                "UPDATE devices SET status = 'offline'
+                 WHERE name = ?1 AND is_self = 0 AND NOT {}",
+                admission::NOT_ADMITTED
+            ),
             params![instance_name],
         );
 
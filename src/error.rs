@@ -1,5 +1,41 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+/// Machine-readable reason a request was refused, sent as the error
+/// body's `code` so clients can branch on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Admission would take the mesh past `mesh.max_devices`
+    MeshFull,
+    /// The device is archived and isn't contacted
+    NodeArchived,
+    /// The content scanner flagged the file
+    Quarantined,
+    /// The file failed integrity verification
+    Corrupt,
+    /// The file hasn't been scanned yet
+    PendingScan,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::MeshFull => "mesh_full",
+            ErrorCode::NodeArchived => "node_archived",
+            ErrorCode::Quarantined => "quarantined",
+            ErrorCode::Corrupt => "corrupt",
+            ErrorCode::PendingScan => "pending_scan",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
@@ -9,6 +45,12 @@ pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    #[error("Conflict: {code}: {message}")]
+    Conflict { code: ErrorCode, message: String },
+
+    #[error("Forbidden: {code}: {message}")]
+    Forbidden { code: ErrorCode, message: String },
+
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
 
@@ -18,47 +60,156 @@ pub enum AppError {
     #[error("Internal error: {0}")]
     Internal(String),
 
+    #[error("Peer unavailable: {0}")]
+    PeerUnavailable(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+
+    /// The endpoint needs a cargo feature this binary was built without.
+    #[error("Feature disabled: {0}")]
+    FeatureDisabled(&'static str),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
-            AppError::Database(e) => {
-                tracing::error!("Database error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
+impl AppError {
+    /// Machine-readable code for the error body, and its message.
+    fn code_and_message(&self) -> (&str, String) {
+        match self {
+            AppError::NotFound => ("not_found", "Not found".to_string()),
+            AppError::BadRequest(msg) => ("bad_request", msg.clone()),
+            AppError::Conflict { code, message } | AppError::Forbidden { code, message } => {
+                (code.as_str(), message.clone())
             }
-            AppError::Pool(e) => {
-                tracing::error!("Pool error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
+            AppError::Database(_) | AppError::Pool(_) | AppError::Internal(_) | AppError::Io(_) => {
+                ("internal", "Internal server error".to_string())
             }
-            AppError::Internal(msg) => {
-                tracing::error!("Internal error: {}", msg);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
+            AppError::PeerUnavailable(msg) => {
+                ("peer_unavailable", format!("Peer unavailable: {msg}"))
             }
-            AppError::Io(e) => {
-                tracing::error!("IO error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
+            AppError::FeatureDisabled(feature) => (
+                "feature_disabled",
+                format!("this node was built without the {feature} feature"),
+            ),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AppError::Database(_) | AppError::Pool(_) | AppError::Internal(_) | AppError::Io(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
             }
-        };
+            AppError::PeerUnavailable(_) => StatusCode::BAD_GATEWAY,
+            AppError::FeatureDisabled(_) => StatusCode::NOT_IMPLEMENTED,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    message: String,
+}
+
+/// Errors are sent as `{"error": {"code": "...", "message": "..."}}` so
+/// clients can branch on `code` instead of parsing text.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match &self {
+            AppError::Database(e) => tracing::error!("Database error: {}", e),
+            AppError::Pool(e) => tracing::error!("Pool error: {}", e),
+            AppError::Internal(msg) => tracing::error!("Internal error: {}", msg),
+            AppError::PeerUnavailable(msg) => tracing::warn!("Peer unavailable: {}", msg),
+            AppError::Io(e) => tracing::error!("IO error: {}", e),
+            _ => {}
+        }
 
-        (status, message).into_response()
+        let (code, message) = self.code_and_message();
+        let body = ErrorBody {
+            error: ErrorDetail { code, message },
+        };
+        (self.status(), Json(body)).into_response()
     }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(err: AppError) -> (StatusCode, serde_json::Value) {
+        let resp = err.into_response();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn errors_have_a_stable_json_shape() {
+        let cases = [
+            (AppError::NotFound, 404, "not_found", "Not found"),
+            (
+                AppError::BadRequest("Unknown log level: loud".into()),
+                400,
+                "bad_request",
+                "Unknown log level: loud",
+            ),
+            (
+                AppError::Conflict {
+                    code: ErrorCode::MeshFull,
+                    message: "the mesh already has 2 devices".into(),
+                },
+                409,
+                "mesh_full",
+                "the mesh already has 2 devices",
+            ),
+            (
+                AppError::Forbidden {
+                    code: ErrorCode::Quarantined,
+                    message: "flagged by the scanner".into(),
+                },
+                403,
+                "quarantined",
+                "flagged by the scanner",
+            ),
+            // A message shaped like a code is still just a message
+            (
+                AppError::BadRequest("base_path: must start with '/'".into()),
+                400,
+                "bad_request",
+                "base_path: must start with '/'",
+            ),
+            (
+                AppError::Internal("disk on fire".into()),
+                500,
+                "internal",
+                "Internal server error",
+            ),
+            (
+                AppError::FeatureDisabled("media"),
+                501,
+                "feature_disabled",
+                "this node was built without the media feature",
+            ),
+        ];
+        for (err, status, code, message) in cases {
+            let (got_status, got) = body(err).await;
+            assert_eq!(got_status.as_u16(), status);
+            assert_eq!(
+                got,
+                serde_json::json!({"error": {"code": code, "message": message}})
+            );
+        }
+    }
+}
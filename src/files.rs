@@ -1,7 +1,7 @@
 use crate::config::Config;
 use crate::error::{AppError, AppResult};
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug, Serialize, serde::Deserialize, Clone)]
 pub struct FileEntry {
@@ -30,15 +30,25 @@ pub fn resolve_dir(config: &Config, label: &str) -> AppResult<PathBuf> {
 }
 
 /// Resolve a relative path within a directory, rejecting traversal attacks
-pub fn resolve_path(base_dir: &PathBuf, rel_path: &str) -> AppResult<PathBuf> {
+pub fn resolve_path(base_dir: &Path, rel_path: &str) -> AppResult<PathBuf> {
     let cleaned = rel_path.trim_start_matches('/');
-    if cleaned.contains("..") {
+    if cleaned.contains('\0') {
+        return Err(AppError::BadRequest("Invalid path".into()));
+    }
+    // Judge by components so names like "a..b.jpg" are fine but "..",
+    // absolute paths and Windows prefixes are not
+    if !Path::new(cleaned)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
         return Err(AppError::BadRequest("Path traversal not allowed".into()));
     }
 
     let full_path = base_dir.join(cleaned);
 
-    let canonical_base = base_dir.canonicalize().unwrap_or_else(|_| base_dir.clone());
+    let canonical_base = base_dir
+        .canonicalize()
+        .unwrap_or_else(|_| base_dir.to_path_buf());
     let canonical_full = full_path
         .canonicalize()
         .unwrap_or_else(|_| full_path.clone());
@@ -50,6 +60,44 @@ pub fn resolve_path(base_dir: &PathBuf, rel_path: &str) -> AppResult<PathBuf> {
     Ok(full_path)
 }
 
+/// Longest file name kept by `sanitize_filename`, in bytes.
+const MAX_FILENAME_BYTES: usize = 200;
+
+/// Make a file name from another node safe to store and display: no path
+/// separators, control characters or bidi overrides that could disguise
+/// the extension, and at most `MAX_FILENAME_BYTES` with the extension kept.
+pub fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !is_bidi_control(*c))
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        return "file".to_string();
+    }
+    if cleaned.len() <= MAX_FILENAME_BYTES {
+        return cleaned.to_string();
+    }
+
+    let ext = match cleaned.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= 16 => &cleaned[stem.len()..],
+        _ => "",
+    };
+    let mut stem_end = MAX_FILENAME_BYTES - ext.len();
+    while !cleaned.is_char_boundary(stem_end) {
+        stem_end -= 1;
+    }
+    format!("{}{ext}", &cleaned[..stem_end])
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200E}' | '\u{200F}' | '\u{061C}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
 /// List files in a directory
 pub fn list_files(config: &Config, label: &str, rel_path: &str) -> AppResult<Vec<FileEntry>> {
     let base = resolve_dir(config, label)?;
@@ -296,6 +344,50 @@ mod tests {
         assert!(resolve_path(&base, "foo/../../../etc/passwd").is_err());
     }
 
+    #[test]
+    fn resolve_path_rejects_absolute_and_nul() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path().to_path_buf();
+        assert!(resolve_path(&base, "sub/\0file").is_err());
+        assert!(resolve_path(&base, "sub/..").is_err());
+        // A leading slash is relative to the directory, not the filesystem root
+        assert_eq!(resolve_path(&base, "/a.txt").unwrap(), base.join("a.txt"));
+    }
+
+    #[test]
+    fn resolve_path_allows_dots_inside_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path().to_path_buf();
+        assert_eq!(
+            resolve_path(&base, "./a..b.jpg").unwrap(),
+            base.join("./a..b.jpg")
+        );
+    }
+
+    #[test]
+    fn sanitize_filename_strips_hostile_characters() {
+        assert_eq!(
+            sanitize_filename("../../.ssh/authorized_keys"),
+            ".._.._.ssh_authorized_keys"
+        );
+        assert_eq!(sanitize_filename("photo\u{202E}gpj.exe"), "photogpj.exe");
+        assert_eq!(sanitize_filename("a\0b\r\n.jpg"), "ab.jpg");
+        assert_eq!(sanitize_filename("  "), "file");
+        assert_eq!(sanitize_filename(".."), "file");
+        assert_eq!(
+            sanitize_filename("Café déjà vu 写真.jpg"),
+            "Café déjà vu 写真.jpg"
+        );
+    }
+
+    #[test]
+    fn sanitize_filename_truncates_keeping_extension() {
+        let long = format!("{}.jpeg", "é".repeat(150));
+        let sanitized = sanitize_filename(&long);
+        assert!(sanitized.len() <= MAX_FILENAME_BYTES);
+        assert!(sanitized.ends_with("é.jpeg"));
+    }
+
     #[test]
     fn resolve_path_allows_normal_paths() {
         let tmp = tempfile::tempdir().unwrap();
@@ -0,0 +1,38 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+
+use super::HttpState;
+use crate::error::AppResult;
+use crate::release_notes::{self, Release, WhatsNew};
+
+/// GET /api/v1/changelog — release notes built into this binary.
+async fn changelog() -> Json<&'static [Release]> {
+    Json(release_notes::changelog())
+}
+
+/// GET /api/v1/whats-new — releases since the version that ran before and
+/// notes left by migrations, until dismissed; null when there is nothing.
+async fn whats_new(State(state): State<HttpState>) -> AppResult<Json<Option<WhatsNew>>> {
+    let conn = state.db.get()?;
+    Ok(Json(release_notes::whats_new(
+        &conn,
+        release_notes::changelog(),
+    )?))
+}
+
+/// POST /api/v1/whats-new/dismiss — hide what GET /api/v1/whats-new shows.
+async fn dismiss(State(state): State<HttpState>) -> AppResult<StatusCode> {
+    let conn = state.db.get()?;
+    release_notes::dismiss(&conn, Utc::now())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route("/api/v1/changelog", get(changelog))
+        .route("/api/v1/whats-new", get(whats_new))
+        .route("/api/v1/whats-new/dismiss", post(dismiss))
+}
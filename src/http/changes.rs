@@ -0,0 +1,33 @@
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+
+use super::HttpState;
+use crate::changes::{self, ChangesPage};
+use crate::error::AppResult;
+
+#[derive(Debug, Default, Deserialize)]
+struct ChangesQuery {
+    /// From the previous response; omit to start over
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+/// GET /api/v1/changes — files and devices that changed since `cursor`,
+/// for a client catching up after being offline.
+async fn changes_since(
+    State(state): State<HttpState>,
+    Query(query): Query<ChangesQuery>,
+) -> AppResult<Json<ChangesPage>> {
+    let conn = state.db.get()?;
+    Ok(Json(changes::since(
+        &conn,
+        query.cursor.as_deref(),
+        query.limit.unwrap_or(changes::DEFAULT_PAGE),
+    )?))
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new().route("/api/v1/changes", get(changes_since))
+}
@@ -0,0 +1,119 @@
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{Extensions, HeaderMap, StatusCode, Version};
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+/// Responses smaller than this aren't worth the encoding overhead.
+const MIN_SIZE: u16 = 1024;
+
+/// gzip or brotli, whichever the client's Accept-Encoding prefers.
+pub fn layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(SizeAbove::new(MIN_SIZE).and(compressible))
+}
+
+/// Only text-like bodies shrink: images and video are already compressed,
+/// and an event stream must reach the client as each event is written.
+fn compressible(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    match mime.split_once('/') {
+        Some(("text", "event-stream")) => false,
+        Some(("text", _)) => true,
+        Some(("application", sub)) => {
+            matches!(sub, "json" | "javascript" | "xml") || sub.ends_with("+json")
+        }
+        Some(("image", "svg+xml")) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn big() -> String {
+        "salita ".repeat(1024)
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/json", get(|| async { axum::Json(vec![big()]) }))
+            .route("/small", get(|| async { axum::Json("ok") }))
+            .route(
+                "/events",
+                get(|| async { ([(CONTENT_TYPE, "text/event-stream")], big()) }),
+            )
+            .route(
+                "/photo",
+                get(|| async { ([(CONTENT_TYPE, "image/jpeg")], big()) }),
+            )
+            .layer(layer())
+    }
+
+    async fn encoding(uri: &str, accept: Option<&str>) -> Option<String> {
+        let mut req = Request::builder().uri(uri);
+        if let Some(accept) = accept {
+            req = req.header(ACCEPT_ENCODING, accept);
+        }
+        let res = app()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        res.headers()
+            .get(CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn large_json_is_encoded_as_requested() {
+        assert_eq!(
+            encoding("/json", Some("gzip")).await.as_deref(),
+            Some("gzip")
+        );
+        assert_eq!(encoding("/json", Some("br")).await.as_deref(), Some("br"));
+        assert_eq!(encoding("/json", None).await, None);
+    }
+
+    #[tokio::test]
+    async fn small_media_and_event_streams_are_left_alone() {
+        assert_eq!(encoding("/small", Some("gzip, br")).await, None);
+        assert_eq!(encoding("/photo", Some("gzip, br")).await, None);
+        assert_eq!(encoding("/events", Some("gzip, br")).await, None);
+    }
+
+    #[test]
+    fn compressible_content_types() {
+        let check = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+            compressible(
+                StatusCode::OK,
+                Version::HTTP_11,
+                &headers,
+                &Extensions::new(),
+            )
+        };
+        assert!(check("text/html; charset=utf-8"));
+        assert!(check("application/json"));
+        assert!(check("application/problem+json"));
+        assert!(check("image/svg+xml"));
+        assert!(!check("text/event-stream"));
+        assert!(!check("image/png"));
+        assert!(!check("video/mp4"));
+        assert!(!check("application/octet-stream"));
+    }
+}
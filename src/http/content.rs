@@ -1,14 +1,20 @@
 use axum::body::Body;
 use axum::extract::{Path, Query, State};
-use axum::http::header;
+use axum::http::{header, HeaderValue};
 use axum::response::{IntoResponse, Json, Response};
 use axum::routing::{get, post};
 use axum::Router;
 use rusqlite::params;
 use serde::{Deserialize, Serialize};
+use tokio_util::io::ReaderStream;
 
+use crate::content_controls;
 use crate::error::{AppError, AppResult};
 use crate::http::HttpState;
+use crate::node_archive;
+use crate::peer_client::PeerClient;
+use crate::remote_cache;
+use crate::scan::{self, Scanner};
 
 pub fn router() -> Router<HttpState> {
     Router::new()
@@ -27,21 +33,46 @@ async fn serve_content(
     State(state): State<HttpState>,
     Path(cid): Path<String>,
 ) -> AppResult<Response> {
-    let conn = state.db.get()?;
+    let (dir, path, filename, is_local): (String, String, String, bool) = {
+        let conn = state.db.get()?;
+        let row: (String, String, String, bool) = conn
+            .query_row(
+                "SELECT dir, path, filename, is_local FROM content_index WHERE cid = ?1",
+                params![cid],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(|_| AppError::NotFound)?;
+        if row.3 {
+            let scanning = Scanner::from_config(&state.config.storage).is_some();
+            scan::check_available(&conn, scanning, &row.0, &row.1)?;
+        }
+        row
+    };
 
-    let (dir, path): (String, String) = conn
-        .query_row(
-            "SELECT dir, path FROM content_index WHERE cid = ?1",
-            params![cid],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        )
-        .map_err(|_| AppError::NotFound)?;
+    // Entries from other nodes are fetched from their origin and cached
+    if !is_local {
+        let content =
+            remote_cache::fetch_through(&state.remote_cache, &state.db, &PeerClient::new(), &cid)
+                .await?;
+        // A peer's content type is only passed on if it's a valid header
+        let content_type = HeaderValue::from_str(&content.content_type)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+        return Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, content.size)
+            .header(
+                header::CONTENT_DISPOSITION,
+                super::headers::content_disposition(&filename),
+            )
+            .body(Body::from_stream(ReaderStream::new(content.file)))
+            .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")));
+    }
 
     let base = state
         .config
         .resolve_directory(&dir)
         .ok_or(AppError::NotFound)?;
-    let file_path = base.join(&path);
+    let file_path = crate::files::resolve_path(&base, &path)?;
 
     if !file_path.is_file() {
         return Err(AppError::NotFound);
@@ -64,7 +95,7 @@ async fn serve_content(
         .header(header::CONTENT_TYPE, content_type)
         .header(
             header::CONTENT_DISPOSITION,
-            format!("inline; filename=\"{}\"", filename),
+            super::headers::content_disposition(&filename),
         )
         .body(Body::from(bytes))
         .unwrap())
@@ -129,6 +160,19 @@ async fn serve_preview(
     let result = tokio::task::spawn_blocking(move || {
         let conn = db.get()?;
 
+        let (dir, path, file_type, is_local): (String, String, String, bool) = conn
+            .query_row(
+                "SELECT dir, path, file_type, is_local FROM content_index WHERE cid = ?1",
+                params![cid_clone],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(|_| AppError::NotFound)?;
+        // A preview shows the file, so it is gated like the file itself
+        if is_local {
+            let scanning = Scanner::from_config(&config.storage).is_some();
+            scan::check_available(&conn, scanning, &dir, &path)?;
+        }
+
         // Check if preview already cached in DB
         let existing: Option<Vec<u8>> = conn
             .query_row(
@@ -142,42 +186,22 @@ async fn serve_preview(
             return Ok::<Vec<u8>, AppError>(preview);
         }
 
-        // Generate on-demand: look up the file path
-        let (dir, path, file_type): (String, String, String) = conn
-            .query_row(
-                "SELECT dir, path, file_type FROM content_index WHERE cid = ?1",
-                params![cid_clone],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-            )
-            .map_err(|_| AppError::NotFound)?;
-
+        // Generate on-demand
         let base = config
             .resolve_directory(&dir)
             .ok_or(AppError::NotFound)?;
-        let file_path = base.join(&path);
+        let file_path = crate::files::resolve_path(&base, &path)?;
 
         if !file_path.is_file() {
             return Err(AppError::NotFound);
         }
 
-        let preview_bytes = if file_type == "raw" {
-            crate::thumbnail::generate_raw_preview(&file_path)
-                .map_err(|e| AppError::Internal(format!("RAW preview error: {e}")))?
-        } else {
-            let bytes = std::fs::read(&file_path)
-                .map_err(|e| AppError::Internal(format!("Read error: {e}")))?;
-            crate::thumbnail::generate_image_preview(&bytes)
-                .map_err(|e| AppError::Internal(format!("Preview error: {e}")))?
-        };
-
-        // Decode to get dimensions and cache
-        if let Ok(img) = image::load_from_memory(&preview_bytes) {
-            let _ = conn.execute(
-                "INSERT OR REPLACE INTO content_previews (cid, preview, width, height)
-                 VALUES (?1, ?2, ?3, ?4)",
-                params![cid_clone, preview_bytes, img.width() as i32, img.height() as i32],
-            );
-        }
+        let (preview_bytes, width, height) = generate_preview(&file_type, &file_path)?;
+        let _ = conn.execute(
+            "INSERT OR REPLACE INTO content_previews (cid, preview, width, height)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![cid_clone, preview_bytes, width, height],
+        );
 
         Ok(preview_bytes)
     })
@@ -193,6 +217,35 @@ async fn serve_preview(
         .unwrap())
 }
 
+/// Render a preview JPEG of a local file, with its width and height.
+#[cfg(feature = "media")]
+fn generate_preview(
+    file_type: &str,
+    file_path: &std::path::Path,
+) -> AppResult<(Vec<u8>, u32, u32)> {
+    let preview_bytes = if file_type == "raw" {
+        crate::thumbnail::generate_raw_preview(file_path)
+            .map_err(|e| AppError::Internal(format!("RAW preview error: {e}")))?
+    } else {
+        let bytes =
+            std::fs::read(file_path).map_err(|e| AppError::Internal(format!("Read error: {e}")))?;
+        crate::thumbnail::generate_image_preview(&bytes)
+            .map_err(|e| AppError::Internal(format!("Preview error: {e}")))?
+    };
+
+    let img = image::load_from_memory(&preview_bytes)
+        .map_err(|e| AppError::Internal(format!("Failed to read back preview: {e}")))?;
+    Ok((preview_bytes, img.width(), img.height()))
+}
+
+#[cfg(not(feature = "media"))]
+fn generate_preview(
+    _file_type: &str,
+    _file_path: &std::path::Path,
+) -> AppResult<(Vec<u8>, u32, u32)> {
+    Err(AppError::FeatureDisabled("media"))
+}
+
 // --- Content metadata ---
 
 #[derive(Serialize)]
@@ -400,6 +453,12 @@ async fn catalog(
          LEFT JOIN content_previews cp ON ci.cid = cp.cid
          WHERE 1=1",
     );
+    sql.push_str(" AND ");
+    sql.push_str(content_controls::NOT_MUTED);
+    sql.push_str(" AND ");
+    sql.push_str(node_archive::NOT_FROM_ARCHIVED);
+    // Quarantined and not yet scanned files are only listed for review
+    sql.push_str(" AND ci.scan_status = 'clean'");
     let mut bind_values: Vec<String> = Vec::new();
 
     if let Some(ref dir) = params.dir {
@@ -411,7 +470,9 @@ async fn catalog(
         sql.push_str(&format!(" AND ci.file_type = ?{}", bind_values.len()));
     }
     if let Some(ref since) = params.since {
-        bind_values.push(since.clone());
+        let since = crate::db::parse_ts(since)
+            .map_err(|e| AppError::BadRequest(format!("Invalid since timestamp: {e}")))?;
+        bind_values.push(crate::db::format_ts(since));
         sql.push_str(&format!(" AND ci.indexed_at > ?{}", bind_values.len()));
     }
 
@@ -476,6 +537,7 @@ async fn index_on_demand(
     let paths = body.paths;
 
     let results = tokio::task::spawn_blocking(move || {
+        let scanner = Scanner::from_config(&config.storage);
         let base = match config.resolve_directory(&dir) {
             Some(b) if b.is_dir() => b,
             _ => return Vec::new(),
@@ -483,15 +545,17 @@ async fn index_on_demand(
 
         let mut results = Vec::new();
         for rel_path in &paths {
-            let file_path = base.join(rel_path);
-            if !file_path.is_file() {
-                results.push(IndexResult {
-                    path: rel_path.clone(),
-                    cid: None,
-                    has_thumbnail: false,
-                });
-                continue;
-            }
+            let file_path = match crate::files::resolve_path(&base, rel_path) {
+                Ok(p) if p.is_file() => p,
+                _ => {
+                    results.push(IndexResult {
+                        path: rel_path.clone(),
+                        cid: None,
+                        has_thumbnail: false,
+                    });
+                    continue;
+                }
+            };
 
             // Check if already indexed with thumbnail
             if let Ok(conn) = pool.get() {
@@ -519,7 +583,7 @@ async fn index_on_demand(
             }
 
             // Index this file now
-            match crate::indexer::index_file(&pool, &dir, &base, &file_path) {
+            match crate::indexer::index_file(&pool, scanner.as_ref(), &dir, &base, &file_path) {
                 Ok(Some(entry)) => {
                     results.push(IndexResult {
                         path: rel_path.clone(),
@@ -537,7 +601,7 @@ async fn index_on_demand(
                                 |row| row.get(0),
                             )
                             .ok();
-                        let has_thumb = cid.as_ref().map_or(false, |c| {
+                        let has_thumb = cid.as_ref().is_some_and(|c| {
                             conn.query_row(
                                 "SELECT COUNT(*) > 0 FROM content_thumbnails WHERE cid = ?1",
                                 rusqlite::params![c],
@@ -569,3 +633,49 @@ async fn index_on_demand(
 
     Ok(Json(results))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DirectoryConfig;
+    use axum::extract::Request;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn previews_of_quarantined_files_are_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = crate::db::test_pool();
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO content_index (cid, dir, path, filename, size, file_type, scan_status)
+                 VALUES ('c1', 'photos', 'a.jpg', 'a.jpg', 5, 'image', 'flagged');
+                 INSERT INTO content_previews (cid, preview, width, height)
+                 VALUES ('c1', x'ffd8', 1, 1);",
+            )
+            .unwrap();
+        let mut state = HttpState::for_tests(pool.clone(), tmp.path());
+        state.config.directories = vec![DirectoryConfig {
+            label: "photos".into(),
+            path: tmp.path().to_string_lossy().into_owned(),
+        }];
+        let app = router().with_state(state);
+        let preview = || {
+            Request::builder()
+                .uri("/api/v1/content/c1/preview")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let resp = app.clone().oneshot(preview()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        pool.get()
+            .unwrap()
+            .execute("UPDATE content_index SET scan_status = 'clean'", [])
+            .unwrap();
+        let resp = app.oneshot(preview()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
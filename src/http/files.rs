@@ -9,6 +9,7 @@ use serde::Deserialize;
 use super::HttpState;
 use crate::error::AppResult;
 use crate::files;
+use crate::scan::{self, Scanner};
 
 #[derive(Deserialize)]
 struct ListParams {
@@ -49,6 +50,11 @@ async fn read_file_handler(
     State(state): State<HttpState>,
     Query(params): Query<FileParams>,
 ) -> AppResult<Response> {
+    {
+        let conn = state.db.get()?;
+        let scanning = Scanner::from_config(&state.config.storage).is_some();
+        scan::check_available(&conn, scanning, &params.dir, &params.path)?;
+    }
     let bytes = files::read_file_bytes(&state.config, &params.dir, &params.path)?;
 
     let file_path = std::path::Path::new(&params.path);
@@ -65,7 +71,7 @@ async fn read_file_handler(
         .header(header::CONTENT_TYPE, content_type)
         .header(
             header::CONTENT_DISPOSITION,
-            format!("inline; filename=\"{}\"", filename),
+            super::headers::content_disposition(&filename),
         )
         .body(Body::from(bytes))
         .unwrap())
@@ -0,0 +1,141 @@
+use axum::extract::{Request, State};
+use axum::http::header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+
+/// Add security headers to every response, leaving any a handler set itself.
+/// `csp` is `None` when the policy has been disabled in config.
+pub async fn security_headers(
+    State(csp): State<Option<HeaderValue>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let mut res = next.run(req).await;
+    let headers = res.headers_mut();
+
+    headers
+        .entry(X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers
+        .entry(REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+    headers
+        .entry(X_FRAME_OPTIONS)
+        .or_insert(HeaderValue::from_static("DENY"));
+    if let Some(csp) = csp {
+        headers.entry(CONTENT_SECURITY_POLICY).or_insert(csp);
+    }
+
+    res
+}
+
+/// Parse the configured policy; an empty string disables the header.
+pub fn csp_header(policy: &str) -> anyhow::Result<Option<HeaderValue>> {
+    if policy.trim().is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(policy)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid content_security_policy: {e}"))
+}
+
+/// `inline` Content-Disposition for `filename`. Names come from disk or
+/// from other nodes, so the quoted form gets an ASCII-only fallback and the
+/// real name goes in the RFC 5987 `filename*` parameter.
+pub fn content_disposition(filename: &str) -> HeaderValue {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    HeaderValue::from_str(&format!(
+        "inline; filename=\"{fallback}\"; filename*=UTF-8''{encoded}"
+    ))
+    .unwrap_or_else(|_| HeaderValue::from_static("inline"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_CSP;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    fn app(csp: Option<HeaderValue>) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route(
+                "/custom",
+                get(|| async { ([(CONTENT_SECURITY_POLICY, "default-src 'self'")], "ok") }),
+            )
+            .layer(middleware::from_fn_with_state(csp, security_headers))
+    }
+
+    async fn get_headers(app: Router, uri: &str) -> axum::http::HeaderMap {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().headers().clone()
+    }
+
+    #[tokio::test]
+    async fn sets_default_headers() {
+        let headers = get_headers(app(csp_header(DEFAULT_CSP).unwrap()), "/").await;
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[CONTENT_SECURITY_POLICY], DEFAULT_CSP);
+    }
+
+    #[tokio::test]
+    async fn keeps_handler_policy() {
+        let headers = get_headers(app(csp_header(DEFAULT_CSP).unwrap()), "/custom").await;
+        assert_eq!(headers[CONTENT_SECURITY_POLICY], "default-src 'self'");
+    }
+
+    #[tokio::test]
+    async fn empty_policy_disables_csp() {
+        let csp = csp_header("  ").unwrap();
+        assert!(csp.is_none());
+        let headers = get_headers(app(csp), "/").await;
+        assert!(headers.get(CONTENT_SECURITY_POLICY).is_none());
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+
+    #[test]
+    fn content_disposition_escapes_hostile_names() {
+        assert_eq!(
+            content_disposition("photo.jpg"),
+            "inline; filename=\"photo.jpg\"; filename*=UTF-8''photo.jpg"
+        );
+        assert_eq!(
+            content_disposition("a\"b\r\nSet-Cookie: x.jpg"),
+            "inline; filename=\"a_b__Set-Cookie: x.jpg\"; \
+             filename*=UTF-8''a%22b%0D%0ASet-Cookie%3A%20x.jpg"
+        );
+        assert_eq!(
+            content_disposition("写真.jpg"),
+            "inline; filename=\"__.jpg\"; filename*=UTF-8''%E5%86%99%E7%9C%9F.jpg"
+        );
+    }
+
+    #[test]
+    fn invalid_policy_is_rejected() {
+        assert!(csp_header("default-src\n'none'").is_err());
+    }
+}
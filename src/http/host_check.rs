@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::http::uri::Authority;
+use axum::http::{header::HOST, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// How often the machine's own addresses are re-read, so a new DHCP lease
+/// or interface is picked up without a restart.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Minimum time between warnings about rejected Host headers.
+const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Host header values this node answers to. A browser tricked by DNS
+/// rebinding sends the attacker's domain as Host, so anything that doesn't
+/// name this machine is turned away.
+pub struct AllowedHosts {
+    any: bool,
+    names: HashSet<String>,
+    addrs: RwLock<HashSet<IpAddr>>,
+    last_warned: Mutex<Option<Instant>>,
+}
+
+impl AllowedHosts {
+    /// Allow localhost, `hostname` and its .local name, plus `extra` from
+    /// config. Own addresses are added by `refresh_addrs`.
+    pub fn new(extra: &[String], hostname: Option<&str>) -> Self {
+        let mut names: HashSet<String> = ["localhost".to_string()].into();
+        if let Some(hostname) = hostname {
+            let hostname = normalize(hostname);
+            names.insert(format!("{hostname}.local"));
+            names.insert(hostname);
+        }
+        names.extend(extra.iter().map(|h| normalize(h)));
+
+        Self {
+            any: names.contains("*"),
+            names,
+            addrs: RwLock::new(HashSet::new()),
+            last_warned: Mutex::new(None),
+        }
+    }
+
+    /// Re-read the addresses of this machine's network interfaces.
+    pub fn refresh_addrs(&self) {
+        match if_addrs::get_if_addrs() {
+            Ok(interfaces) => self.set_addrs(interfaces.iter().map(|i| i.ip()).collect()),
+            Err(e) => tracing::warn!("Failed to list network interfaces: {e}"),
+        }
+    }
+
+    fn set_addrs(&self, addrs: HashSet<IpAddr>) {
+        if let Ok(mut current) = self.addrs.write() {
+            *current = addrs;
+        }
+    }
+
+    /// Whether a Host header value (with or without a port) names this node.
+    pub fn allows(&self, host: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let Ok(authority) = host.parse::<Authority>() else {
+            return false;
+        };
+        let name = authority
+            .host()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+
+        match name.parse::<IpAddr>() {
+            Ok(ip) => {
+                ip.is_loopback()
+                    || self.addrs.read().is_ok_and(|addrs| addrs.contains(&ip))
+                    || self.names.contains(&ip.to_string())
+            }
+            Err(_) => self.names.contains(&normalize(name)),
+        }
+    }
+
+    /// Log a rejected host at warn level at most once per `WARN_INTERVAL`.
+    fn warn_rejected(&self, host: &str) {
+        let now = Instant::now();
+        let should_warn = self.last_warned.lock().is_ok_and(|mut last| {
+            let due = last.is_none_or(|at| now.duration_since(at) >= WARN_INTERVAL);
+            if due {
+                *last = Some(now);
+            }
+            due
+        });
+        if should_warn {
+            tracing::warn!(
+                "Rejected request for unknown Host {host:?}; \
+                 add it to server.allowed_hosts if it is expected"
+            );
+        } else {
+            tracing::debug!("Rejected request for unknown Host {host:?}");
+        }
+    }
+}
+
+fn normalize(host: &str) -> String {
+    host.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Keep the interface address list current for the life of the server.
+pub fn spawn_refresh(hosts: Arc<AllowedHosts>) {
+    tokio::spawn(async move {
+        loop {
+            hosts.refresh_addrs();
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+/// Answer 421 Misdirected Request before routing when the Host header
+/// doesn't name this node. Requests without a Host come from non-browser
+/// clients, which DNS rebinding can't drive, and are let through.
+pub async fn validate_host(
+    State(hosts): State<Arc<AllowedHosts>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let host = req
+        .headers()
+        .get(HOST)
+        .map(|h| h.to_str().unwrap_or_default().to_string())
+        .or_else(|| req.uri().authority().map(|a| a.to_string()));
+
+    if let Some(host) = host {
+        if !hosts.allows(&host) {
+            hosts.warn_rejected(&host);
+            return (StatusCode::MISDIRECTED_REQUEST, "Unknown host").into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    fn hosts(extra: &[&str]) -> AllowedHosts {
+        let extra: Vec<String> = extra.iter().map(|h| h.to_string()).collect();
+        let hosts = AllowedHosts::new(&extra, Some("Den-Mac"));
+        hosts.set_addrs(["192.168.1.20".parse().unwrap(), "fe80::1".parse().unwrap()].into());
+        hosts
+    }
+
+    async fn status(hosts: AllowedHosts, host: Option<&str>) -> StatusCode {
+        let app = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(hosts),
+                validate_host,
+            ));
+        let mut req = Request::builder().uri("/health");
+        if let Some(host) = host {
+            req = req.header(HOST, host);
+        }
+        app.oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn own_names_and_addresses_are_allowed() {
+        let hosts = hosts(&[]);
+        for host in [
+            "localhost",
+            "localhost:6969",
+            "127.0.0.1:6969",
+            "[::1]:6969",
+            "den-mac",
+            "Den-Mac.local:6969",
+            "den-mac.local.",
+            "192.168.1.20:6969",
+            "[fe80::1]:6969",
+        ] {
+            assert!(hosts.allows(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn foreign_hosts_are_rejected() {
+        let hosts = hosts(&[]);
+        for host in [
+            "evil.example.com",
+            "evil.example.com:6969",
+            "192.168.1.21:6969",
+            "localhost.evil.example.com",
+            "",
+            "bad host",
+        ] {
+            assert!(!hosts.allows(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn config_can_add_hosts_or_disable_the_check() {
+        let hosts = hosts(&["Home.Example.com", "10.0.0.5"]);
+        assert!(hosts.allows("home.example.com"));
+        assert!(hosts.allows("10.0.0.5:443"));
+        assert!(!hosts.allows("other.example.com"));
+
+        assert!(AllowedHosts::new(&["*".to_string()], None).allows("anything.example.com"));
+    }
+
+    #[tokio::test]
+    async fn middleware_rejects_with_421() {
+        assert_eq!(
+            status(hosts(&[]), Some("evil.example.com")).await,
+            StatusCode::MISDIRECTED_REQUEST
+        );
+        assert_eq!(
+            status(hosts(&[]), Some("localhost:6969")).await,
+            StatusCode::OK
+        );
+        assert_eq!(status(hosts(&[]), None).await, StatusCode::OK);
+    }
+}
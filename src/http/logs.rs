@@ -0,0 +1,102 @@
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::Level;
+
+use super::HttpState;
+use crate::error::{AppError, AppResult};
+use crate::log_buffer::{LogBuffer, LogRecord};
+use crate::log_level::LogLevelStatus;
+
+const DEFAULT_LIMIT: usize = 200;
+
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route("/api/v1/logs", get(recent_logs))
+        .route("/api/v1/logs/level", get(log_level).put(set_log_level))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LogsQuery {
+    /// Least severe level to include: error, warn or info (the default)
+    level: Option<String>,
+    limit: Option<usize>,
+}
+
+/// GET /api/v1/logs — the newest buffered log lines, oldest first.
+async fn recent_logs(
+    State(state): State<HttpState>,
+    Query(query): Query<LogsQuery>,
+) -> AppResult<Json<Vec<LogRecord>>> {
+    Ok(Json(filter_logs(&state.logs, &query)?))
+}
+
+/// GET /api/v1/logs/level — the filter in effect and when it reverts.
+async fn log_level(State(state): State<HttpState>) -> Json<LogLevelStatus> {
+    Json(state.log_level.status())
+}
+
+#[derive(Debug, Deserialize)]
+struct SetLogLevel {
+    /// RUST_LOG syntax, e.g. "salita=debug,info"
+    filter: String,
+}
+
+/// PUT /api/v1/logs/level — change the filter without a restart.
+async fn set_log_level(
+    State(state): State<HttpState>,
+    Json(body): Json<SetLogLevel>,
+) -> AppResult<Json<LogLevelStatus>> {
+    Ok(Json(state.log_level.set(&body.filter)?))
+}
+
+fn filter_logs(logs: &LogBuffer, query: &LogsQuery) -> AppResult<Vec<LogRecord>> {
+    let level = match query.level.as_deref() {
+        None => Level::INFO,
+        Some(level) => level
+            .parse::<Level>()
+            .map_err(|_| AppError::BadRequest(format!("Unknown log level: {level}")))?,
+    };
+    Ok(logs.snapshot(level, query.limit.unwrap_or(DEFAULT_LIMIT)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::log_buffer::BufferLayer;
+
+    #[test]
+    fn query_selects_level_and_limit() {
+        let logs = Arc::new(LogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry().with(BufferLayer::new(logs.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("indexed");
+            tracing::warn!("peer slow");
+            tracing::error!("disk full");
+        });
+
+        let query = |level: Option<&str>, limit: Option<usize>| LogsQuery {
+            level: level.map(str::to_string),
+            limit,
+        };
+        let messages =
+            |records: Vec<LogRecord>| records.into_iter().map(|r| r.message).collect::<Vec<_>>();
+
+        assert_eq!(
+            messages(filter_logs(&logs, &query(None, None)).unwrap()),
+            vec!["indexed", "peer slow", "disk full"]
+        );
+        assert_eq!(
+            messages(filter_logs(&logs, &query(Some("warn"), Some(1))).unwrap()),
+            vec!["disk full"]
+        );
+        assert!(matches!(
+            filter_logs(&logs, &query(Some("loud"), None)),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -1,16 +1,32 @@
-use axum::extract::State;
-use axum::routing::get;
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post, put};
 use axum::{Json, Router};
-use serde::Serialize;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
 
 use super::HttpState;
+use crate::admission;
+use crate::content_controls::{self, NodeContentSettings};
+use crate::error::{AppError, AppResult};
+use crate::membership::{self, MeshMember};
+use crate::node_alerts::{self, NodeAlert};
+use crate::node_archive;
+use crate::node_labels;
+use crate::sync_status::{self, PeerSyncStatus};
+use crate::update_check::UpdateManifest;
+
+/// Catalog entries listed in a device's detail view.
+const RECENT_ENTRIES_LIMIT: i64 = 10;
 
 #[derive(Serialize)]
 struct NodeInfo {
     id: String,
     name: String,
     version: String,
+    /// Name announced over mDNS, reachable as `<hostname>.local`
+    hostname: Option<String>,
     directories: Vec<String>,
+    update_available: Option<UpdateManifest>,
 }
 
 async fn get_node(State(state): State<HttpState>) -> Json<NodeInfo> {
@@ -24,7 +40,9 @@ async fn get_node(State(state): State<HttpState>) -> Json<NodeInfo> {
         id: state.node_identity.id.clone(),
         name: state.node_identity.name.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname: state.config.server.advertised_hostname(),
         directories: dirs,
+        update_available: state.update_status.available(),
     })
 }
 
@@ -38,27 +56,54 @@ async fn list_directories(State(state): State<HttpState>) -> Json<Vec<String>> {
     Json(dirs)
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct DeviceListQuery {
+    /// Only devices carrying this label (case-insensitive)
+    label: Option<String>,
+    /// List archived devices instead of active ones
+    #[serde(default)]
+    archived: bool,
+}
+
 async fn list_devices(
     State(state): State<HttpState>,
+    Query(query): Query<DeviceListQuery>,
 ) -> Result<Json<Vec<serde_json::Value>>, crate::error::AppError> {
     let conn = state.db.get().map_err(|e| {
         crate::error::AppError::Internal(format!("Database error: {}", e))
     })?;
 
     let mut stmt = conn
-        .prepare("SELECT id, name, endpoint, port, is_self, status, last_seen FROM devices")
+        .prepare(
+            "SELECT d.id, d.name, d.endpoint, d.port, d.is_self, d.status, d.last_seen,
+                    COALESCE(s.muted, 0), COALESCE(s.blocked, 0), d.learned_from, d.archived_at
+             FROM devices d
+             LEFT JOIN node_content_settings s ON s.node_id = d.id
+             WHERE (?1 IS NULL OR d.id IN (SELECT node_id FROM node_labels WHERE label = ?1))
+               AND (d.archived_at IS NOT NULL) = ?2",
+        )
         .map_err(|e| crate::error::AppError::Internal(format!("Query error: {}", e)))?;
+    let mut alerts = crate::node_alerts::compute_alerts(&conn)?;
+    let mut labels = node_labels::all(&conn)?;
 
+    let label = query.label.as_deref().map(node_labels::normalize);
     let devices: Vec<serde_json::Value> = stmt
-        .query_map([], |row| {
+        .query_map(rusqlite::params![label, query.archived], |row| {
+            let id = row.get::<_, String>(0)?;
             Ok(serde_json::json!({
-                "id": row.get::<_, String>(0)?,
+                "alerts": alerts.remove(&id).unwrap_or_default(),
+                "labels": labels.remove(&id).unwrap_or_default(),
+                "id": id,
                 "name": row.get::<_, String>(1)?,
                 "endpoint": row.get::<_, Option<String>>(2)?,
                 "port": row.get::<_, i64>(3)?,
                 "is_self": row.get::<_, bool>(4)?,
                 "status": row.get::<_, String>(5)?,
                 "last_seen": row.get::<_, Option<String>>(6)?,
+                "muted": row.get::<_, bool>(7)?,
+                "blocked": row.get::<_, bool>(8)?,
+                "learned_from": row.get::<_, Option<String>>(9)?,
+                "archived_at": row.get::<_, Option<String>>(10)?,
             }))
         })
         .map_err(|e| crate::error::AppError::Internal(format!("Query error: {}", e)))?
@@ -68,9 +113,508 @@ async fn list_devices(
     Ok(Json(devices))
 }
 
+#[derive(Debug, Serialize)]
+struct Device {
+    id: String,
+    name: String,
+    endpoint: Option<String>,
+    port: i64,
+    base_path: String,
+    is_self: bool,
+    status: String,
+    last_seen: Option<String>,
+    created_at: String,
+    /// Peer this device was heard about from, if never seen firsthand
+    learned_from: Option<String>,
+    /// When the owner archived it, if they did
+    archived_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CatalogEntry {
+    cid: String,
+    filename: String,
+    size: i64,
+    file_type: String,
+    indexed_at: String,
+}
+
+/// Catalog entries a device has contributed.
+#[derive(Debug, Serialize)]
+struct DeviceCatalog {
+    files: i64,
+    bytes: i64,
+    recent: Vec<CatalogEntry>,
+}
+
+/// Everything this node knows about one device, for a device detail view.
+#[derive(Debug, Serialize)]
+struct DeviceDetail {
+    #[serde(flatten)]
+    device: Device,
+    content_settings: NodeContentSettings,
+    labels: Vec<String>,
+    sync: Vec<PeerSyncStatus>,
+    alerts: Vec<NodeAlert>,
+    catalog: DeviceCatalog,
+}
+
+/// Assemble a device's detail with one query per section, or `None` if
+/// the device is unknown.
+fn device_detail(conn: &rusqlite::Connection, id: &str) -> AppResult<Option<DeviceDetail>> {
+    let device = conn
+        .query_row(
+            "SELECT id, name, endpoint, port, base_path, is_self, status, last_seen, created_at,
+                    learned_from, archived_at
+             FROM devices WHERE id = ?1",
+            [id],
+            |row| {
+                Ok(Device {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    endpoint: row.get(2)?,
+                    port: row.get(3)?,
+                    base_path: row.get(4)?,
+                    is_self: row.get(5)?,
+                    status: row.get(6)?,
+                    last_seen: row.get(7)?,
+                    created_at: row.get(8)?,
+                    learned_from: row.get(9)?,
+                    archived_at: row.get(10)?,
+                })
+            },
+        )
+        .optional()?;
+    let Some(device) = device else {
+        return Ok(None);
+    };
+
+    // Local files carry no origin_node, so this node's own are is_local
+    const FROM_DEVICE: &str = "(is_local = 1 AND ?2) OR (is_local = 0 AND origin_node = ?1)";
+    let (files, bytes) = conn.query_row(
+        &format!("SELECT COUNT(*), COALESCE(SUM(size), 0) FROM content_index WHERE {FROM_DEVICE}"),
+        rusqlite::params![id, device.is_self],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT cid, filename, size, file_type, indexed_at FROM content_index
+         WHERE {FROM_DEVICE}
+         ORDER BY indexed_at DESC, cid
+         LIMIT ?3"
+    ))?;
+    let recent = stmt
+        .query_map(
+            rusqlite::params![id, device.is_self, RECENT_ENTRIES_LIMIT],
+            |row| {
+                Ok(CatalogEntry {
+                    cid: row.get(0)?,
+                    filename: row.get(1)?,
+                    size: row.get(2)?,
+                    file_type: row.get(3)?,
+                    indexed_at: row.get(4)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let sync = sync_status::list_status(conn)?
+        .into_iter()
+        .filter(|s| s.node_id == id)
+        .collect();
+    let alerts = node_alerts::compute_alerts(conn)?
+        .remove(id)
+        .unwrap_or_default();
+
+    Ok(Some(DeviceDetail {
+        device,
+        content_settings: content_controls::get(conn, id)?,
+        labels: node_labels::list(conn, id)?,
+        sync,
+        alerts,
+        catalog: DeviceCatalog {
+            files,
+            bytes,
+            recent,
+        },
+    }))
+}
+
+async fn get_device(
+    State(state): State<HttpState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DeviceDetail>> {
+    let pool = state.db.clone();
+    let detail = tokio::task::spawn_blocking(move || {
+        let conn = pool.get()?;
+        device_detail(&conn, &id)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Task join error: {e}")))??;
+
+    detail.map(Json).ok_or(AppError::NotFound)
+}
+
+async fn sync_status(
+    State(state): State<HttpState>,
+) -> Result<Json<Vec<crate::sync_status::PeerSyncStatus>>, crate::error::AppError> {
+    let conn = state.db.get()?;
+    let status = crate::sync_status::list_status(&conn)?;
+    Ok(Json(status))
+}
+
+#[derive(Deserialize)]
+struct ContentSettingsUpdate {
+    muted: bool,
+    blocked: bool,
+}
+
+#[derive(Serialize)]
+struct ContentSettingsResponse {
+    #[serde(flatten)]
+    settings: NodeContentSettings,
+    /// Entries deleted because the node is blocked
+    purged: u64,
+}
+
+async fn get_content_settings(
+    State(state): State<HttpState>,
+    Path(node_id): Path<String>,
+) -> AppResult<Json<NodeContentSettings>> {
+    let conn = state.db.get()?;
+    Ok(Json(content_controls::get(&conn, &node_id)?))
+}
+
+/// Mute or block catalog entries from another node. Blocking deletes what
+/// was already synced; unblocking pulls it back in from the catalog doc.
+async fn update_content_settings(
+    State(state): State<HttpState>,
+    Path(node_id): Path<String>,
+    Json(update): Json<ContentSettingsUpdate>,
+) -> AppResult<Json<ContentSettingsResponse>> {
+    if node_id == state.node_identity.id {
+        return Err(AppError::BadRequest(
+            "Cannot mute or block this node".into(),
+        ));
+    }
+
+    let pool = state.db.clone();
+    let id = node_id.clone();
+    let (settings, was_blocked, purged) = tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        let was_blocked = content_controls::update(&mut conn, &id, update.muted, update.blocked)?;
+        // Also finishes a purge an earlier block didn't complete
+        let purged = if update.blocked {
+            content_controls::purge_blocked(&mut conn, &id)?
+        } else {
+            Vec::new()
+        };
+        Ok::<_, AppError>((content_controls::get(&conn, &id)?, was_blocked, purged))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Task join error: {e}")))??;
+
+    // Cached copies of its content go with the catalog entries
+    state.remote_cache.remove(&state.db, &purged).await?;
+
+    if was_blocked && !settings.blocked {
+        spawn_catch_up(&state, format!("unblocking {node_id}"));
+    }
+
+    Ok(Json(ContentSettingsResponse {
+        settings,
+        purged: purged.len() as u64,
+    }))
+}
+
+/// Pull back in, from the catalog doc, the entries that were skipped while
+/// a node was blocked or archived. Runs in the background.
+fn spawn_catch_up(state: &HttpState, after: String) {
+    if let Some(catalog) = state.catalog.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = catalog.lock().await.initial_sync().await {
+                tracing::warn!("Catch-up sync after {after} failed: {e}");
+            }
+        });
+    }
+}
+
+/// POST /api/v1/devices/{id}/approve — admit a device waiting under
+/// `mesh.approval = "manual"`, or one rejected earlier.
+async fn approve_device(
+    State(state): State<HttpState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DeviceDetail>> {
+    let conn = state.db.get()?;
+    admission::approve(&conn, &state.config.mesh, &id)?;
+    device_detail(&conn, &id)?
+        .map(Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// POST /api/v1/devices/{id}/reject — turn a pending device away. It
+/// stays listed as rejected and is ignored when it announces itself.
+async fn reject_device(
+    State(state): State<HttpState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DeviceDetail>> {
+    let conn = state.db.get()?;
+    admission::reject(&conn, &id)?;
+    device_detail(&conn, &id)?
+        .map(Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// POST /api/v1/devices/{id}/archive — set a member aside without
+/// forgetting it. Idempotent.
+async fn archive_device(
+    State(state): State<HttpState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DeviceDetail>> {
+    let conn = state.db.get()?;
+    node_archive::archive(&conn, &id)?;
+    device_detail(&conn, &id)?
+        .map(Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// POST /api/v1/devices/{id}/unarchive — return an archived device to
+/// the mesh as it was, catching up on what it published meanwhile.
+/// Idempotent.
+async fn unarchive_device(
+    State(state): State<HttpState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DeviceDetail>> {
+    let conn = state.db.get()?;
+    if node_archive::unarchive(&conn, &id)? {
+        spawn_catch_up(&state, format!("unarchiving {id}"));
+    }
+    device_detail(&conn, &id)?
+        .map(Json)
+        .ok_or(AppError::NotFound)
+}
+
+/// PUT /api/v1/devices/{id}/labels/{label} — tag a device. Idempotent;
+/// returns the device's labels.
+async fn add_label(
+    State(state): State<HttpState>,
+    Path((node_id, label)): Path<(String, String)>,
+) -> AppResult<Json<Vec<String>>> {
+    let conn = state.db.get()?;
+    node_labels::add(&conn, &node_id, &label)?;
+    Ok(Json(node_labels::list(&conn, &node_id)?))
+}
+
+/// DELETE /api/v1/devices/{id}/labels/{label} — untag a device. Returns
+/// the device's remaining labels.
+async fn remove_label(
+    State(state): State<HttpState>,
+    Path((node_id, label)): Path<(String, String)>,
+) -> AppResult<Json<Vec<String>>> {
+    let conn = state.db.get()?;
+    node_labels::remove(&conn, &node_id, &label)?;
+    Ok(Json(node_labels::list(&conn, &node_id)?))
+}
+
+/// GET /api/v1/labels — device ids grouped by label, for a grouped
+/// device list. Unlabeled devices are grouped under "unlabeled".
+async fn label_groups(
+    State(state): State<HttpState>,
+) -> AppResult<Json<std::collections::BTreeMap<String, Vec<String>>>> {
+    let conn = state.db.get()?;
+    let mut stmt = conn.prepare("SELECT id FROM devices ORDER BY name, id")?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(Json(node_labels::group_devices(
+        &ids,
+        &node_labels::all(&conn)?,
+    )))
+}
+
+/// GET /api/v1/mesh/members — the devices this node knows firsthand, for
+/// peers pulling membership.
+async fn mesh_members(State(state): State<HttpState>) -> AppResult<Json<Vec<MeshMember>>> {
+    let conn = state.db.get()?;
+    Ok(Json(membership::firsthand_members(&conn)?))
+}
+
 pub fn router() -> Router<HttpState> {
     Router::new()
         .route("/api/v1/node", get(get_node))
         .route("/api/v1/directories", get(list_directories))
         .route("/api/v1/devices", get(list_devices))
+        .route("/api/v1/devices/{id}", get(get_device))
+        .route(
+            "/api/v1/devices/{id}/content-settings",
+            get(get_content_settings).put(update_content_settings),
+        )
+        .route("/api/v1/devices/{id}/approve", post(approve_device))
+        .route("/api/v1/devices/{id}/reject", post(reject_device))
+        .route("/api/v1/devices/{id}/archive", post(archive_device))
+        .route("/api/v1/devices/{id}/unarchive", post(unarchive_device))
+        .route(
+            "/api/v1/devices/{id}/labels/{label}",
+            put(add_label).delete(remove_label),
+        )
+        .route("/api/v1/labels", get(label_groups))
+        .route("/api/v1/mesh/members", get(mesh_members))
+        .route("/api/v1/sync/status", get(sync_status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn seeded_pool() -> crate::db::DbPool {
+        let pool = crate::db::test_pool();
+
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO devices (id, name, is_self, status, last_seen)
+               VALUES ('me', 'laptop', 1, 'online', datetime('now'));
+             INSERT INTO devices (id, name, endpoint, status, last_seen)
+               VALUES ('peer', 'nas', '192.168.1.5', 'online', datetime('now'));
+             INSERT INTO node_content_settings (node_id, muted) VALUES ('peer', 1);",
+        )
+        .unwrap();
+        for (cid, origin, is_local, age) in [
+            ("local", None, 1, "-1 hour"),
+            ("old", Some("peer"), 0, "-2 hours"),
+            ("new", Some("peer"), 0, "-1 minute"),
+        ] {
+            conn.execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, origin_node, is_local, indexed_at)
+                 VALUES (?1, 'd', ?1, ?1, 10, ?2, ?3, datetime('now', ?4))",
+                params![cid, origin, is_local, age],
+            )
+            .unwrap();
+        }
+        sync_status::record_failure(&conn, "peer", sync_status::SyncKind::Catalog, "boom").unwrap();
+        node_labels::add(&conn, "peer", "infra").unwrap();
+        drop(conn);
+        pool
+    }
+
+    #[test]
+    fn detail_composes_every_section() {
+        let pool = seeded_pool();
+        let detail = device_detail(&pool.get().unwrap(), "peer")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(detail.device.name, "nas");
+        assert_eq!(detail.device.endpoint.as_deref(), Some("192.168.1.5"));
+        assert!(detail.content_settings.muted);
+        assert_eq!(detail.labels, vec!["infra"]);
+        assert_eq!(detail.sync.len(), 1);
+        assert_eq!(detail.sync[0].last_error.as_deref(), Some("boom"));
+        assert_eq!(detail.alerts.len(), 1);
+        assert_eq!(detail.catalog.files, 2);
+        assert_eq!(detail.catalog.bytes, 20);
+        let recent: Vec<_> = detail
+            .catalog
+            .recent
+            .iter()
+            .map(|e| e.cid.as_str())
+            .collect();
+        assert_eq!(recent, vec!["new", "old"]);
+    }
+
+    #[test]
+    fn self_detail_counts_local_files() {
+        let pool = seeded_pool();
+        let detail = device_detail(&pool.get().unwrap(), "me").unwrap().unwrap();
+        assert!(detail.device.is_self);
+        assert_eq!(detail.catalog.files, 1);
+        assert_eq!(detail.catalog.recent[0].cid, "local");
+        assert!(detail.sync.is_empty());
+        assert!(detail.alerts.is_empty());
+    }
+
+    #[test]
+    fn unknown_device_is_none() {
+        let pool = seeded_pool();
+        assert!(device_detail(&pool.get().unwrap(), "nobody")
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn unarchiving_catches_up_on_what_was_skipped() {
+        use crate::catalog_sync::CatalogSync;
+        use crate::iroh_node::IrohNode;
+        use axum::body::Body;
+        use axum::extract::Request;
+        use tower::ServiceExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = seeded_pool();
+        let iroh = IrohNode::start(tmp.path(), None).await.unwrap();
+        let peer = CatalogSync::new(
+            iroh.docs.clone(),
+            iroh.blobs.clone(),
+            pool.clone(),
+            "peer".into(),
+        )
+        .await
+        .unwrap();
+        let catalog = CatalogSync::new(
+            iroh.docs.clone(),
+            iroh.blobs.clone(),
+            pool.clone(),
+            "node-1".into(),
+        )
+        .await
+        .unwrap();
+
+        node_archive::archive(&pool.get().unwrap(), "peer").unwrap();
+        peer.publish_entry(
+            "while-away",
+            "a.jpg",
+            "d",
+            "a.jpg",
+            1,
+            None,
+            "image",
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(catalog.initial_sync().await.unwrap(), 0);
+
+        let mut state = HttpState::for_tests(pool.clone(), tmp.path());
+        state.catalog = Some(std::sync::Arc::new(tokio::sync::Mutex::new(catalog)));
+        let resp = router()
+            .with_state(state)
+            .oneshot(
+                Request::post("/api/v1/devices/peer/unarchive")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+
+        let ingested = || {
+            pool.get()
+                .unwrap()
+                .query_row(
+                    "SELECT COUNT(*) FROM content_index WHERE cid = 'while-away'",
+                    [],
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap()
+                > 0
+        };
+        for _ in 0..100 {
+            if ingested() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(ingested());
+        iroh.shutdown().await.unwrap();
+    }
 }
@@ -1,49 +1,174 @@
+mod changelog;
+mod changes;
+mod compression;
 mod content;
 mod files;
+mod headers;
+mod host_check;
+mod logs;
 mod mesh;
+mod preflight;
+mod quarantine;
+mod storage;
+mod summary;
 
+pub use summary::{Summary, SummaryCounts};
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::State;
 use axum::routing::get;
-use axum::Router;
+use axum::{middleware, Json, Router};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "discovery")]
 use tokio::sync::watch;
+use tokio::sync::Mutex;
 
+use crate::catalog_sync::CatalogSync;
 use crate::config::Config;
+use crate::content_controls;
 use crate::db::DbPool;
+#[cfg(feature = "discovery")]
 use crate::discovery::MdnsDiscovery;
+use crate::error::AppResult;
+use crate::lifecycle::{self, CrashInfo};
+use crate::log_buffer::LogBuffer;
+use crate::log_level::LogLevel;
+use crate::membership;
 use crate::node::NodeIdentity;
+use crate::remote_cache::RemoteCache;
+use crate::update_check::{self, UpdateManifest, UpdateStatus};
 
 #[derive(Clone)]
 pub struct HttpState {
     pub config: Config,
     pub db: DbPool,
     pub node_identity: NodeIdentity,
+    pub started_at: Instant,
+    pub summary_cache: Arc<summary::SummaryCache>,
+    pub catalog: Option<Arc<Mutex<CatalogSync>>>,
+    pub remote_cache: Arc<RemoteCache>,
+    pub update_status: Arc<UpdateStatus>,
+    pub logs: Arc<LogBuffer>,
+    pub log_level: Arc<LogLevel>,
 }
 
 pub async fn run_serve(
     config: Config,
     pool: DbPool,
     node_identity: NodeIdentity,
+    data_dir: &Path,
+    catalog: Option<Arc<Mutex<CatalogSync>>>,
+    logs: Arc<LogBuffer>,
+    log_level: Arc<LogLevel>,
 ) -> anyhow::Result<()> {
-    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let hostname = config.server.advertised_hostname();
+    match (&hostname, config.server.advertised_hostname.is_empty()) {
+        (Some(name), true) => tracing::info!("Advertising as {name}.local (machine hostname)"),
+        (Some(name), false) => tracing::info!("Advertising as {name}.local (configured)"),
+        (None, _) => tracing::warn!("Could not read the machine's hostname"),
+    }
+
+    #[cfg(feature = "discovery")]
+    let (shutdown_tx, mdns) = {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mdns = MdnsDiscovery::start(
+            &node_identity.id,
+            &node_identity.name,
+            &config.server,
+            pool.clone(),
+            config.mesh.clone(),
+            shutdown_rx,
+        )?;
+        (shutdown_tx, mdns)
+    };
+    #[cfg(not(feature = "discovery"))]
+    tracing::info!("Built without mDNS discovery; peers won't be found automatically");
+
+    let csp = headers::csp_header(&config.server.content_security_policy)?;
+
+    // The machine's own name keeps working when a different one is advertised
+    let mut extra_hosts = config.server.allowed_hosts.clone();
+    if !config.server.advertised_hostname.is_empty() {
+        if let Some(machine) = hostname::get().ok().and_then(|h| h.into_string().ok()) {
+            extra_hosts.push(format!("{machine}.local"));
+            extra_hosts.push(machine);
+        }
+    }
+    let allowed_hosts = Arc::new(host_check::AllowedHosts::new(
+        &extra_hosts,
+        hostname.as_deref(),
+    ));
+    host_check::spawn_refresh(allowed_hosts.clone());
+
+    let remote_cache = RemoteCache::new(
+        data_dir.join("remote-cache"),
+        config.storage.remote_cache_bytes,
+        config.storage.remote_cache_max_item_bytes,
+    );
+    match remote_cache.sweep_partials() {
+        Ok(swept) if swept.files > 0 => tracing::info!(
+            "Removed {} partial cache files ({} bytes)",
+            swept.files,
+            swept.bytes
+        ),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Failed to sweep remote cache: {e}"),
+    }
+    // Purges of blocked nodes cut short by a restart
+    let purged = content_controls::resume_purges(&mut *pool.get()?)?;
+    if !purged.is_empty() {
+        tracing::info!("Removed {} entries from blocked nodes", purged.len());
+        remote_cache.remove(&pool, &purged).await?;
+    }
 
-    let mdns = MdnsDiscovery::start(
-        &node_identity.id,
-        &node_identity.name,
-        config.server.port,
-        pool.clone(),
-        shutdown_rx,
-    )?;
+    let update_status = Arc::new(UpdateStatus::default());
+    update_check::spawn_update_check(&config.updates, data_dir, update_status.clone());
+    if let Some(interval) = config.mesh.gossip_interval() {
+        membership::spawn_gossip(pool.clone(), config.mesh.clone(), interval);
+    }
 
     let state = HttpState {
         config: config.clone(),
         db: pool,
         node_identity,
+        started_at: Instant::now(),
+        summary_cache: Arc::new(summary::SummaryCache::default()),
+        catalog,
+        remote_cache: Arc::new(remote_cache),
+        update_status,
+        logs,
+        log_level,
     };
 
-    let app = Router::new()
+    let routes = Router::new()
         .route("/health", get(health))
         .merge(mesh::router())
         .merge(files::router())
         .merge(content::router())
+        .merge(summary::router())
+        .merge(storage::router())
+        .merge(logs::router())
+        .merge(changelog::router())
+        .merge(changes::router())
+        .merge(preflight::router())
+        .merge(quarantine::router());
+
+    let mut app = mount(routes, &config.server.base_path);
+    if config.server.compression {
+        app = app.layer(compression::layer());
+    }
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            allowed_hosts,
+            host_check::validate_host,
+        ))
+        .layer(middleware::from_fn_with_state(
+            csp,
+            headers::security_headers,
+        ))
         .with_state(state);
 
     let addr: std::net::SocketAddr =
@@ -51,14 +176,177 @@ pub async fn run_serve(
     tracing::info!("Salita daemon listening on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
 
-    let _ = shutdown_tx.send(true);
-    mdns.shutdown();
+    #[cfg(feature = "discovery")]
+    {
+        let _ = shutdown_tx.send(true);
+        mdns.shutdown();
+    }
 
     Ok(())
 }
 
-async fn health() -> &'static str {
-    "ok"
+/// Mount `routes` under the configured path prefix ("" for the root).
+fn mount<S>(routes: Router<S>, base_path: &str) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if base_path.is_empty() {
+        routes
+    } else {
+        Router::new().nest(base_path, routes)
+    }
+}
+
+/// Resolve on Ctrl-C or SIGTERM so the daemon can stop cleanly.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down");
+}
+
+/// Liveness, plus enough to tell a node that just restarted after a crash
+/// from one that has been up all along.
+#[derive(Debug, Serialize, Deserialize)]
+struct Health {
+    status: String,
+    uptime_secs: u64,
+    /// Most recent run that ended without a clean shutdown
+    last_crash: Option<CrashInfo>,
+    /// Newer release found by the update check
+    update_available: Option<UpdateManifest>,
+}
+
+async fn health(State(state): State<HttpState>) -> AppResult<Json<Health>> {
+    let last_crash = lifecycle::last_crash(&*state.db.get()?)?;
+    Ok(Json(Health {
+        status: "ok".to_string(),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        last_crash,
+        update_available: state.update_status.available(),
+    }))
+}
+
+#[cfg(test)]
+impl HttpState {
+    /// State around `pool` for exercising handlers, with defaults for the
+    /// rest and the remote cache under `cache_dir`.
+    pub(crate) fn for_tests(pool: DbPool, cache_dir: &Path) -> Self {
+        let (_, handle) =
+            tracing_subscriber::reload::Layer::new(tracing_subscriber::EnvFilter::new("info"));
+        Self {
+            config: Config::default(),
+            db: pool,
+            node_identity: NodeIdentity {
+                id: "node-1".to_string(),
+                name: "den".to_string(),
+                created_at: "2026-01-01 00:00:00".to_string(),
+            },
+            started_at: Instant::now(),
+            summary_cache: Arc::new(summary::SummaryCache::default()),
+            catalog: None,
+            remote_cache: Arc::new(RemoteCache::new(cache_dir.to_path_buf(), 1024, 1024)),
+            update_status: Arc::new(UpdateStatus::default()),
+            logs: Arc::new(LogBuffer::default()),
+            log_level: Arc::new(LogLevel::new(handle, "info".to_string(), None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    async fn status(app: Router, uri: &str) -> StatusCode {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    fn ok() -> Router {
+        Router::new().route("/health", get(|| async { "ok" }))
+    }
+
+    #[tokio::test]
+    async fn routes_are_mounted_under_base_path() {
+        let routes = ok();
+        let app = mount(routes, "/apps/salita");
+        assert_eq!(
+            status(app.clone(), "/apps/salita/health").await,
+            StatusCode::OK
+        );
+        assert_eq!(status(app, "/health").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn empty_base_path_mounts_at_root() {
+        let app = mount(ok(), "");
+        assert_eq!(status(app, "/health").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn health_reports_the_last_crash_and_updates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = crate::db::test_pool();
+        {
+            let conn = pool.get().unwrap();
+            lifecycle::record_start(&conn, "0.2.0", chrono::Utc::now()).unwrap();
+            lifecycle::record_start(&conn, "0.2.0", chrono::Utc::now()).unwrap();
+        }
+        let state = HttpState::for_tests(pool, tmp.path());
+        state.update_status.set(Some(UpdateManifest {
+            version: "9.0.0".to_string(),
+            notes_url: None,
+        }));
+        let app = Router::new()
+            .route("/health", get(health))
+            .with_state(state);
+
+        let req = Request::builder()
+            .uri("/health")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let health: Health = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(health.status, "ok");
+        assert!(health.last_crash.is_some());
+        assert_eq!(health.update_available.unwrap().version, "9.0.0");
+    }
 }
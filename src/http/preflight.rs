@@ -0,0 +1,115 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::HttpState;
+
+/// Where a client is connecting from, as far as this node can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Localhost,
+    Lan,
+    External,
+}
+
+/// This node's view of the caller's connection, so a client can tell it
+/// is on the wrong network before trying anything that needs the LAN.
+#[derive(Debug, Serialize)]
+struct Preflight {
+    client_ip: IpAddr,
+    origin: Origin,
+    /// Whether the caller is on this machine or its local network
+    same_network: bool,
+    server_time: DateTime<Utc>,
+    node_id: String,
+    version: &'static str,
+}
+
+/// Classify an address the way mDNS reachability works: loopback is this
+/// machine, private and link-local ranges are the LAN, and anything else
+/// arrived from outside.
+pub fn classify(ip: IpAddr) -> Origin {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    };
+    if ip.is_loopback() {
+        return Origin::Localhost;
+    }
+    let lan = match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_link_local(),
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            // fc00::/7 unique local, fe80::/10 link-local
+            (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
+    };
+    if lan {
+        Origin::Lan
+    } else {
+        Origin::External
+    }
+}
+
+/// GET /api/v1/connect/preflight
+async fn preflight(
+    State(state): State<HttpState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Json<Preflight> {
+    let origin = classify(addr.ip());
+    Json(Preflight {
+        client_ip: addr.ip(),
+        origin,
+        same_network: origin != Origin::External,
+        server_time: Utc::now(),
+        node_id: state.node_identity.id.clone(),
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new().route("/api/v1/connect/preflight", get(preflight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(ip: &str) -> Origin {
+        classify(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn loopback_is_localhost() {
+        assert_eq!(origin("127.0.0.1"), Origin::Localhost);
+        assert_eq!(origin("::1"), Origin::Localhost);
+        assert_eq!(origin("::ffff:127.0.0.1"), Origin::Localhost);
+    }
+
+    #[test]
+    fn private_and_link_local_ranges_are_lan() {
+        for ip in [
+            "192.168.1.20",
+            "10.0.0.5",
+            "172.16.4.1",
+            "169.254.10.10",
+            "::ffff:192.168.1.20",
+            "fd12:3456::1",
+            "fe80::1",
+        ] {
+            assert_eq!(origin(ip), Origin::Lan, "{ip}");
+        }
+    }
+
+    #[test]
+    fn public_and_carrier_addresses_are_external() {
+        for ip in ["8.8.8.8", "100.64.1.1", "172.32.0.1", "2001:db8::1"] {
+            assert_eq!(origin(ip), Origin::External, "{ip}");
+        }
+    }
+}
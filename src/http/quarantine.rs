@@ -0,0 +1,105 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use rusqlite::{params, OptionalExtension};
+
+use super::HttpState;
+use crate::error::{AppError, AppResult};
+use crate::indexer::IndexedEntry;
+use crate::scan::{self, FlaggedFile};
+
+/// GET /api/v1/quarantine — local files the content scanner flagged.
+async fn list(State(state): State<HttpState>) -> AppResult<Json<Vec<FlaggedFile>>> {
+    let conn = state.db.get()?;
+    Ok(Json(scan::list_flagged(&conn)?))
+}
+
+/// POST /api/v1/quarantine/{cid}/release — the scanner was wrong; make
+/// the file downloadable again and publish it to the mesh catalog.
+async fn release(State(state): State<HttpState>, Path(cid): Path<String>) -> AppResult<StatusCode> {
+    let entry = {
+        let conn = state.db.get()?;
+        if !scan::release(&conn, &cid)? {
+            return Err(AppError::NotFound);
+        }
+        conn.query_row(
+            "SELECT filename, dir, path, size, mime, file_type, modified
+             FROM content_index WHERE cid = ?1",
+            params![cid],
+            |row| {
+                Ok(IndexedEntry {
+                    cid: cid.clone(),
+                    filename: row.get(0)?,
+                    dir: row.get(1)?,
+                    path: row.get(2)?,
+                    size: row.get(3)?,
+                    mime: row.get(4)?,
+                    file_type: row.get(5)?,
+                    modified: row.get(6)?,
+                    thumbnail_bytes: None,
+                })
+            },
+        )?
+    };
+    tracing::info!("Released {}/{} from quarantine", entry.dir, entry.path);
+
+    if let Some(catalog) = state.catalog.clone() {
+        tokio::spawn(async move {
+            let result = catalog
+                .lock()
+                .await
+                .publish_entry(
+                    &entry.cid,
+                    &entry.filename,
+                    &entry.dir,
+                    &entry.path,
+                    entry.size,
+                    entry.mime.as_deref(),
+                    &entry.file_type,
+                    entry.modified.as_deref(),
+                    None,
+                )
+                .await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to publish released file {}: {e}", entry.cid);
+            }
+        });
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/v1/quarantine/{cid} — delete a flagged file from disk and
+/// from the index.
+async fn remove(State(state): State<HttpState>, Path(cid): Path<String>) -> AppResult<StatusCode> {
+    let conn = state.db.get()?;
+    let (dir, path): (String, String) = conn
+        .query_row(
+            "SELECT dir, path FROM content_index WHERE cid = ?1 AND is_local = 1 AND scan_status = ?2",
+            params![cid, scan::FLAGGED],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or(AppError::NotFound)?;
+
+    let base = state
+        .config
+        .resolve_directory(&dir)
+        .ok_or(AppError::NotFound)?;
+    let file_path = crate::files::resolve_path(&base, &path)?;
+    match std::fs::remove_file(&file_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    conn.execute("DELETE FROM content_index WHERE cid = ?1", params![cid])?;
+    tracing::info!("Deleted quarantined file {dir}/{path}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route("/api/v1/quarantine", get(list))
+        .route("/api/v1/quarantine/{cid}", delete(remove))
+        .route("/api/v1/quarantine/{cid}/release", post(release))
+}
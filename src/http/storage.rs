@@ -0,0 +1,315 @@
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+
+use super::HttpState;
+use crate::catalog_sync::GcReport;
+use crate::error::{AppError, AppResult};
+use crate::integrity::{self, IntegrityStats, VerifyBudget, VerifyReport};
+
+/// Number of files listed in `StorageOverview::largest`.
+const LARGEST_LIMIT: i64 = 10;
+
+/// Where the catalog's bytes live, for answering "what is using my disk".
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageOverview {
+    pub nodes: Vec<NodeStorage>,
+    pub by_type: Vec<TypeStorage>,
+    pub largest: Vec<LargestFile>,
+    pub remote_cache_bytes: i64,
+    pub remote_cache_budget: u64,
+    /// Thumbnail blob bytes no local file references, released by the
+    /// next GC pass. `None` when catalog sync isn't running.
+    pub orphaned_bytes: Option<u64>,
+    pub integrity: IntegrityStats,
+}
+
+/// Files and bytes a node contributes to the catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeStorage {
+    pub node_id: Option<String>,
+    pub node_name: Option<String>,
+    pub is_local: bool,
+    pub files: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeStorage {
+    pub file_type: String,
+    pub files: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LargestFile {
+    pub cid: String,
+    pub filename: String,
+    pub size: i64,
+    pub file_type: String,
+    pub node_id: Option<String>,
+    pub is_local: bool,
+    /// Whether a remote file also has a copy in the local cache.
+    pub cached: bool,
+}
+
+/// The database side of the overview: one grouped pass per breakdown.
+pub fn compute_overview(
+    conn: &rusqlite::Connection,
+    remote_cache_budget: u64,
+) -> AppResult<StorageOverview> {
+    // Local rows carry no origin_node, so attribute them to this node
+    let mut stmt = conn.prepare(
+        "SELECT n.node_id, d.name, n.is_local, n.files, n.bytes
+         FROM (
+             SELECT CASE WHEN is_local = 1 THEN (SELECT node_id FROM current_node)
+                         ELSE origin_node END AS node_id,
+                    is_local, COUNT(*) AS files, COALESCE(SUM(size), 0) AS bytes
+             FROM content_index
+             GROUP BY 1, 2
+         ) n
+         LEFT JOIN devices d ON d.id = n.node_id
+         ORDER BY n.bytes DESC",
+    )?;
+    let nodes = stmt
+        .query_map([], |row| {
+            Ok(NodeStorage {
+                node_id: row.get(0)?,
+                node_name: row.get(1)?,
+                is_local: row.get(2)?,
+                files: row.get(3)?,
+                bytes: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT file_type, COUNT(*), COALESCE(SUM(size), 0)
+         FROM content_index
+         GROUP BY file_type
+         ORDER BY 3 DESC",
+    )?;
+    let by_type = stmt
+        .query_map([], |row| {
+            Ok(TypeStorage {
+                file_type: row.get(0)?,
+                files: row.get(1)?,
+                bytes: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT c.cid, c.filename, c.size, c.file_type,
+                CASE WHEN c.is_local = 1 THEN (SELECT node_id FROM current_node)
+                     ELSE c.origin_node END,
+                c.is_local, r.cid IS NOT NULL
+         FROM content_index c
+         LEFT JOIN remote_cache r ON r.cid = c.cid
+         ORDER BY c.size DESC, c.cid
+         LIMIT ?1",
+    )?;
+    let largest = stmt
+        .query_map([LARGEST_LIMIT], |row| {
+            Ok(LargestFile {
+                cid: row.get(0)?,
+                filename: row.get(1)?,
+                size: row.get(2)?,
+                file_type: row.get(3)?,
+                node_id: row.get(4)?,
+                is_local: row.get(5)?,
+                cached: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let remote_cache_bytes: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(size), 0) FROM remote_cache",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(StorageOverview {
+        nodes,
+        by_type,
+        largest,
+        remote_cache_bytes,
+        remote_cache_budget,
+        orphaned_bytes: None,
+        integrity: integrity::stats(conn)?,
+    })
+}
+
+async fn get_overview(State(state): State<HttpState>) -> AppResult<Json<StorageOverview>> {
+    let pool = state.db.clone();
+    let budget = state.config.storage.remote_cache_bytes;
+    let mut overview = tokio::task::spawn_blocking(move || {
+        let conn = pool.get()?;
+        compute_overview(&conn, budget)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Storage overview task error: {e}")))??;
+
+    if let Some(catalog) = &state.catalog {
+        overview.orphaned_bytes = Some(
+            catalog
+                .lock()
+                .await
+                .orphaned_bytes()
+                .await
+                .map_err(|e| AppError::Internal(format!("Blob scan failed: {e}")))?,
+        );
+    }
+
+    Ok(Json(overview))
+}
+
+/// Run a blob GC pass now instead of waiting for the scheduled one.
+async fn run_gc(State(state): State<HttpState>) -> AppResult<Json<GcReport>> {
+    let catalog = state
+        .catalog
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest("Catalog sync is not running".into()))?;
+
+    let report = catalog
+        .lock()
+        .await
+        .gc()
+        .await
+        .map_err(|e| AppError::Internal(format!("Blob GC failed: {e}")))?;
+    Ok(Json(report))
+}
+
+/// Verify the next batch of local files now instead of waiting for the
+/// scheduled run.
+async fn run_verify(State(state): State<HttpState>) -> AppResult<Json<VerifyReport>> {
+    let pool = state.db.clone();
+    let config = state.config.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let budget = VerifyBudget::from_config(&config.storage);
+        integrity::verify_batch(&pool, &config, budget)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Verification task error: {e}")))?
+    .map_err(|e| AppError::Internal(format!("Verification failed: {e}")))?;
+    Ok(Json(report))
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new()
+        .route("/api/v1/storage", get(get_overview))
+        .route("/api/v1/storage/gc", post(run_gc))
+        .route("/api/v1/storage/verify", post(run_verify))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, DbPool};
+    use rusqlite::params;
+
+    fn seeded_pool() -> DbPool {
+        let pool = db::test_pool();
+
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO current_node (node_id) VALUES ('me');
+             INSERT INTO devices (id, name, is_self) VALUES ('me', 'laptop', 1);
+             INSERT INTO devices (id, name) VALUES ('peer', 'nas');",
+        )
+        .unwrap();
+        for (cid, size, file_type, origin, is_local) in [
+            ("a", 100, "image", None, 1),
+            ("b", 300, "video", None, 1),
+            ("c", 50, "image", Some("peer"), 0),
+            ("d", 900, "other", Some("peer"), 0),
+        ] {
+            conn.execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, file_type, origin_node, is_local)
+                 VALUES (?1, 'd', ?1, ?1, ?2, ?3, ?4, ?5)",
+                params![cid, size, file_type, origin, is_local],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO remote_cache (cid, size, content_type) VALUES ('d', 900, 'text/plain')",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+        pool
+    }
+
+    #[test]
+    fn nodes_include_local_files_under_self() {
+        let pool = seeded_pool();
+        let overview = compute_overview(&pool.get().unwrap(), 1024).unwrap();
+        assert_eq!(
+            overview.nodes,
+            vec![
+                NodeStorage {
+                    node_id: Some("peer".into()),
+                    node_name: Some("nas".into()),
+                    is_local: false,
+                    files: 2,
+                    bytes: 950,
+                },
+                NodeStorage {
+                    node_id: Some("me".into()),
+                    node_name: Some("laptop".into()),
+                    is_local: true,
+                    files: 2,
+                    bytes: 400,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_by_type_and_cache_usage() {
+        let pool = seeded_pool();
+        let overview = compute_overview(&pool.get().unwrap(), 1024).unwrap();
+        let by_type: Vec<_> = overview
+            .by_type
+            .iter()
+            .map(|t| (t.file_type.as_str(), t.files, t.bytes))
+            .collect();
+        assert_eq!(
+            by_type,
+            vec![("other", 1, 900), ("video", 1, 300), ("image", 2, 150)]
+        );
+        assert_eq!(overview.remote_cache_bytes, 900);
+        assert_eq!(overview.remote_cache_budget, 1024);
+        assert_eq!(overview.orphaned_bytes, None);
+    }
+
+    #[test]
+    fn largest_files_flag_cached_copies() {
+        let pool = seeded_pool();
+        let overview = compute_overview(&pool.get().unwrap(), 1024).unwrap();
+        let largest: Vec<_> = overview
+            .largest
+            .iter()
+            .map(|f| (f.cid.as_str(), f.node_id.as_deref(), f.cached))
+            .collect();
+        assert_eq!(
+            largest,
+            vec![
+                ("d", Some("peer"), true),
+                ("b", Some("me"), false),
+                ("a", Some("me"), false),
+                ("c", Some("peer"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_db_has_empty_overview() {
+        let pool = db::test_pool();
+        let overview = compute_overview(&pool.get().unwrap(), 0).unwrap();
+        assert!(overview.nodes.is_empty());
+        assert!(overview.largest.is_empty());
+        assert_eq!(overview.remote_cache_bytes, 0);
+    }
+}
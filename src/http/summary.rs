@@ -0,0 +1,231 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::HttpState;
+use crate::error::{AppError, AppResult};
+use crate::lifecycle::{self, CrashInfo};
+use crate::update_check::UpdateManifest;
+
+/// How long aggregate counts are reused before hitting the database again.
+const SUMMARY_TTL: Duration = Duration::from_secs(30);
+
+/// Compact node snapshot for widgets and status displays. Also what
+/// `PeerClient::summary` decodes, so both sides share one definition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub node_id: String,
+    pub node_name: String,
+    pub version: String,
+    pub uptime_secs: u64,
+    /// Most recent run that ended without a clean shutdown
+    pub last_crash: Option<CrashInfo>,
+    /// Newer release found by the update check
+    pub update_available: Option<UpdateManifest>,
+    #[serde(flatten)]
+    pub counts: SummaryCounts,
+}
+
+/// Aggregate counts, cached for `SUMMARY_TTL`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SummaryCounts {
+    pub devices_online: i64,
+    pub devices_offline: i64,
+    pub local_files: i64,
+    pub remote_files: i64,
+    pub indexed_today: i64,
+    pub local_bytes: i64,
+}
+
+/// Caches `SummaryCounts` so frequent polling stays cheap.
+#[derive(Default)]
+pub struct SummaryCache {
+    inner: Mutex<Option<(Instant, SummaryCounts)>>,
+}
+
+impl SummaryCache {
+    /// Return the cached counts if they are younger than `SUMMARY_TTL`,
+    /// otherwise recompute them. The lock is held while computing so
+    /// concurrent pollers don't all hit the database at once.
+    pub fn get_or_compute<F>(&self, now: Instant, compute: F) -> AppResult<SummaryCounts>
+    where
+        F: FnOnce() -> AppResult<SummaryCounts>,
+    {
+        let mut guard = self
+            .inner
+            .lock()
+            .map_err(|_| AppError::Internal("Summary cache poisoned".into()))?;
+
+        if let Some((computed_at, counts)) = guard.as_ref() {
+            if now.duration_since(*computed_at) < SUMMARY_TTL {
+                return Ok(counts.clone());
+            }
+        }
+
+        let counts = compute()?;
+        *guard = Some((now, counts.clone()));
+        Ok(counts)
+    }
+}
+
+/// Compute the aggregate counts with one query per table.
+pub fn compute_counts(conn: &rusqlite::Connection) -> AppResult<SummaryCounts> {
+    let (devices_online, devices_offline): (i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(status = 'online'), 0), COALESCE(SUM(status != 'online'), 0)
+         FROM devices WHERE archived_at IS NULL",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let (local_files, remote_files, indexed_today, local_bytes): (i64, i64, i64, i64) = conn
+        .query_row(
+            "SELECT COALESCE(SUM(is_local = 1), 0),
+                    COALESCE(SUM(is_local = 0), 0),
+                    COALESCE(SUM(indexed_at >= date('now')), 0),
+                    COALESCE(SUM(CASE WHEN is_local = 1 THEN size ELSE 0 END), 0)
+             FROM content_index",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+    Ok(SummaryCounts {
+        devices_online,
+        devices_offline,
+        local_files,
+        remote_files,
+        indexed_today,
+        local_bytes,
+    })
+}
+
+async fn get_summary(State(state): State<HttpState>) -> AppResult<Json<Summary>> {
+    let pool = state.db.clone();
+    let cache = state.summary_cache.clone();
+
+    let (counts, last_crash) = tokio::task::spawn_blocking(move || {
+        let counts = cache.get_or_compute(Instant::now(), || {
+            let conn = pool.get()?;
+            compute_counts(&conn)
+        })?;
+        let last_crash = lifecycle::last_crash(&*pool.get()?)?;
+        Ok::<_, AppError>((counts, last_crash))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Summary task error: {e}")))??;
+
+    Ok(Json(Summary {
+        node_id: state.node_identity.id.clone(),
+        node_name: state.node_identity.name.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        last_crash,
+        update_available: state.update_status.available(),
+        counts,
+    }))
+}
+
+pub fn router() -> Router<HttpState> {
+    Router::new().route("/api/v1/summary", get(get_summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{self, DbPool};
+    use rusqlite::params;
+    use std::cell::Cell;
+
+    fn seeded_pool() -> DbPool {
+        let pool = db::test_pool();
+
+        let conn = pool.get().unwrap();
+        for (id, status) in [("a", "online"), ("b", "online"), ("c", "offline")] {
+            conn.execute(
+                "INSERT INTO devices (id, name, status) VALUES (?1, ?1, ?2)",
+                params![id, status],
+            )
+            .unwrap();
+        }
+        for (cid, size, is_local, indexed_at) in [
+            ("c1", 100, 1, "+0 days"),
+            ("c2", 50, 1, "-3 days"),
+            ("c3", 999, 0, "+0 days"),
+        ] {
+            conn.execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, is_local, indexed_at)
+                 VALUES (?1, 'd', ?1, ?1, ?2, ?3, datetime('now', ?4))",
+                params![cid, size, is_local, indexed_at],
+            )
+            .unwrap();
+        }
+        drop(conn);
+        pool
+    }
+
+    #[test]
+    fn compute_counts_matches_seeded_db() {
+        let pool = seeded_pool();
+        let counts = compute_counts(&pool.get().unwrap()).unwrap();
+        assert_eq!(counts.devices_online, 2);
+        assert_eq!(counts.devices_offline, 1);
+        assert_eq!(counts.local_files, 2);
+        assert_eq!(counts.remote_files, 1);
+        assert_eq!(counts.indexed_today, 2);
+        assert_eq!(counts.local_bytes, 150);
+    }
+
+    /// `PeerClient::summary` decodes what this node's own handler serves.
+    #[tokio::test]
+    async fn peer_client_reads_the_served_summary() {
+        let tmp = tempfile::tempdir().unwrap();
+        let app = Router::new()
+            .nest("/salita", router())
+            .with_state(HttpState::for_tests(seeded_pool(), tmp.path()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let fetched = crate::peer_client::PeerClient::new()
+            .summary("127.0.0.1", port, "/salita")
+            .await
+            .unwrap();
+        assert_eq!(fetched.node_name, "den");
+        assert_eq!(fetched.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(fetched.last_crash, None);
+        assert_eq!(fetched.counts.devices_online, 2);
+        assert_eq!(fetched.counts.local_files, 2);
+        assert_eq!(fetched.counts.local_bytes, 150);
+    }
+
+    #[test]
+    fn compute_counts_on_empty_db() {
+        let pool = db::test_pool();
+        let counts = compute_counts(&pool.get().unwrap()).unwrap();
+        assert_eq!(counts.devices_online, 0);
+        assert_eq!(counts.local_bytes, 0);
+    }
+
+    #[test]
+    fn cache_reuses_counts_within_ttl() {
+        let cache = SummaryCache::default();
+        let calls = Cell::new(0);
+        let compute = || {
+            calls.set(calls.get() + 1);
+            Ok(SummaryCounts::default())
+        };
+
+        let start = Instant::now();
+        cache.get_or_compute(start, compute).unwrap();
+        cache
+            .get_or_compute(start + Duration::from_secs(29), compute)
+            .unwrap();
+        assert_eq!(calls.get(), 1);
+
+        cache.get_or_compute(start + SUMMARY_TTL, compute).unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use rusqlite::params;
+
+use crate::db::DbPool;
+use crate::indexer::{classify_file, hash_file};
+use crate::tempfiles;
+
+/// Whether imported files are left in place or removed from the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    Copy,
+    Move,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub source: PathBuf,
+    /// Shared directory the files are placed in; the indexer picks them up
+    pub dest: PathBuf,
+    pub recursive: bool,
+    pub mode: ImportMode,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub duplicates: u64,
+    pub unsupported: u64,
+    pub failed: u64,
+    pub bytes: u64,
+}
+
+/// Copy or move the photos and videos under `opts.source` into a shared
+/// directory, keeping their relative paths. Files whose content is
+/// already indexed locally, or already at the destination, are skipped,
+/// so an interrupted import can simply be run again.
+pub fn import_media(pool: &DbPool, opts: &ImportOptions) -> anyhow::Result<ImportReport> {
+    if !opts.source.is_dir() {
+        anyhow::bail!("Not a directory: {}", opts.source.display());
+    }
+    if !opts.dest.is_dir() {
+        anyhow::bail!("Destination does not exist: {}", opts.dest.display());
+    }
+    if opts.dest.starts_with(&opts.source) {
+        anyhow::bail!("Destination must not be inside the source directory");
+    }
+
+    let mut files = Vec::new();
+    collect_files(&opts.source, opts.recursive, &mut files)?;
+
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let total = files.len();
+    for (i, path) in files.into_iter().enumerate() {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if classify_file(&name) == "other" {
+            report.unsupported += 1;
+            continue;
+        }
+
+        match import_file(pool, opts, &path, &mut seen) {
+            Ok(Some(bytes)) => {
+                report.imported += 1;
+                report.bytes += bytes;
+            }
+            Ok(None) => report.duplicates += 1,
+            Err(e) => {
+                report.failed += 1;
+                tracing::warn!("Failed to import {}: {e}", path.display());
+            }
+        }
+
+        if (i + 1) % 100 == 0 {
+            tracing::info!("Import progress: {}/{total} files", i + 1);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Import one file, returning its size, or `None` if it was a duplicate.
+fn import_file(
+    pool: &DbPool,
+    opts: &ImportOptions,
+    path: &Path,
+    seen: &mut HashSet<String>,
+) -> anyhow::Result<Option<u64>> {
+    let cid = hash_file(path)?;
+    if !seen.insert(cid.clone()) || is_indexed(pool, &cid)? {
+        return Ok(None);
+    }
+
+    let rel = path.strip_prefix(&opts.source)?;
+    let Some(target) = free_target(&opts.dest.join(rel), &cid)? else {
+        // An earlier, interrupted run already placed this file
+        return Ok(None);
+    };
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let size = std::fs::metadata(path)?.len();
+    match opts.mode {
+        ImportMode::Copy => copy_preserving_mtime(path, &target)?,
+        ImportMode::Move => {
+            // rename fails across filesystems; fall back to copy + delete
+            if std::fs::rename(path, &target).is_err() {
+                copy_preserving_mtime(path, &target)?;
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(Some(size))
+}
+
+fn is_indexed(pool: &DbPool, cid: &str) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+    let found = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM content_index WHERE cid = ?1 AND is_local = 1)",
+        params![cid],
+        |row| row.get(0),
+    )?;
+    Ok(found)
+}
+
+/// Pick where a file with hash `cid` should go: `wanted` if it's free,
+/// otherwise `name-1.ext`, `name-2.ext`, ... Returns `None` if a file
+/// with the same content is already at one of those paths.
+fn free_target(wanted: &Path, cid: &str) -> anyhow::Result<Option<PathBuf>> {
+    let stem = wanted.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = wanted
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut candidate = wanted.to_path_buf();
+    for n in 1.. {
+        if !candidate.exists() {
+            return Ok(Some(candidate));
+        }
+        if hash_file(&candidate)? == cid {
+            return Ok(None);
+        }
+        candidate = wanted.with_file_name(format!("{stem}-{n}{ext}"));
+    }
+    unreachable!()
+}
+
+/// Copy through a temporary name so an interrupted copy never leaves a
+/// truncated file under the real name, and keep the mtime the indexer
+/// falls back on for the capture date.
+fn copy_preserving_mtime(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let tmp = tempfiles::temp_path(to);
+    std::fs::copy(from, &tmp)?;
+    if let Ok(mtime) = std::fs::metadata(from).and_then(|m| m.modified()) {
+        std::fs::File::options()
+            .write(true)
+            .open(&tmp)?
+            .set_modified(mtime)?;
+    }
+    std::fs::rename(&tmp, to)?;
+    Ok(())
+}
+
+/// List regular files, skipping hidden ones like the indexer does.
+fn collect_files(dir: &Path, recursive: bool, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if recursive {
+                collect_files(&path, recursive, out)?;
+            }
+        } else if file_type.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    struct Fixture {
+        _tmp: tempfile::TempDir,
+        source: PathBuf,
+        dest: PathBuf,
+    }
+
+    fn fixture(files: &[(&str, &str)]) -> Fixture {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("Pictures");
+        let dest = tmp.path().join("shared");
+        std::fs::create_dir_all(&dest).unwrap();
+        for (path, contents) in files {
+            let path = source.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Fixture {
+            _tmp: tmp,
+            source,
+            dest,
+        }
+    }
+
+    fn options(f: &Fixture, recursive: bool, mode: ImportMode) -> ImportOptions {
+        ImportOptions {
+            source: f.source.clone(),
+            dest: f.dest.clone(),
+            recursive,
+            mode,
+        }
+    }
+
+    #[test]
+    fn copies_media_and_skips_unsupported() {
+        let f = fixture(&[
+            ("a.jpg", "a"),
+            ("notes.txt", "n"),
+            ("2019/b.mov", "b"),
+            (".hidden.jpg", "h"),
+        ]);
+        let report = import_media(&test_pool(), &options(&f, true, ImportMode::Copy)).unwrap();
+
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.unsupported, 1);
+        assert_eq!(report.bytes, 2);
+        assert_eq!(std::fs::read(f.dest.join("a.jpg")).unwrap(), b"a");
+        assert_eq!(std::fs::read(f.dest.join("2019/b.mov")).unwrap(), b"b");
+        assert!(!f.dest.join("notes.txt").exists());
+        assert!(f.source.join("a.jpg").exists());
+    }
+
+    #[test]
+    fn non_recursive_stays_at_top_level() {
+        let f = fixture(&[("a.jpg", "a"), ("2019/b.jpg", "b")]);
+        let report = import_media(&test_pool(), &options(&f, false, ImportMode::Copy)).unwrap();
+        assert_eq!(report.imported, 1);
+        assert!(!f.dest.join("2019").exists());
+    }
+
+    #[test]
+    fn move_removes_imported_sources() {
+        let f = fixture(&[("a.jpg", "a"), ("notes.txt", "n")]);
+        import_media(&test_pool(), &options(&f, true, ImportMode::Move)).unwrap();
+        assert!(!f.source.join("a.jpg").exists());
+        assert!(f.source.join("notes.txt").exists());
+        assert!(f.dest.join("a.jpg").exists());
+    }
+
+    #[test]
+    fn duplicates_are_skipped_and_rerun_is_idempotent() {
+        let f = fixture(&[("a.jpg", "same"), ("copy/a2.jpg", "same"), ("b.jpg", "b")]);
+        let pool = test_pool();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size) VALUES (?1, 'd', 'x.jpg', 'x.jpg', 1)",
+                params![blake3::hash(b"b").to_hex().to_string()],
+            )
+            .unwrap();
+
+        let opts = options(&f, true, ImportMode::Copy);
+        let report = import_media(&pool, &opts).unwrap();
+        assert_eq!((report.imported, report.duplicates), (1, 2));
+        assert!(!f.dest.join("b.jpg").exists());
+
+        let report = import_media(&pool, &opts).unwrap();
+        assert_eq!((report.imported, report.duplicates), (0, 3));
+    }
+
+    #[test]
+    fn name_clash_with_different_content_gets_a_suffix() {
+        let f = fixture(&[("a.jpg", "new")]);
+        std::fs::write(f.dest.join("a.jpg"), "old").unwrap();
+        import_media(&test_pool(), &options(&f, true, ImportMode::Copy)).unwrap();
+        assert_eq!(std::fs::read(f.dest.join("a.jpg")).unwrap(), b"old");
+        assert_eq!(std::fs::read(f.dest.join("a-1.jpg")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn destination_inside_source_is_refused() {
+        let f = fixture(&[("a.jpg", "a")]);
+        let opts = ImportOptions {
+            dest: f.source.clone(),
+            ..options(&f, true, ImportMode::Copy)
+        };
+        assert!(import_media(&test_pool(), &opts).is_err());
+    }
+}
@@ -6,9 +6,13 @@ use rusqlite::params;
 use tokio::sync::Mutex;
 
 use crate::catalog_sync::CatalogSync;
+use crate::changes;
 use crate::config::Config;
 use crate::db::DbPool;
+use crate::scan::{self, Scanner};
+#[cfg(feature = "media")]
 use crate::thumbnail;
+use crate::tombstones;
 
 const RAW_EXTENSIONS: &[&str] = &[
     "cr2", "cr3", "nef", "arw", "orf", "rw2", "dng", "raf", "pef", "srw", "x3f", "3fr", "mrw",
@@ -84,6 +88,7 @@ pub fn spawn_indexer(
                                 tracing::debug!("Failed to publish catalog entry: {e}");
                             }
                         }
+                        CatalogSync::retract_stale(cat, &pool).await;
                     }
                 }
                 Err(e) => {
@@ -106,6 +111,8 @@ fn run_index_cycle(
     let mut file_count = 0u64;
     let mut thumb_count = 0u64;
     let mut to_publish = Vec::new();
+    let scanner = Scanner::from_config(&config.storage);
+    let mut mounted = Vec::new();
 
     for dir_config in &config.directories {
         let base = config.resolve_directory(&dir_config.label);
@@ -122,10 +129,30 @@ fn run_index_cycle(
         };
 
         let (f, t, mut entries) =
-            index_directory(pool, &dir_config.label, &base, &base);
+            index_directory(pool, scanner.as_ref(), &dir_config.label, &base, &base);
         file_count += f;
         thumb_count += t;
         to_publish.append(&mut entries);
+        mounted.push((dir_config.label.as_str(), base));
+    }
+
+    // After every directory is indexed, so a file moved between them is
+    // already at its new location
+    for (label, base) in mounted {
+        if let Err(e) = remove_vanished_files(pool, label, &base) {
+            tracing::warn!("Failed to check {label} for removed files: {e}");
+        }
+    }
+
+    // Keep the change journal to its retention period
+    match pool
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|conn| Ok(changes::prune(&conn)?))
+    {
+        Ok(0) => {}
+        Ok(pruned) => tracing::debug!("Pruned {pruned} expired changes"),
+        Err(e) => tracing::warn!("Failed to prune the change journal: {e}"),
     }
 
     let elapsed = start.elapsed();
@@ -139,9 +166,35 @@ fn run_index_cycle(
     (file_count, thumb_count, to_publish)
 }
 
+/// Forget local files of a mounted directory that are no longer on disk,
+/// retracting them from the catalog. Returns how many were removed.
+fn remove_vanished_files(pool: &DbPool, dir_label: &str, base: &Path) -> anyhow::Result<u64> {
+    let paths: Vec<String> = {
+        let conn = pool.get()?;
+        let mut stmt =
+            conn.prepare("SELECT path FROM content_index WHERE dir = ?1 AND is_local = 1")?;
+        let paths = stmt.query_map(params![dir_label], |row| row.get(0))?;
+        paths.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut removed = 0u64;
+    for path in paths {
+        if base.join(&path).is_file() {
+            continue;
+        }
+        let mut conn = pool.get()?;
+        if tombstones::remove_vanished(&mut conn, dir_label, &path)? {
+            tracing::info!("{dir_label}/{path} is gone; removed it from the index");
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
 /// Recursively index a directory, returning (files_indexed, thumbnails_generated, entries).
 fn index_directory(
     pool: &DbPool,
+    scanner: Option<&Scanner>,
     dir_label: &str,
     base: &Path,
     current: &Path,
@@ -168,8 +221,7 @@ fn index_directory(
         }
 
         if path.is_dir() {
-            let (f, t, mut sub_entries) =
-                index_directory(pool, dir_label, base, &path);
+            let (f, t, mut sub_entries) = index_directory(pool, scanner, dir_label, base, &path);
             file_count += f;
             thumb_count += t;
             to_publish.append(&mut sub_entries);
@@ -182,7 +234,7 @@ fn index_directory(
 
         // Background indexer: metadata only (hash + EXIF), NO thumbnails.
         // Thumbnails are generated on-demand when someone browses.
-        match index_file_metadata_only(pool, dir_label, base, &path) {
+        match index_file_metadata_only(pool, scanner, dir_label, base, &path) {
             Ok(Some(indexed)) => {
                 file_count += 1;
                 to_publish.push(indexed);
@@ -206,6 +258,7 @@ fn index_directory(
 /// Used by the background indexer to avoid CPU spikes.
 fn index_file_metadata_only(
     pool: &DbPool,
+    scanner: Option<&Scanner>,
     dir_label: &str,
     base: &Path,
     path: &Path,
@@ -241,18 +294,19 @@ fn index_file_metadata_only(
         .first()
         .map(|m| m.to_string());
 
-    // Check if already indexed
-    let conn = pool.get()?;
-    let existing: Option<(String, Option<String>)> = conn
+    // Check if already indexed (and scanned, if a scan was cut short)
+    let existing: Option<(String, Option<String>, String)> = pool
+        .get()?
         .query_row(
-            "SELECT cid, modified FROM content_index WHERE dir = ?1 AND path = ?2",
+            "SELECT cid, modified, scan_status FROM content_index WHERE dir = ?1 AND path = ?2",
             params![dir_label, rel_path],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
         )
         .ok();
 
-    if let Some((_existing_cid, existing_modified)) = &existing {
-        if existing_modified.as_deref() == modified.as_deref() {
+    if let Some((_existing_cid, existing_modified, scan_status)) = &existing {
+        if existing_modified.as_deref() == modified.as_deref() && scan_status != scan::PENDING_SCAN
+        {
             return Ok(None);
         }
     }
@@ -260,29 +314,7 @@ fn index_file_metadata_only(
     // Compute BLAKE3 hash
     let cid = hash_file(path)?;
 
-    conn.execute(
-        "INSERT INTO content_index (cid, dir, path, filename, size, mime, file_type, modified, is_local)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 1)
-         ON CONFLICT(cid) DO UPDATE SET
-           dir = excluded.dir,
-           path = excluded.path,
-           filename = excluded.filename,
-           size = excluded.size,
-           mime = excluded.mime,
-           file_type = excluded.file_type,
-           modified = excluded.modified,
-           is_local = 1,
-           indexed_at = datetime('now')
-         ",
-        params![cid, dir_label, rel_path, filename, size, mime, file_type, modified],
-    )?;
-
-    conn.execute(
-        "DELETE FROM content_index WHERE dir = ?1 AND path = ?2 AND cid != ?3",
-        params![dir_label, rel_path, cid],
-    )?;
-
-    Ok(Some(IndexedEntry {
+    let entry = IndexedEntry {
         cid,
         filename,
         dir: dir_label.to_string(),
@@ -292,12 +324,18 @@ fn index_file_metadata_only(
         file_type: file_type.to_string(),
         modified,
         thumbnail_bytes: None, // No thumbnail in background pass
-    }))
+    };
+    if !upsert_and_scan(pool, scanner, &entry, path)? {
+        return Ok(None);
+    }
+
+    Ok(Some(entry))
 }
 
 /// Full index: hash + EXIF + thumbnail. Used by on-demand indexing endpoint.
 pub fn index_file(
     pool: &DbPool,
+    scanner: Option<&Scanner>,
     dir_label: &str,
     base: &Path,
     path: &Path,
@@ -336,16 +374,22 @@ pub fn index_file(
 
     // Check if already indexed with same modified time
     let conn = pool.get()?;
-    let existing: Option<(String, Option<String>)> = conn
+    let existing: Option<(String, Option<String>, String)> = conn
         .query_row(
-            "SELECT cid, modified FROM content_index WHERE dir = ?1 AND path = ?2",
+            "SELECT cid, modified, scan_status FROM content_index WHERE dir = ?1 AND path = ?2",
             params![dir_label, rel_path],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
         )
         .ok();
 
-    if let Some((existing_cid, existing_modified)) = &existing {
-        if existing_modified.as_deref() == modified.as_deref() {
+    if let Some((existing_cid, existing_modified, scan_status)) = &existing {
+        if existing_modified.as_deref() == modified.as_deref()
+            && (scan_status == scan::FLAGGED || scan_status == scan::CORRUPT)
+        {
+            // Quarantined: no thumbnail until it's released
+            return Ok(None);
+        }
+        if existing_modified.as_deref() == modified.as_deref() && scan_status == scan::CLEAN {
             // Already indexed and unchanged — check if thumbnail exists for image/raw
             if file_type == "image" || file_type == "raw" {
                 let has_thumb: bool = conn
@@ -366,48 +410,41 @@ pub fn index_file(
         }
     }
 
+    drop(conn);
+
     // Compute BLAKE3 hash
     let cid = hash_file(path)?;
 
-    // Upsert content_index
-    conn.execute(
-        "INSERT INTO content_index (cid, dir, path, filename, size, mime, file_type, modified, is_local)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 1)
-         ON CONFLICT(cid) DO UPDATE SET
-           dir = excluded.dir,
-           path = excluded.path,
-           filename = excluded.filename,
-           size = excluded.size,
-           mime = excluded.mime,
-           file_type = excluded.file_type,
-           modified = excluded.modified,
-           is_local = 1,
-           indexed_at = datetime('now')
-         ",
-        params![cid, dir_label, rel_path, filename, size, mime, file_type, modified],
-    )?;
-
-    // Also handle dir+path conflict (file moved but same path)
-    conn.execute(
-        "DELETE FROM content_index WHERE dir = ?1 AND path = ?2 AND cid != ?3",
-        params![dir_label, rel_path, cid],
-    )?;
+    let mut entry = IndexedEntry {
+        cid,
+        filename,
+        dir: dir_label.to_string(),
+        path: rel_path,
+        size,
+        mime,
+        file_type: file_type.to_string(),
+        modified,
+        thumbnail_bytes: None,
+    };
+    if !upsert_and_scan(pool, scanner, &entry, path)? {
+        return Ok(None);
+    }
+    let conn = pool.get()?;
 
     // Generate thumbnail for image/raw files
-    let mut thumbnail_bytes = None;
     if file_type == "image" || file_type == "raw" {
         let has_thumb: bool = conn
             .query_row(
                 "SELECT COUNT(*) > 0 FROM content_thumbnails WHERE cid = ?1",
-                params![cid],
+                params![entry.cid],
                 |row| row.get(0),
             )
             .unwrap_or(false);
 
         if !has_thumb {
-            match generate_and_store_thumbnail(&conn, &cid, file_type, path) {
+            match generate_and_store_thumbnail(&conn, &entry.cid, file_type, path) {
                 Ok(bytes) => {
-                    thumbnail_bytes = Some(bytes);
+                    entry.thumbnail_bytes = Some(bytes);
                 }
                 Err(e) => {
                     tracing::debug!(
@@ -419,21 +456,84 @@ pub fn index_file(
         }
     }
 
-    Ok(Some(IndexedEntry {
-        cid,
-        filename,
-        dir: dir_label.to_string(),
-        path: rel_path,
-        size,
-        mime,
-        file_type: file_type.to_string(),
-        modified,
-        thumbnail_bytes,
-    }))
+    Ok(Some(entry))
+}
+
+/// Record a local file and, with a scanner configured, scan it. The entry
+/// is stored as pending first so it can't be downloaded mid-scan. No
+/// pooled connection is held while the scanner runs, since it can take a
+/// while. Returns whether the file is clean and may be published.
+fn upsert_and_scan(
+    pool: &DbPool,
+    scanner: Option<&Scanner>,
+    entry: &IndexedEntry,
+    path: &Path,
+) -> anyhow::Result<bool> {
+    let Some(scanner) = scanner else {
+        upsert_local_entry(&mut *pool.get()?, entry, scan::CLEAN)?;
+        return Ok(true);
+    };
+    upsert_local_entry(&mut *pool.get()?, entry, scan::PENDING_SCAN)?;
+    let status = scanner.scan(path);
+    pool.get()?.execute(
+        "UPDATE content_index SET scan_status = ?2 WHERE cid = ?1",
+        params![entry.cid, status],
+    )?;
+    Ok(status == scan::CLEAN)
+}
+
+/// Record a local file in content_index as one transaction.
+/// Any stale row at the same dir+path (the file's previous contents) is
+/// removed first, otherwise the unique path index rejects the new cid,
+/// and queued for deletion from the catalog.
+fn upsert_local_entry(
+    conn: &mut rusqlite::Connection,
+    entry: &IndexedEntry,
+    scan_status: &str,
+) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+
+    tombstones::queue_stale(&tx, &entry.dir, &entry.path, &entry.cid)?;
+    tx.execute(
+        "DELETE FROM content_index WHERE dir = ?1 AND path = ?2 AND cid != ?3",
+        params![entry.dir, entry.path, entry.cid],
+    )?;
+
+    tx.execute(
+        "INSERT INTO content_index
+           (cid, dir, path, filename, size, mime, file_type, modified, is_local, scan_status)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 1, ?9)
+         ON CONFLICT(cid) DO UPDATE SET
+           dir = excluded.dir,
+           path = excluded.path,
+           filename = excluded.filename,
+           size = excluded.size,
+           mime = excluded.mime,
+           file_type = excluded.file_type,
+           modified = excluded.modified,
+           is_local = 1,
+           scan_status = excluded.scan_status,
+           indexed_at = datetime('now')
+         ",
+        params![
+            entry.cid,
+            entry.dir,
+            entry.path,
+            entry.filename,
+            entry.size,
+            entry.mime,
+            entry.file_type,
+            entry.modified,
+            scan_status
+        ],
+    )?;
+
+    tx.commit()
 }
 
 /// Extract EXIF DateTimeOriginal (or DateTimeDigitized, DateTime) from an image file.
 /// Returns an RFC 3339 formatted date string, or None if no EXIF date is found.
+#[cfg(feature = "media")]
 fn extract_exif_date(path: &Path) -> Option<String> {
     let file = std::fs::File::open(path).ok()?;
     let mut reader = std::io::BufReader::new(file);
@@ -463,8 +563,14 @@ fn extract_exif_date(path: &Path) -> Option<String> {
     None
 }
 
+/// Without the media feature only the filesystem mtime is used.
+#[cfg(not(feature = "media"))]
+fn extract_exif_date(_path: &Path) -> Option<String> {
+    None
+}
+
 /// Compute BLAKE3 hash of a file, streaming for efficiency.
-fn hash_file(path: &Path) -> anyhow::Result<String> {
+pub(crate) fn hash_file(path: &Path) -> anyhow::Result<String> {
     let mut hasher = blake3::Hasher::new();
     let file = std::fs::File::open(path)?;
     let mut reader = std::io::BufReader::with_capacity(1 << 16, file); // 64KB buffer
@@ -474,6 +580,7 @@ fn hash_file(path: &Path) -> anyhow::Result<String> {
 
 /// Generate a thumbnail and store it in the content_thumbnails table.
 /// Returns the JPEG bytes for publishing to the catalog.
+#[cfg(feature = "media")]
 fn generate_and_store_thumbnail(
     conn: &rusqlite::Connection,
     cid: &str,
@@ -505,3 +612,181 @@ fn generate_and_store_thumbnail(
 
     Ok(jpeg_bytes)
 }
+
+#[cfg(not(feature = "media"))]
+fn generate_and_store_thumbnail(
+    _conn: &rusqlite::Connection,
+    _cid: &str,
+    _file_type: &str,
+    _path: &Path,
+) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("built without the media feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    fn entry(cid: &str, path: &str) -> IndexedEntry {
+        IndexedEntry {
+            cid: cid.to_string(),
+            filename: path.to_string(),
+            dir: "photos".to_string(),
+            path: path.to_string(),
+            size: 10,
+            mime: None,
+            file_type: "other".to_string(),
+            modified: None,
+            thumbnail_bytes: None,
+        }
+    }
+
+    fn cids(conn: &rusqlite::Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT cid FROM content_index ORDER BY cid")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect()
+    }
+
+    #[test]
+    fn classify_file_by_extension() {
+        assert_eq!(classify_file("IMG_0001.CR2"), "raw");
+        assert_eq!(classify_file("photo.jpeg"), "image");
+        assert_eq!(classify_file("clip.mov"), "video");
+        assert_eq!(classify_file("notes.txt"), "other");
+    }
+
+    #[test]
+    fn upsert_replaces_changed_file_at_same_path() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+
+        upsert_local_entry(&mut conn, &entry("old", "a.jpg"), scan::CLEAN).unwrap();
+        upsert_local_entry(&mut conn, &entry("new", "a.jpg"), scan::CLEAN).unwrap();
+
+        assert_eq!(cids(&conn), vec!["new".to_string()]);
+        // Peers are told the old contents are gone
+        assert_eq!(tombstones::pending_retractions(&conn).unwrap(), vec!["old"]);
+    }
+
+    #[test]
+    fn upsert_rolls_back_on_failure() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        upsert_local_entry(&mut conn, &entry("old", "a.jpg"), scan::CLEAN).unwrap();
+
+        conn.execute_batch(
+            "CREATE TRIGGER reject_bad BEFORE INSERT ON content_index
+             WHEN NEW.cid = 'bad' BEGIN SELECT RAISE(ABORT, 'injected'); END;",
+        )
+        .unwrap();
+
+        assert!(upsert_local_entry(&mut conn, &entry("bad", "a.jpg"), scan::CLEAN).is_err());
+        // The stale-row delete must not have been committed on its own.
+        assert_eq!(cids(&conn), vec!["old".to_string()]);
+        assert!(tombstones::pending_retractions(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_file_records_local_entry() {
+        let pool = test_pool();
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("notes.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        let indexed = index_file(&pool, None, "docs", tmp.path(), &file)
+            .unwrap()
+            .expect("new file should be indexed");
+        assert_eq!(indexed.cid, blake3::hash(b"hello").to_hex().to_string());
+        assert_eq!(indexed.path, "notes.txt");
+
+        // Unchanged file is skipped on the next pass
+        assert!(index_file(&pool, None, "docs", tmp.path(), &file)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn removed_files_are_dropped_and_retracted() {
+        let pool = test_pool();
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("sub")).unwrap();
+        std::fs::write(tmp.path().join("deleted.txt"), "deleted").unwrap();
+        std::fs::write(tmp.path().join("moved.txt"), "moved").unwrap();
+        let config = Config {
+            directories: vec![crate::config::DirectoryConfig {
+                label: "docs".to_string(),
+                path: tmp.path().to_string_lossy().into_owned(),
+            }],
+            ..Config::default()
+        };
+        run_index_cycle(&config, &pool);
+
+        std::fs::remove_file(tmp.path().join("deleted.txt")).unwrap();
+        std::fs::rename(
+            tmp.path().join("moved.txt"),
+            tmp.path().join("sub/moved.txt"),
+        )
+        .unwrap();
+        run_index_cycle(&config, &pool);
+
+        let conn = pool.get().unwrap();
+        let paths: Vec<String> = conn
+            .prepare("SELECT path FROM content_index")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            paths,
+            vec![Path::new("sub").join("moved.txt").to_string_lossy()]
+        );
+        // Only the deleted file's entry is withdrawn from peers
+        assert_eq!(
+            tombstones::pending_retractions(&conn).unwrap(),
+            vec![blake3::hash(b"deleted").to_hex().to_string()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flagged_files_are_indexed_but_not_published() {
+        let pool = test_pool();
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("notes.txt");
+        std::fs::write(&file, "hello").unwrap();
+        let timeout = std::time::Duration::from_secs(5);
+        let flagging = Scanner::new("false", timeout, scan::ScanFailurePolicy::Open).unwrap();
+
+        assert!(
+            index_file(&pool, Some(&flagging), "docs", tmp.path(), &file)
+                .unwrap()
+                .is_none()
+        );
+        let status: String = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT scan_status FROM content_index", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(status, scan::FLAGGED);
+
+        // A changed file is scanned again
+        std::fs::write(&file, "hello again").unwrap();
+        let passing = Scanner::new("true", timeout, scan::ScanFailurePolicy::Closed).unwrap();
+        let conn = pool.get().unwrap();
+        conn.execute("UPDATE content_index SET modified = 'earlier'", [])
+            .unwrap();
+        drop(conn);
+        assert!(index_file(&pool, Some(&passing), "docs", tmp.path(), &file)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::Path;
+
+const LOCK_FILE: &str = ".lock";
+
+/// Exclusive advisory lock on a data directory, held for as long as the
+/// value lives. The OS drops the lock when the holder exits, so a crashed
+/// process never leaves a stale lock behind.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Lock `data_dir` for this process and record our PID in the lockfile.
+    /// Fails with the other process's PID if it is already locked.
+    pub fn acquire(data_dir: &Path) -> anyhow::Result<Self> {
+        let path = data_dir.join(LOCK_FILE);
+        // Don't truncate before locking: the PID belongs to the holder
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = match holder_pid(&mut file) {
+                    Some(pid) => format!(" (pid {pid})"),
+                    None => String::new(),
+                };
+                anyhow::bail!(
+                    "Another salita instance{holder} is already using {}. \
+                     Stop it, or pass a different --data-dir.",
+                    data_dir.display()
+                );
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(anyhow::anyhow!("Failed to lock {}: {e}", path.display()));
+            }
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Read the PID the current holder wrote into the lockfile.
+fn holder_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_acquire_fails_with_holder_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let _lock = InstanceLock::acquire(tmp.path()).unwrap();
+
+        let err = InstanceLock::acquire(tmp.path()).err().unwrap();
+        let pid = std::process::id().to_string();
+        assert!(err.to_string().contains(&format!("(pid {pid})")), "{err}");
+
+        let recorded = std::fs::read_to_string(tmp.path().join(LOCK_FILE)).unwrap();
+        assert_eq!(recorded, pid);
+    }
+
+    #[test]
+    fn lock_is_released_on_drop() {
+        let tmp = tempfile::tempdir().unwrap();
+        drop(InstanceLock::acquire(tmp.path()).unwrap());
+        assert!(InstanceLock::acquire(tmp.path()).is_ok());
+    }
+
+    #[test]
+    fn leftover_lockfile_does_not_block() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join(LOCK_FILE), "4194305000").unwrap();
+
+        let _lock = InstanceLock::acquire(tmp.path()).unwrap();
+        let recorded = std::fs::read_to_string(tmp.path().join(LOCK_FILE)).unwrap();
+        assert_eq!(recorded, std::process::id().to_string());
+    }
+}
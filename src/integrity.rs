@@ -0,0 +1,498 @@
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::config::{Config, StorageConfig};
+use crate::db::{self, DbPool};
+use crate::indexer::hash_file;
+use crate::scan::{self, FlaggedFile};
+use crate::tombstones;
+
+/// How much one verification run may read. A run always checks at least
+/// one file, so a file larger than `max_bytes` doesn't stall the pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyBudget {
+    pub max_files: usize,
+    pub max_bytes: u64,
+}
+
+impl VerifyBudget {
+    pub fn from_config(storage: &StorageConfig) -> Self {
+        Self {
+            max_files: storage.verify_max_files.max(1),
+            max_bytes: storage.verify_max_bytes,
+        }
+    }
+}
+
+/// What one run found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// Files whose bytes still hash to their cid
+    pub verified: usize,
+    /// Files newly found changed
+    pub corrupt: usize,
+    /// Files gone from disk, dropped from the index
+    pub removed: usize,
+    /// Corrupt files that hash to their cid again
+    pub restored: usize,
+    pub bytes_read: u64,
+    /// Whether the run reached the end of the index, completing a pass
+    pub completed_pass: bool,
+}
+
+/// Verification progress, for the storage overview.
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityStats {
+    pub last_full_pass_at: Option<String>,
+    /// Local files not verified since they were indexed
+    pub unverified_files: i64,
+    pub corrupt: Vec<FlaggedFile>,
+}
+
+/// A local file due for verification.
+struct Candidate {
+    cid: String,
+    dir: String,
+    path: String,
+    size: i64,
+    scan_status: String,
+    indexed_at: String,
+}
+
+enum Outcome {
+    Matches,
+    Mismatch,
+    /// Gone from a directory that is mounted
+    Missing,
+    /// Modified since it was indexed, or unreadable for now; left alone
+    Skipped,
+}
+
+/// Re-hash the next batch of local files against their cids, resuming
+/// where the last run stopped. A file whose bytes changed without its
+/// modification time moving is marked corrupt so it isn't served; a
+/// corrupt file that matches again is cleared. A file gone from a mounted
+/// directory was deleted or moved, so it is dropped and retracted like
+/// the indexer does. Files the scanner hasn't passed are left to it.
+pub fn verify_batch(
+    pool: &DbPool,
+    config: &Config,
+    budget: VerifyBudget,
+) -> anyhow::Result<VerifyReport> {
+    let candidates = {
+        let conn = pool.get()?;
+        next_candidates(&conn, budget.max_files)?
+    };
+    let reached_end = candidates.len() < budget.max_files;
+
+    let mut report = VerifyReport::default();
+    let mut last = None;
+    let mut out_of_bytes = false;
+    for (visited, candidate) in candidates.iter().enumerate() {
+        let size = candidate.size.max(0) as u64;
+        if visited > 0 && report.bytes_read + size > budget.max_bytes {
+            out_of_bytes = true;
+            break;
+        }
+        let outcome = check(config, candidate);
+        if matches!(outcome, Outcome::Matches | Outcome::Mismatch) {
+            report.bytes_read += size;
+        }
+        record(&mut *pool.get()?, candidate, outcome, &mut report)?;
+        last = Some(candidate);
+    }
+
+    let conn = pool.get()?;
+    if reached_end && !out_of_bytes {
+        conn.execute(
+            "UPDATE integrity_state
+             SET cursor_dir = NULL, cursor_path = NULL, last_full_pass_at = datetime('now')
+             WHERE id = 1",
+            [],
+        )?;
+        report.completed_pass = true;
+    } else if let Some(last) = last {
+        conn.execute(
+            "UPDATE integrity_state SET cursor_dir = ?1, cursor_path = ?2 WHERE id = 1",
+            params![last.dir, last.path],
+        )?;
+    }
+    Ok(report)
+}
+
+/// Up to `limit` local files after the cursor, in (dir, path) order.
+fn next_candidates(conn: &Connection, limit: usize) -> rusqlite::Result<Vec<Candidate>> {
+    let (cursor_dir, cursor_path): (Option<String>, Option<String>) = conn.query_row(
+        "SELECT cursor_dir, cursor_path FROM integrity_state WHERE id = 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT cid, dir, path, size, scan_status, indexed_at FROM content_index
+         WHERE is_local = 1 AND scan_status IN (?1, ?2)
+           AND (?3 IS NULL OR (dir, path) > (?3, ?4))
+         ORDER BY dir, path
+         LIMIT ?5",
+    )?;
+    let rows = stmt.query_map(
+        params![
+            scan::CLEAN,
+            scan::CORRUPT,
+            cursor_dir,
+            cursor_path,
+            limit as i64
+        ],
+        |row| {
+            Ok(Candidate {
+                cid: row.get(0)?,
+                dir: row.get(1)?,
+                path: row.get(2)?,
+                size: row.get(3)?,
+                scan_status: row.get(4)?,
+                indexed_at: row.get(5)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+fn check(config: &Config, candidate: &Candidate) -> Outcome {
+    // A directory that is no longer shared or not mounted isn't corrupt
+    let Some(base) = config.resolve_directory(&candidate.dir) else {
+        return Outcome::Skipped;
+    };
+    if !base.is_dir() {
+        return Outcome::Skipped;
+    }
+    let path = base.join(&candidate.path);
+    let metadata = match std::fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Outcome::Missing,
+        Err(e) => {
+            tracing::debug!("Can't verify {}: {e}", path.display());
+            return Outcome::Skipped;
+        }
+    };
+    if modified_since_indexed(&metadata, &candidate.indexed_at) {
+        // An edit, which the indexer picks up
+        return Outcome::Skipped;
+    }
+    match hash_file(&path) {
+        Ok(cid) if cid == candidate.cid => Outcome::Matches,
+        Ok(_) => Outcome::Mismatch,
+        Err(e) => {
+            tracing::debug!("Can't verify {}: {e}", path.display());
+            Outcome::Skipped
+        }
+    }
+}
+
+/// Whether the file's mtime is later than the second it was indexed in.
+fn modified_since_indexed(metadata: &std::fs::Metadata, indexed_at: &str) -> bool {
+    let (Ok(modified), Ok(indexed_at)) = (metadata.modified(), db::parse_ts(indexed_at)) else {
+        return false;
+    };
+    let modified: chrono::DateTime<chrono::Utc> = modified.into();
+    modified.timestamp() > indexed_at.timestamp()
+}
+
+fn record(
+    conn: &mut Connection,
+    candidate: &Candidate,
+    outcome: Outcome,
+    report: &mut VerifyReport,
+) -> rusqlite::Result<()> {
+    let location = Path::new(&candidate.dir).join(&candidate.path);
+    match outcome {
+        Outcome::Matches => {
+            conn.execute(
+                "UPDATE content_index SET scan_status = ?2, last_verified_at = datetime('now')
+                 WHERE cid = ?1",
+                params![candidate.cid, scan::CLEAN],
+            )?;
+            if candidate.scan_status == scan::CORRUPT {
+                tracing::info!("{} verifies again", location.display());
+                report.restored += 1;
+            } else {
+                report.verified += 1;
+            }
+        }
+        Outcome::Mismatch if candidate.scan_status == scan::CLEAN => {
+            conn.execute(
+                "UPDATE content_index SET scan_status = ?2 WHERE cid = ?1",
+                params![candidate.cid, scan::CORRUPT],
+            )?;
+            tracing::warn!(
+                "{} no longer matches its content hash; it won't be served",
+                location.display()
+            );
+            report.corrupt += 1;
+        }
+        Outcome::Missing => {
+            if tombstones::remove_vanished(conn, &candidate.dir, &candidate.path)? {
+                tracing::info!("{} is gone; removed it from the index", location.display());
+                report.removed += 1;
+            }
+        }
+        Outcome::Mismatch | Outcome::Skipped => {}
+    }
+    Ok(())
+}
+
+pub fn stats(conn: &Connection) -> rusqlite::Result<IntegrityStats> {
+    let (last_full_pass_at, unverified_files) = conn.query_row(
+        "SELECT (SELECT last_full_pass_at FROM integrity_state WHERE id = 1),
+                (SELECT COUNT(*) FROM content_index
+                 WHERE is_local = 1 AND scan_status = ?1 AND last_verified_at IS NULL)",
+        params![scan::CLEAN],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT cid, dir, path, size, indexed_at FROM content_index
+         WHERE is_local = 1 AND scan_status = ?1
+         ORDER BY dir, path",
+    )?;
+    let corrupt = stmt
+        .query_map(params![scan::CORRUPT], |row| {
+            Ok(FlaggedFile {
+                cid: row.get(0)?,
+                dir: row.get(1)?,
+                path: row.get(2)?,
+                size: row.get(3)?,
+                indexed_at: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(IntegrityStats {
+        last_full_pass_at,
+        unverified_files,
+        corrupt,
+    })
+}
+
+/// Spawn a background task that verifies a batch every `interval`.
+pub fn spawn_verifier(config: Config, pool: DbPool, interval: Duration) {
+    tokio::spawn(async move {
+        let budget = VerifyBudget::from_config(&config.storage);
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let cfg = config.clone();
+            let db = pool.clone();
+            let result = tokio::task::spawn_blocking(move || verify_batch(&db, &cfg, budget)).await;
+            match result {
+                Ok(Ok(report)) => {
+                    if report.corrupt > 0 {
+                        tracing::warn!("Integrity check found {} corrupt files", report.corrupt);
+                    }
+                    if report.completed_pass {
+                        tracing::info!("Integrity verification pass complete");
+                    }
+                }
+                Ok(Err(e)) => tracing::warn!("Integrity verification failed: {e}"),
+                Err(e) => tracing::error!("Integrity verification task panicked: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DirectoryConfig;
+    use crate::db::test_pool;
+    use crate::error::{AppError, ErrorCode};
+    use crate::node_alerts::{self, AlertKind};
+
+    fn config(dir: &Path) -> Config {
+        Config {
+            directories: vec![DirectoryConfig {
+                label: "d".to_string(),
+                path: dir.to_string_lossy().into_owned(),
+            }],
+            ..Config::default()
+        }
+    }
+
+    /// Write a file and index it as of `indexed_at` (an SQLite modifier
+    /// relative to now).
+    fn add_file(pool: &DbPool, dir: &Path, name: &str, contents: &str, indexed_at: &str) {
+        std::fs::write(dir.join(name), contents).unwrap();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, is_local, indexed_at)
+                 VALUES (?1, 'd', ?2, ?2, ?3, 1, datetime('now', ?4))",
+                params![
+                    blake3::hash(contents.as_bytes()).to_hex().as_str(),
+                    name,
+                    contents.len() as i64,
+                    indexed_at
+                ],
+            )
+            .unwrap();
+    }
+
+    fn status(pool: &DbPool, name: &str) -> (String, Option<String>) {
+        pool.get()
+            .unwrap()
+            .query_row(
+                "SELECT scan_status, last_verified_at FROM content_index WHERE path = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+    }
+
+    const UNLIMITED: VerifyBudget = VerifyBudget {
+        max_files: 100,
+        max_bytes: u64::MAX,
+    };
+
+    #[test]
+    fn changed_files_are_marked_corrupt_until_they_match() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let config = config(tmp.path());
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            add_file(&pool, tmp.path(), name, name, "+1 minute");
+        }
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO devices (id, name, is_self) VALUES ('me', 'me', 1)",
+                [],
+            )
+            .unwrap();
+
+        let report = verify_batch(&pool, &config, UNLIMITED).unwrap();
+        assert_eq!((report.verified, report.corrupt), (3, 0));
+        assert!(report.completed_pass);
+        assert!(status(&pool, "a.txt").1.is_some());
+
+        // Same size and no newer mtime, as a failing disk would leave it
+        std::fs::write(tmp.path().join("b.txt"), "b.tx!").unwrap();
+        std::fs::remove_file(tmp.path().join("c.txt")).unwrap();
+        let report = verify_batch(&pool, &config, UNLIMITED).unwrap();
+        assert_eq!((report.verified, report.corrupt, report.removed), (1, 1, 1));
+        assert_eq!(status(&pool, "b.txt").0, scan::CORRUPT);
+
+        let conn = pool.get().unwrap();
+        assert!(matches!(
+            scan::check_available(&conn, false, "d", "b.txt"),
+            Err(AppError::Forbidden {
+                code: ErrorCode::Corrupt,
+                ..
+            })
+        ));
+        let alerts = node_alerts::compute_alerts(&conn).unwrap();
+        assert_eq!(alerts["me"][0].kind, AlertKind::CorruptFiles);
+        let stats = stats(&conn).unwrap();
+        assert_eq!(stats.corrupt.len(), 1);
+        assert!(stats.last_full_pass_at.is_some());
+        drop(conn);
+
+        // Found again on a later run, e.g. restored from a backup
+        std::fs::write(tmp.path().join("b.txt"), "b.txt").unwrap();
+        let report = verify_batch(&pool, &config, UNLIMITED).unwrap();
+        assert_eq!((report.restored, report.corrupt), (1, 0));
+        assert_eq!(status(&pool, "b.txt").0, scan::CLEAN);
+    }
+
+    #[test]
+    fn deleted_files_are_forgotten_without_an_alert() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        add_file(&pool, tmp.path(), "a.txt", "a", "+1 minute");
+        add_file(&pool, tmp.path(), "b.txt", "b", "+1 minute");
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO devices (id, name, is_self) VALUES ('me', 'me', 1)",
+                [],
+            )
+            .unwrap();
+        std::fs::remove_file(tmp.path().join("a.txt")).unwrap();
+
+        let report = verify_batch(&pool, &config(tmp.path()), UNLIMITED).unwrap();
+        assert_eq!((report.verified, report.removed), (1, 1));
+        let conn = pool.get().unwrap();
+        let alerts = node_alerts::compute_alerts(&conn).unwrap();
+        assert!(alerts
+            .values()
+            .flatten()
+            .all(|alert| alert.kind != AlertKind::CorruptFiles));
+        assert_eq!(
+            tombstones::pending_retractions(&conn).unwrap(),
+            vec![blake3::hash(b"a").to_hex().to_string()]
+        );
+        drop(conn);
+
+        // An unmounted directory says nothing about its files
+        let unmounted = config(&tmp.path().join("gone"));
+        let report = verify_batch(&pool, &unmounted, UNLIMITED).unwrap();
+        assert_eq!(report.removed, 0);
+        assert_eq!(status(&pool, "b.txt").0, scan::CLEAN);
+    }
+
+    #[test]
+    fn files_edited_since_indexing_are_left_to_the_indexer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        add_file(&pool, tmp.path(), "a.txt", "old", "-1 hour");
+        std::fs::write(tmp.path().join("a.txt"), "new").unwrap();
+
+        let report = verify_batch(&pool, &config(tmp.path()), UNLIMITED).unwrap();
+        assert_eq!(
+            report,
+            VerifyReport {
+                completed_pass: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(status(&pool, "a.txt"), (scan::CLEAN.to_string(), None));
+    }
+
+    #[test]
+    fn runs_stop_at_the_budget_and_resume_from_the_cursor() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let config = config(tmp.path());
+        for name in ["1", "2", "3", "4", "5"] {
+            add_file(&pool, tmp.path(), name, &name.repeat(10), "+1 minute");
+        }
+
+        let by_files = VerifyBudget {
+            max_files: 2,
+            max_bytes: u64::MAX,
+        };
+        let runs: Vec<_> = (0..3)
+            .map(|_| {
+                let report = verify_batch(&pool, &config, by_files).unwrap();
+                (report.verified, report.completed_pass)
+            })
+            .collect();
+        assert_eq!(runs, vec![(2, false), (2, false), (1, true)]);
+        assert_eq!(stats(&pool.get().unwrap()).unwrap().unverified_files, 0);
+
+        // A second file would go over, but the first always gets checked
+        let by_bytes = VerifyBudget {
+            max_files: 100,
+            max_bytes: 15,
+        };
+        let report = verify_batch(&pool, &config, by_bytes).unwrap();
+        assert_eq!((report.verified, report.bytes_read), (1, 10));
+        assert!(!report.completed_pass);
+        let cursor: String = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT cursor_path FROM integrity_state", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(cursor, "1");
+    }
+}
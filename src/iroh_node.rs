@@ -1,9 +1,13 @@
 use std::path::Path;
+use std::time::Duration;
 
 use iroh::protocol::Router;
 use iroh::Endpoint;
+use iroh_blobs::store::fs::options::Options;
 use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::store::GcConfig;
 use iroh_blobs::{BlobsProtocol, ALPN as BLOBS_ALPN};
+use iroh_docs::engine::ProtectCallbackHandler;
 use iroh_docs::protocol::Docs;
 use iroh_docs::ALPN as DOCS_ALPN;
 use iroh_gossip::net::Gossip;
@@ -24,7 +28,10 @@ impl IrohNode {
     /// Creates:
     /// - `data_dir/iroh-blobs/` for blob storage
     /// - `data_dir/iroh-docs/` for document storage
-    pub async fn start(data_dir: &Path) -> anyhow::Result<Self> {
+    ///
+    /// With a `gc_interval`, the blob store deletes untagged blobs on that
+    /// interval; blobs referenced by docs entries are protected.
+    pub async fn start(data_dir: &Path, gc_interval: Option<Duration>) -> anyhow::Result<Self> {
         let blobs_dir = data_dir.join("iroh-blobs");
         let docs_dir = data_dir.join("iroh-docs");
         std::fs::create_dir_all(&blobs_dir)?;
@@ -35,14 +42,28 @@ impl IrohNode {
 
         tracing::info!("iroh node started: {}", endpoint.id());
 
-        // Blob storage on filesystem
-        let blobs = FsStore::load(&blobs_dir).await?;
+        // Blob storage on filesystem, garbage collected if enabled
+        let mut options = Options::new(&blobs_dir);
+        let mut protect_handler = None;
+        if let Some(interval) = gc_interval {
+            let (handler, protect_cb) = ProtectCallbackHandler::new();
+            options.gc = Some(GcConfig {
+                interval,
+                add_protected: Some(protect_cb),
+            });
+            protect_handler = Some(handler);
+        }
+        let blobs = FsStore::load_with_opts(blobs_dir.join("blobs.db"), options).await?;
 
         // Gossip protocol for doc sync
         let gossip: Gossip = Gossip::builder().spawn(endpoint.clone());
 
         // Docs protocol with persistent storage
-        let docs = Docs::persistent(docs_dir)
+        let mut docs = Docs::persistent(docs_dir);
+        if let Some(handler) = protect_handler {
+            docs = docs.protect_handler(handler);
+        }
+        let docs = docs
             .spawn(endpoint.clone(), (*blobs).clone(), gossip.clone())
             .await?;
 
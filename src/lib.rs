@@ -1,13 +1,37 @@
+pub mod admission;
 pub mod catalog_sync;
+pub mod changes;
 pub mod config;
+pub mod content_controls;
 pub mod db;
+#[cfg(feature = "discovery")]
 pub mod discovery;
 pub mod error;
 pub mod files;
 pub mod http;
+pub mod import;
 pub mod indexer;
+pub mod instance_lock;
+pub mod integrity;
 pub mod iroh_node;
+pub mod lifecycle;
+pub mod log_buffer;
+pub mod log_level;
 pub mod mcp;
+pub mod membership;
 pub mod node;
+pub mod node_alerts;
+pub mod node_archive;
+pub mod node_labels;
 pub mod peer_client;
+pub mod query_log;
+pub mod registration;
+pub mod release_notes;
+pub mod remote_cache;
+pub mod scan;
+pub mod sync_status;
+pub mod tempfiles;
+#[cfg(feature = "media")]
 pub mod thumbnail;
+pub mod tombstones;
+pub mod update_check;
@@ -0,0 +1,173 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::format_ts;
+
+/// Lifecycle rows kept; older ones are pruned on each start.
+const MAX_EVENTS: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    Started,
+    Stopped,
+    CrashDetected,
+}
+
+impl LifecycleEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LifecycleEvent::Started => "started",
+            LifecycleEvent::Stopped => "stopped",
+            LifecycleEvent::CrashDetected => "crash_detected",
+        }
+    }
+}
+
+/// A previous run that ended without a clean shutdown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashInfo {
+    /// When the crashed run had started
+    pub previous_start: String,
+    /// When the crash was noticed, i.e. the next start
+    pub detected_at: String,
+}
+
+/// Record a daemon start. If the last recorded event is also a start, the
+/// run before this one never recorded its stop, so a crash is logged too.
+pub fn record_start(
+    conn: &Connection,
+    version: &str,
+    now: DateTime<Utc>,
+) -> rusqlite::Result<Option<CrashInfo>> {
+    let now = format_ts(now);
+    let last: Option<(String, String)> = conn
+        .query_row(
+            "SELECT event, at FROM node_lifecycle ORDER BY id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let crash = match last {
+        Some((event, at)) if event == LifecycleEvent::Started.as_str() => {
+            conn.execute(
+                "INSERT INTO node_lifecycle (event, version, at, previous_start)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![LifecycleEvent::CrashDetected.as_str(), version, now, at],
+            )?;
+            Some(CrashInfo {
+                previous_start: at,
+                detected_at: now.clone(),
+            })
+        }
+        _ => None,
+    };
+
+    insert(conn, LifecycleEvent::Started, version, &now)?;
+    conn.execute(
+        "DELETE FROM node_lifecycle
+         WHERE id <= (SELECT MAX(id) FROM node_lifecycle) - ?1",
+        params![MAX_EVENTS],
+    )?;
+    Ok(crash)
+}
+
+/// Record a clean shutdown.
+pub fn record_stop(conn: &Connection, version: &str, now: DateTime<Utc>) -> rusqlite::Result<()> {
+    insert(conn, LifecycleEvent::Stopped, version, &format_ts(now))
+}
+
+/// The version that recorded the latest event, i.e. the one that ran last.
+pub fn last_version(conn: &Connection) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT version FROM node_lifecycle ORDER BY id DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// The most recent crash on record, if any.
+pub fn last_crash(conn: &Connection) -> rusqlite::Result<Option<CrashInfo>> {
+    conn.query_row(
+        "SELECT previous_start, at FROM node_lifecycle
+         WHERE event = ?1 ORDER BY id DESC LIMIT 1",
+        params![LifecycleEvent::CrashDetected.as_str()],
+        |row| {
+            Ok(CrashInfo {
+                previous_start: row.get(0)?,
+                detected_at: row.get(1)?,
+            })
+        },
+    )
+    .optional()
+}
+
+fn insert(
+    conn: &Connection,
+    event: LifecycleEvent,
+    version: &str,
+    at: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO node_lifecycle (event, version, at) VALUES (?1, ?2, ?3)",
+        params![event.as_str(), version, at],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::db::test_pool;
+
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 1, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn clean_stop_then_start_is_not_a_crash() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        assert_eq!(record_start(&conn, "0.2.0", at(1)).unwrap(), None);
+        record_stop(&conn, "0.2.0", at(2)).unwrap();
+        assert_eq!(record_start(&conn, "0.2.0", at(3)).unwrap(), None);
+        assert_eq!(last_crash(&conn).unwrap(), None);
+    }
+
+    #[test]
+    fn start_after_start_is_a_crash() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        record_start(&conn, "0.2.0", at(1)).unwrap();
+        let crash = record_start(&conn, "0.2.0", at(5)).unwrap().unwrap();
+        assert_eq!(crash.previous_start, "2026-03-01 01:00:00");
+        assert_eq!(crash.detected_at, "2026-03-01 05:00:00");
+        assert_eq!(last_crash(&conn).unwrap(), Some(crash));
+
+        // The new run is tracked normally
+        record_stop(&conn, "0.2.0", at(6)).unwrap();
+        assert_eq!(record_start(&conn, "0.2.0", at(7)).unwrap(), None);
+    }
+
+    #[test]
+    fn old_events_are_pruned() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        for _ in 0..MAX_EVENTS {
+            record_start(&conn, "0.2.0", at(1)).unwrap();
+            record_stop(&conn, "0.2.0", at(2)).unwrap();
+        }
+        record_start(&conn, "0.2.0", at(3)).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM node_lifecycle", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, MAX_EVENTS);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// How many events are kept until config says otherwise.
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// Field name parts whose values never enter the buffer, so both `token`
+/// and `access_token` are masked but `ping` isn't.
+const SENSITIVE_FIELDS: &[&str] = &[
+    "token",
+    "pin",
+    "password",
+    "secret",
+    "cookie",
+    "authorization",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// One captured log event.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    pub at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+fn serialize_level<S: serde::Serializer>(level: &Level, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(level.as_str())
+}
+
+/// The most recent info-and-above log events, so a node can be
+/// troubleshot over HTTP without shell access. The lock is only held to
+/// push or copy out records; formatting happens before it is taken.
+pub struct LogBuffer {
+    capacity: AtomicUsize,
+    records: Mutex<VecDeque<LogRecord>>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: AtomicUsize::new(capacity),
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Logging starts before the config is read, so the size is applied
+    /// once it is known. Shrinking drops the oldest records.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        if let Ok(mut records) = self.records.lock() {
+            while records.len() > capacity {
+                records.pop_front();
+            }
+        }
+    }
+
+    fn push(&self, record: LogRecord) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        if let Ok(mut records) = self.records.lock() {
+            while records.len() >= capacity {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+
+    /// Up to `limit` of the newest records at `min_level` or more severe,
+    /// oldest first.
+    pub fn snapshot(&self, min_level: Level, limit: usize) -> Vec<LogRecord> {
+        let Ok(records) = self.records.lock() else {
+            return Vec::new();
+        };
+        let mut matching: Vec<LogRecord> = records
+            .iter()
+            .rev()
+            .filter(|r| r.level <= min_level)
+            .take(limit)
+            .cloned()
+            .collect();
+        matching.reverse();
+        matching
+    }
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Layer feeding a `LogBuffer`. Add it alongside the fmt layer.
+pub struct BufferLayer {
+    buffer: Arc<LogBuffer>,
+}
+
+impl BufferLayer {
+    pub fn new(buffer: Arc<LogBuffer>) -> Self {
+        Self { buffer }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Less severe levels compare greater
+        if *metadata.level() > Level::INFO {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        self.buffer.push(LogRecord {
+            at: Utc::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.finish(),
+        });
+    }
+}
+
+/// Formats an event as its message followed by `key=value` fields,
+/// masking any field that looks like a credential.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(self) -> String {
+        if self.fields.is_empty() {
+            self.message
+        } else if self.message.is_empty() {
+            self.fields.trim_start().to_string()
+        } else {
+            format!("{}{}", self.message, self.fields)
+        }
+    }
+
+    fn add_field(&mut self, field: &Field, value: &dyn std::fmt::Display) {
+        if is_sensitive(field.name()) {
+            let _ = write!(self.fields, " {}={REDACTED}", field.name());
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.add_field(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.add_field(field, &format_args!("{value:?}"));
+        }
+    }
+}
+
+fn is_sensitive(name: &str) -> bool {
+    name.to_ascii_lowercase()
+        .split(['_', '.'])
+        .any(|part| SENSITIVE_FIELDS.contains(&part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn capture(capacity: usize, f: impl FnOnce()) -> Arc<LogBuffer> {
+        let buffer = Arc::new(LogBuffer::new(capacity));
+        let subscriber = tracing_subscriber::registry().with(BufferLayer::new(buffer.clone()));
+        tracing::subscriber::with_default(subscriber, f);
+        buffer
+    }
+
+    fn messages(records: &[LogRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    #[test]
+    fn keeps_info_and_above() {
+        let buffer = capture(10, || {
+            tracing::debug!("noise");
+            tracing::info!("started");
+            tracing::warn!(peer = "den", "sync slow");
+        });
+        let records = buffer.snapshot(Level::INFO, 10);
+        assert_eq!(messages(&records), vec!["started", "sync slow peer=den"]);
+        assert_eq!(records[1].level, Level::WARN);
+    }
+
+    #[test]
+    fn redacts_sensitive_fields() {
+        let buffer = capture(10, || {
+            tracing::info!(access_token = "abc123", pin = 1234, ping_ms = 8, "paired");
+        });
+        let records = buffer.snapshot(Level::INFO, 10);
+        assert_eq!(
+            messages(&records),
+            vec!["paired access_token=[redacted] pin=[redacted] ping_ms=8"]
+        );
+    }
+
+    #[test]
+    fn level_filter_and_limit() {
+        let buffer = capture(10, || {
+            tracing::warn!("first warning");
+            tracing::info!("info");
+            tracing::error!("failure");
+            tracing::warn!("second warning");
+        });
+        assert_eq!(
+            messages(&buffer.snapshot(Level::WARN, 10)),
+            vec!["first warning", "failure", "second warning"]
+        );
+        assert_eq!(
+            messages(&buffer.snapshot(Level::WARN, 2)),
+            vec!["failure", "second warning"]
+        );
+        assert_eq!(
+            messages(&buffer.snapshot(Level::ERROR, 10)),
+            vec!["failure"]
+        );
+    }
+
+    #[test]
+    fn ring_buffer_caps_at_capacity() {
+        let buffer = capture(3, || {
+            for i in 0..5 {
+                tracing::info!("event {i}");
+            }
+        });
+        assert_eq!(
+            messages(&buffer.snapshot(Level::INFO, 10)),
+            vec!["event 2", "event 3", "event 4"]
+        );
+
+        buffer.set_capacity(1);
+        assert_eq!(messages(&buffer.snapshot(Level::INFO, 10)), vec!["event 4"]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::error::{AppError, AppResult};
+
+/// Handle to the daemon's log filter, as installed in `main`.
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// The filter in effect and when a temporary one gives way to the default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogLevelStatus {
+    pub filter: String,
+    pub default: String,
+    /// None while the default is in effect or reverting is off
+    pub revert_at: Option<DateTime<Utc>>,
+}
+
+/// Changes the log filter at runtime, e.g. to turn on debug logging while
+/// reproducing a problem without restarting the daemon. A changed filter
+/// reverts to the default after `revert_after`, if set, so verbose
+/// logging isn't left on by accident.
+pub struct LogLevel {
+    handle: FilterHandle,
+    default: String,
+    revert_after: Option<Duration>,
+    active: Mutex<Active>,
+}
+
+struct Active {
+    status: LogLevelStatus,
+    /// Bumped on every change so a pending revert for an older one does
+    /// nothing
+    generation: u64,
+}
+
+impl LogLevel {
+    /// `default` is the filter `handle` was created with.
+    pub fn new(handle: FilterHandle, default: String, revert_after: Option<Duration>) -> Self {
+        Self {
+            handle,
+            revert_after,
+            active: Mutex::new(Active {
+                status: LogLevelStatus {
+                    filter: default.clone(),
+                    default: default.clone(),
+                    revert_at: None,
+                },
+                generation: 0,
+            }),
+            default,
+        }
+    }
+
+    pub fn status(&self) -> LogLevelStatus {
+        self.lock().status.clone()
+    }
+
+    /// Apply `filter` (RUST_LOG syntax, e.g. "salita=debug,info") now and
+    /// schedule the revert. An invalid filter changes nothing.
+    pub fn set(self: &Arc<Self>, filter: &str) -> AppResult<LogLevelStatus> {
+        let filter = filter.trim();
+        let parsed = EnvFilter::try_new(filter)
+            .map_err(|e| AppError::BadRequest(format!("Invalid log filter: {e}")))?;
+
+        let mut active = self.lock();
+        self.handle
+            .reload(parsed)
+            .map_err(|e| AppError::Internal(format!("Failed to apply log filter: {e}")))?;
+        active.generation += 1;
+        let revert_after = self.revert_after.filter(|_| filter != self.default);
+        active.status = LogLevelStatus {
+            filter: filter.to_string(),
+            default: self.default.clone(),
+            revert_at: revert_after
+                .map(|after| Utc::now() + chrono::Duration::from_std(after).unwrap_or_default()),
+        };
+        let generation = active.generation;
+        let status = active.status.clone();
+        drop(active);
+        tracing::info!("Log filter set to {filter:?}");
+
+        if let Some(after) = revert_after {
+            let this = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(after).await;
+                this.revert(generation);
+            });
+        }
+        Ok(status)
+    }
+
+    /// Go back to the default filter, unless it was changed again since.
+    fn revert(&self, generation: u64) {
+        let mut active = self.lock();
+        if active.generation != generation {
+            return;
+        }
+        let Ok(parsed) = EnvFilter::try_new(&self.default) else {
+            return;
+        };
+        if let Err(e) = self.handle.reload(parsed) {
+            tracing::warn!("Failed to restore the log filter: {e}");
+            return;
+        }
+        active.status.filter = self.default.clone();
+        active.status.revert_at = None;
+        drop(active);
+        tracing::info!("Log filter restored to {:?}", self.default);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Active> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::log_buffer::{BufferLayer, LogBuffer};
+
+    /// A subscriber whose filter the returned LogLevel controls, recording
+    /// into the returned buffer.
+    fn setup(
+        revert_after: Option<Duration>,
+    ) -> (Arc<LogLevel>, Arc<LogBuffer>, impl tracing::Subscriber) {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("warn"));
+        let logs = Arc::new(LogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry()
+            .with(filter)
+            .with(BufferLayer::new(logs.clone()));
+        let level = Arc::new(LogLevel::new(handle, "warn".into(), revert_after));
+        (level, logs, subscriber)
+    }
+
+    fn messages(logs: &LogBuffer) -> Vec<String> {
+        logs.snapshot(tracing::Level::INFO, 10)
+            .into_iter()
+            .map(|r| r.message)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn valid_filter_applies_and_invalid_one_changes_nothing() {
+        let (level, logs, subscriber) = setup(Some(Duration::from_secs(3600)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info!("hidden");
+        let status = level.set("info").unwrap();
+        assert_eq!(status.filter, "info");
+        assert!(status.revert_at.is_some());
+        tracing::info!("shown");
+
+        assert!(matches!(
+            level.set("salita=loudest"),
+            Err(AppError::BadRequest(_))
+        ));
+        assert_eq!(level.status().filter, "info");
+        let messages = messages(&logs);
+        assert!(!messages.contains(&"hidden".to_string()));
+        assert!(messages.contains(&"shown".to_string()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changed_filter_reverts_after_the_window() {
+        let (level, logs, subscriber) = setup(Some(Duration::from_secs(60)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        level.set("info").unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        // A newer change restarts the window
+        level.set("debug").unwrap();
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        assert_eq!(level.status().filter, "debug");
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(
+            level.status(),
+            LogLevelStatus {
+                filter: "warn".into(),
+                default: "warn".into(),
+                revert_at: None,
+            }
+        );
+        tracing::info!("quiet again");
+        assert!(!messages(&logs).contains(&"quiet again".to_string()));
+    }
+}
@@ -1,22 +1,22 @@
-mod catalog_sync;
-mod config;
-mod db;
-mod discovery;
-mod error;
-mod files;
-mod http;
-mod indexer;
-mod iroh_node;
-mod mcp;
-mod node;
-mod peer_client;
-mod thumbnail;
+use std::path::Path;
+use std::sync::Arc;
 
+use chrono::Utc;
 use clap::Parser;
-use rusqlite::params;
-use tracing_subscriber::EnvFilter;
-
-use crate::config::{Cli, Command, Config};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter};
+
+use salita::config::{Cli, Command, Config, ConfigCommand};
+use salita::import::{ImportMode, ImportOptions};
+use salita::instance_lock::InstanceLock;
+use salita::log_buffer::{BufferLayer, LogBuffer};
+use salita::log_level::LogLevel;
+use salita::registration::{self, DeviceRegistration};
+use salita::{
+    catalog_sync, db, http, import, indexer, integrity, iroh_node, lifecycle, mcp, node,
+    release_notes,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -25,98 +25,200 @@ async fn main() -> anyhow::Result<()> {
     // In MCP mode, tracing must go to stderr (stdout is the MCP transport)
     let is_mcp = matches!(cli.command, Command::Mcp);
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new(if is_mcp { "warn" } else { "info" })),
-        )
-        .with_writer(if is_mcp {
+    let logs = Arc::new(LogBuffer::default());
+    // Behind a reload layer so the filter can be changed while serving
+    let default_filter = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|filter| EnvFilter::try_new(filter).is_ok())
+        .unwrap_or_else(|| if is_mcp { "warn" } else { "info" }.to_string());
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&default_filter));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(if is_mcp {
             std::io::stderr as fn() -> std::io::Stderr
         } else {
             std::io::stderr as fn() -> std::io::Stderr
-        })
+        }))
+        .with(BufferLayer::new(logs.clone()))
         .init();
 
+    let (config, provenance) = Config::load_with_provenance(&cli)?;
+    logs.set_capacity(config.server.log_buffer_lines);
+    let log_level = Arc::new(LogLevel::new(
+        filter_handle,
+        default_filter,
+        config.server.log_level_revert(),
+    ));
+    if let Command::Config {
+        action: ConfigCommand::Show,
+    } = cli.command
+    {
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
+
     let data_dir = Config::data_dir(&cli);
     std::fs::create_dir_all(&data_dir)?;
 
-    let config = Config::load(&cli)?;
+    // Only one daemon per data dir; `mcp` runs alongside it by design
+    let _instance_lock = match cli.command {
+        Command::Serve { .. } => Some(InstanceLock::acquire(&data_dir)?),
+        _ => None,
+    };
+
     let db_path = Config::db_path(&cli);
-    let pool = db::create_pool(&db_path)?;
+    provenance.log_summary(&config, &data_dir, &db_path);
+    let pool = db::create_pool(&db_path, &config.debug)?;
     db::run_migrations(&pool)?;
 
     let node_identity = node::NodeIdentity::load_or_create(&data_dir)?;
     tracing::info!("Node: {} ({})", node_identity.name, node_identity.id);
 
     // Register self in devices table
-    {
-        let conn = pool.get()?;
-        conn.execute(
-            "INSERT INTO devices (id, name, endpoint, port, status, last_seen, is_self)
-             VALUES (?1, ?2, ?3, ?4, 'online', datetime('now'), 1)
-             ON CONFLICT(id) DO UPDATE SET
-               name = excluded.name,
-               endpoint = excluded.endpoint,
-               port = excluded.port,
-               status = 'online',
-               last_seen = datetime('now'),
-               is_self = 1",
-            params![
-                &node_identity.id,
-                &node_identity.name,
-                "localhost",
-                config.server.port
-            ],
-        )?;
-        conn.execute(
-            "INSERT OR REPLACE INTO current_node (node_id) VALUES (?1)",
-            params![&node_identity.id],
-        )?;
-    }
+    registration::register_self(
+        &mut *pool.get()?,
+        &DeviceRegistration {
+            id: &node_identity.id,
+            name: &node_identity.name,
+            endpoint: "localhost",
+            port: config.server.port,
+            base_path: &config.server.base_path,
+        },
+    )?;
 
     match cli.command {
         Command::Serve { .. } => {
-            // Start iroh node for mesh catalog replication
-            let iroh = iroh_node::IrohNode::start(&data_dir).await?;
-            tracing::info!("iroh node ID: {}", iroh.endpoint.id());
-
-            // Start catalog sync
-            let catalog = catalog_sync::CatalogSync::new(
-                iroh.docs.clone(),
-                iroh.blobs.clone(),
-                pool.clone(),
-                node_identity.id.clone(),
-            )
-            .await?;
-            let catalog = std::sync::Arc::new(tokio::sync::Mutex::new(catalog));
-
-            // Run initial sync from existing doc entries
-            {
-                let c = catalog.lock().await;
-                if let Err(e) = c.initial_sync().await {
-                    tracing::warn!("Initial catalog sync failed: {e}");
-                }
+            let version = env!("CARGO_PKG_VERSION");
+            let previous = lifecycle::last_version(&*pool.get()?)?;
+            if release_notes::record_upgrade(
+                &*pool.get()?,
+                previous.as_deref(),
+                version,
+                Utc::now(),
+            )? {
+                tracing::info!(
+                    "Upgraded from {} to {version}; see GET /api/v1/whats-new",
+                    previous.unwrap_or_default()
+                );
+            }
+            if let Some(crash) = lifecycle::record_start(&*pool.get()?, version, Utc::now())? {
+                tracing::warn!(
+                    "The previous run (started {}) did not shut down cleanly",
+                    crash.previous_start
+                );
             }
 
-            // Spawn background subscriber for live updates
-            let catalog_bg = catalog.clone();
-            tokio::spawn(async move {
-                if let Err(e) = catalog_sync::CatalogSync::subscribe_and_ingest(catalog_bg).await {
-                    tracing::error!("Catalog subscription ended: {e}");
-                }
-            });
-
-            // Start indexer (with catalog sync for publishing)
-            indexer::spawn_indexer(config.clone(), pool.clone(), Some(catalog.clone()));
-            http::run_serve(config, pool, node_identity).await?;
-
-            // Cleanup
-            iroh.shutdown().await?;
+            // Recorded however serving ends, so an error on the way out
+            // isn't mistaken for a crash on the next start
+            let served = serve(
+                config,
+                pool.clone(),
+                node_identity,
+                &data_dir,
+                logs,
+                log_level,
+            )
+            .await;
+            lifecycle::record_stop(&*pool.get()?, version, Utc::now())?;
+            served?;
         }
         Command::Mcp => {
             mcp::run_mcp(config, pool).await?;
         }
+        Command::ImportMedia {
+            path,
+            into,
+            recursive,
+            move_files,
+        } => {
+            let dest = config
+                .resolve_directory(&into)
+                .ok_or_else(|| anyhow::anyhow!("No shared directory labelled {into:?}"))?;
+            let opts = ImportOptions {
+                source: path,
+                dest,
+                recursive,
+                mode: if move_files {
+                    ImportMode::Move
+                } else {
+                    ImportMode::Copy
+                },
+            };
+            let report =
+                tokio::task::spawn_blocking(move || import::import_media(&pool, &opts)).await??;
+            println!(
+                "Imported {} files ({} bytes); skipped {} duplicates and {} unsupported; {} failed",
+                report.imported, report.bytes, report.duplicates, report.unsupported, report.failed
+            );
+        }
+        Command::Config { .. } => unreachable!("handled before startup"),
     }
 
     Ok(())
 }
+
+/// Run the daemon: iroh and catalog sync, background tasks and the HTTP
+/// server, until shutdown.
+async fn serve(
+    config: Config,
+    pool: db::DbPool,
+    node_identity: node::NodeIdentity,
+    data_dir: &Path,
+    logs: Arc<LogBuffer>,
+    log_level: Arc<LogLevel>,
+) -> anyhow::Result<()> {
+    // Start iroh node for mesh catalog replication
+    let gc_interval = config.storage.gc_interval();
+    let iroh = iroh_node::IrohNode::start(data_dir, gc_interval).await?;
+    tracing::info!("iroh node ID: {}", iroh.endpoint.id());
+
+    // Start catalog sync
+    let catalog = catalog_sync::CatalogSync::new(
+        iroh.docs.clone(),
+        iroh.blobs.clone(),
+        pool.clone(),
+        node_identity.id.clone(),
+    )
+    .await?;
+    let catalog = std::sync::Arc::new(tokio::sync::Mutex::new(catalog));
+
+    // Run initial sync from existing doc entries
+    {
+        let c = catalog.lock().await;
+        if let Err(e) = c.initial_sync().await {
+            tracing::warn!("Initial catalog sync failed: {e}");
+        }
+    }
+
+    // Spawn background subscriber for live updates
+    let catalog_bg = catalog.clone();
+    tokio::spawn(async move {
+        if let Err(e) = catalog_sync::CatalogSync::subscribe_and_ingest(catalog_bg).await {
+            tracing::error!("Catalog subscription ended: {e}");
+        }
+    });
+
+    if let Some(interval) = gc_interval {
+        catalog_sync::spawn_gc(catalog.clone(), interval);
+    }
+
+    // Start indexer (with catalog sync for publishing)
+    indexer::spawn_indexer(config.clone(), pool.clone(), Some(catalog.clone()));
+    if let Some(interval) = config.storage.verify_interval() {
+        integrity::spawn_verifier(config.clone(), pool.clone(), interval);
+    }
+    let served = http::run_serve(
+        config,
+        pool,
+        node_identity,
+        data_dir,
+        Some(catalog),
+        logs,
+        log_level,
+    )
+    .await;
+
+    // Cleanup, even if the server failed
+    iroh.shutdown().await?;
+    served
+}
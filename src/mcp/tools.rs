@@ -4,24 +4,31 @@ use rusqlite::params;
 
 use crate::db::DbPool;
 use crate::peer_client::PeerClient;
+use crate::scan::{self, Scanner};
 
 use super::types::*;
 use super::SalitaMcp;
 
-/// Lookup device info from the database. Returns (is_self, endpoint, port).
-fn lookup_device(pool: &DbPool, device: &str) -> Result<(bool, String, u16), McpError> {
+/// Lookup device info from the database. Returns (is_self, endpoint, port, base_path).
+fn lookup_device(pool: &DbPool, device: &str) -> Result<(bool, String, u16, String), McpError> {
     let conn = pool
         .get()
         .map_err(|e| McpError::internal_error(format!("Database error: {}", e), None))?;
 
     let result = conn.query_row(
-        "SELECT is_self, endpoint, port FROM devices WHERE id = ?1 OR name = ?1",
+        &format!(
+            "SELECT is_self, endpoint, port, base_path FROM devices
+             WHERE (id = ?1 OR name = ?1) AND NOT {} AND NOT {}",
+            crate::admission::NOT_ADMITTED,
+            crate::node_archive::ARCHIVED
+        ),
         params![device],
         |row| {
             Ok((
                 row.get::<_, bool>(0)?,
                 row.get::<_, String>(1)?,
                 row.get::<_, u16>(2)?,
+                row.get::<_, String>(3)?,
             ))
         },
     );
@@ -39,19 +46,19 @@ fn lookup_device(pool: &DbPool, device: &str) -> Result<(bool, String, u16), Mcp
     }
 }
 
-/// Check if a request targets a remote device, returning (endpoint, port) if so
+/// Check if a request targets a remote device, returning (endpoint, port, base_path) if so
 fn remote_target(
     pool: &DbPool,
     device: &Option<String>,
-) -> Result<Option<(String, u16)>, McpError> {
+) -> Result<Option<(String, u16, String)>, McpError> {
     match device {
         None => Ok(None),
         Some(dev) => {
-            let (is_self, endpoint, port) = lookup_device(pool, dev)?;
+            let (is_self, endpoint, port, base_path) = lookup_device(pool, dev)?;
             if is_self {
                 Ok(None)
             } else {
-                Ok(Some((endpoint, port)))
+                Ok(Some((endpoint, port, base_path)))
             }
         }
     }
@@ -65,7 +72,10 @@ impl SalitaMcp {
             .map_err(|e| McpError::internal_error(format!("Database error: {}", e), None))?;
 
         let mut stmt = conn
-            .prepare("SELECT id, name, endpoint, port, is_self, status, last_seen FROM devices")
+            .prepare(
+                "SELECT id, name, endpoint, port, is_self, status, last_seen FROM devices
+                 WHERE archived_at IS NULL",
+            )
             .map_err(|e| McpError::internal_error(format!("Query error: {}", e), None))?;
 
         let devices: Vec<serde_json::Value> = stmt
@@ -95,12 +105,13 @@ impl SalitaMcp {
     ) -> Result<CallToolResult, McpError> {
         let path = params.path.as_deref().unwrap_or("");
 
-        if let Some((endpoint, port)) = remote_target(&self.pool, &params.device)? {
+        if let Some((endpoint, port, base_path)) = remote_target(&self.pool, &params.device)? {
             let client = PeerClient::new();
             let entries = tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(client.list_files(
                     &endpoint,
                     port,
+                    &base_path,
                     &params.directory,
                     path,
                 ))
@@ -123,12 +134,13 @@ impl SalitaMcp {
         &self,
         params: SearchFilesParams,
     ) -> Result<CallToolResult, McpError> {
-        if let Some((endpoint, port)) = remote_target(&self.pool, &params.device)? {
+        if let Some((endpoint, port, base_path)) = remote_target(&self.pool, &params.device)? {
             let client = PeerClient::new();
             let entries = tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(client.search_files(
                     &endpoint,
                     port,
+                    &base_path,
                     &params.pattern,
                     params.directory.as_deref(),
                 ))
@@ -152,12 +164,13 @@ impl SalitaMcp {
         &self,
         params: ReadFileParams,
     ) -> Result<CallToolResult, McpError> {
-        if let Some((endpoint, port)) = remote_target(&self.pool, &params.device)? {
+        if let Some((endpoint, port, base_path)) = remote_target(&self.pool, &params.device)? {
             let client = PeerClient::new();
             let content = tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(client.read_file(
                     &endpoint,
                     port,
+                    &base_path,
                     &params.directory,
                     &params.path,
                 ))
@@ -167,6 +180,15 @@ impl SalitaMcp {
             return Ok(CallToolResult::success(vec![Content::text(content)]));
         }
 
+        {
+            let conn = self
+                .pool
+                .get()
+                .map_err(|e| McpError::internal_error(format!("Database error: {}", e), None))?;
+            let scanning = Scanner::from_config(&self.config.storage).is_some();
+            scan::check_available(&conn, scanning, &params.directory, &params.path)
+                .map_err(|e| McpError::internal_error(format!("{}", e), None))?;
+        }
         let content = crate::files::read_file(&self.config, &params.directory, &params.path)
             .map_err(|e| McpError::internal_error(format!("{}", e), None))?;
 
@@ -177,12 +199,13 @@ impl SalitaMcp {
         &self,
         params: FileInfoParams,
     ) -> Result<CallToolResult, McpError> {
-        if let Some((endpoint, port)) = remote_target(&self.pool, &params.device)? {
+        if let Some((endpoint, port, base_path)) = remote_target(&self.pool, &params.device)? {
             let client = PeerClient::new();
             let info = tokio::task::block_in_place(|| {
                 tokio::runtime::Handle::current().block_on(client.file_info(
                     &endpoint,
                     port,
+                    &base_path,
                     &params.directory,
                     &params.path,
                 ))
@@ -201,3 +224,46 @@ impl SalitaMcp {
         Ok(CallToolResult::success(vec![Content::text(text)]))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DirectoryConfig};
+
+    #[test]
+    fn quarantined_files_are_not_read() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("notes.txt"), "hello").unwrap();
+        let pool = crate::db::test_pool();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO content_index (cid, dir, path, filename, size, scan_status)
+                 VALUES ('c1', 'docs', 'notes.txt', 'notes.txt', 5, 'flagged')",
+                [],
+            )
+            .unwrap();
+        let config = Config {
+            directories: vec![DirectoryConfig {
+                label: "docs".into(),
+                path: tmp.path().to_string_lossy().into_owned(),
+            }],
+            ..Default::default()
+        };
+        let mcp = SalitaMcp::new(config, pool.clone());
+        let read = || ReadFileParams {
+            device: None,
+            directory: "docs".into(),
+            path: "notes.txt".into(),
+        };
+
+        let err = mcp.read_file_impl(read()).unwrap_err();
+        assert!(err.message.contains("quarantined"), "{}", err.message);
+
+        pool.get()
+            .unwrap()
+            .execute("UPDATE content_index SET scan_status = 'clean'", [])
+            .unwrap();
+        assert!(mcp.read_file_impl(read()).is_ok());
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::admission::{self, Admission, NOT_ADMITTED, REJECTED};
+use crate::config::MeshConfig;
+use crate::db::{self, DbPool};
+use crate::node_archive::ARCHIVED;
+use crate::peer_client::PeerClient;
+
+/// A device as one node describes it to another. Only what a peer needs
+/// to reach the device; nothing about content or local settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeshMember {
+    pub id: String,
+    pub name: String,
+    pub endpoint: Option<String>,
+    pub port: u16,
+    pub base_path: String,
+    pub status: String,
+    pub last_seen: Option<String>,
+}
+
+/// Members this node knows firsthand: itself and the peers it found or
+/// that registered with it. Devices it only heard about from other peers
+/// are left out, so membership spreads one hop per pull and never echoes
+/// back to where it came from.
+pub fn firsthand_members(conn: &Connection) -> rusqlite::Result<Vec<MeshMember>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, name, endpoint, port, base_path, status, last_seen FROM devices
+         WHERE learned_from IS NULL AND NOT {NOT_ADMITTED} AND NOT {ARCHIVED}
+         ORDER BY id"
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok(MeshMember {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            endpoint: row.get(2)?,
+            port: row.get(3)?,
+            base_path: row.get(4)?,
+            status: row.get(5)?,
+            last_seen: row.get(6)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Record what `source_id` reported about the mesh: its full member list.
+/// Returns how many devices were added, updated or expired.
+///
+/// Rules, applied in one transaction:
+/// - This node, the source itself and devices this node knows firsthand
+///   are never touched: what we saw beats what we were told.
+/// - Unknown devices go through `policy` admission like discovered ones,
+///   and are stored with `learned_from` set to the source.
+/// - A device learned earlier is updated when the report's `last_seen` is
+///   newer than ours, whichever peer it came from, or when it is as fresh
+///   and says the device went offline (going offline doesn't move
+///   `last_seen`).
+/// - Devices learned from the source that it no longer lists are
+///   forgotten, unless rejected or archived here.
+/// - Reports without a usable `last_seen` are ignored; times in the
+///   future are taken as now.
+pub fn merge(
+    conn: &mut Connection,
+    policy: &MeshConfig,
+    source_id: &str,
+    members: &[MeshMember],
+) -> rusqlite::Result<usize> {
+    let tx = conn.transaction()?;
+    let now = Utc::now();
+    let mut changed = 0;
+
+    for member in members {
+        if member.id == source_id {
+            continue;
+        }
+        let Some(last_seen) = member
+            .last_seen
+            .as_deref()
+            .and_then(|ts| db::parse_ts(ts).ok())
+            .map(|ts| db::format_ts(ts.min(now)))
+        else {
+            continue;
+        };
+        let status = if member.status == "online" {
+            "online"
+        } else {
+            "offline"
+        };
+
+        let existing: Option<(bool, Option<String>, Option<String>, String)> = tx
+            .query_row(
+                "SELECT is_self, learned_from, last_seen, status FROM devices WHERE id = ?1",
+                params![member.id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        match existing {
+            Some((true, ..)) | Some((false, None, ..)) => {}
+            Some((false, Some(_), stored, stored_status)) => {
+                let fresher = stored.as_ref().is_none_or(|stored| *stored < last_seen);
+                let went_offline = stored.as_ref() == Some(&last_seen)
+                    && status == "offline"
+                    && stored_status == "online";
+                if !fresher && !went_offline {
+                    continue;
+                }
+                changed += tx.execute(
+                    &format!(
+                        "UPDATE devices SET name = ?2, endpoint = ?3, port = ?4, base_path = ?5,
+                             status = CASE WHEN {NOT_ADMITTED} THEN status ELSE ?6 END,
+                             last_seen = ?7, learned_from = ?8
+                         WHERE id = ?1"
+                    ),
+                    params![
+                        member.id,
+                        member.name,
+                        member.endpoint,
+                        member.port,
+                        member.base_path,
+                        status,
+                        last_seen,
+                        source_id
+                    ],
+                )?;
+            }
+            None => {
+                let status = match admission::admit(&tx, policy, &member.id)? {
+                    Admission::Member => status,
+                    Admission::Pending => admission::PENDING,
+                    Admission::Rejected | Admission::MeshFull => continue,
+                };
+                changed += tx.execute(
+                    "INSERT INTO devices
+                         (id, name, endpoint, port, base_path, status, last_seen, learned_from)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        member.id,
+                        member.name,
+                        member.endpoint,
+                        member.port,
+                        member.base_path,
+                        status,
+                        last_seen,
+                        source_id
+                    ],
+                )?;
+            }
+        }
+    }
+
+    // Whatever the source stopped listing, it no longer vouches for
+    let reported: HashSet<&str> = members.iter().map(|m| m.id.as_str()).collect();
+    let learned: Vec<String> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id FROM devices
+             WHERE learned_from = ?1 AND status != '{REJECTED}' AND NOT {ARCHIVED}"
+        ))?;
+        let rows = stmt.query_map(params![source_id], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for id in learned {
+        if !reported.contains(id.as_str()) {
+            changed += tx.execute("DELETE FROM devices WHERE id = ?1", params![id])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Periodically pull the member list of every online peer known
+/// firsthand and merge it, so nodes learn about peers their discovery
+/// can't see (e.g. on another subnet).
+pub fn spawn_gossip(pool: DbPool, policy: MeshConfig, interval: Duration) {
+    tokio::spawn(async move {
+        let client = PeerClient::new();
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = pull_all(&pool, &policy, &client).await {
+                tracing::warn!("Membership sync failed: {e}");
+            }
+        }
+    });
+}
+
+async fn pull_all(pool: &DbPool, policy: &MeshConfig, client: &PeerClient) -> anyhow::Result<()> {
+    let peers: Vec<(String, String, u16, String)> = {
+        let conn = pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, endpoint, port, base_path FROM devices
+             WHERE is_self = 0 AND learned_from IS NULL AND status = 'online'
+               AND endpoint IS NOT NULL AND archived_at IS NULL",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    for (id, endpoint, port, base_path) in peers {
+        match client.mesh_members(&endpoint, port, &base_path).await {
+            Ok(members) => {
+                let changed = merge(&mut *pool.get()?, policy, &id, &members)?;
+                if changed > 0 {
+                    tracing::info!("Updated {changed} mesh members from {id}");
+                }
+            }
+            Err(e) => tracing::debug!("Could not fetch members from {id}: {e}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApprovalMode;
+    use crate::registration::{self, DeviceRegistration};
+    use crate::db::test_pool;
+
+
+    fn device<'a>(id: &'a str, endpoint: &'a str) -> DeviceRegistration<'a> {
+        DeviceRegistration {
+            id,
+            name: id,
+            endpoint,
+            port: 6969,
+            base_path: "",
+        }
+    }
+
+    /// A node's database with itself registered.
+    fn node(id: &str) -> DbPool {
+        let pool = test_pool();
+        registration::register_self(&mut pool.get().unwrap(), &device(id, "localhost")).unwrap();
+        pool
+    }
+
+    fn peer(pool: &DbPool, id: &str, endpoint: &str) {
+        registration::register_peer(
+            &mut pool.get().unwrap(),
+            &MeshConfig::default(),
+            &device(id, endpoint),
+        )
+        .unwrap();
+    }
+
+    fn row(pool: &DbPool, id: &str) -> Option<(String, String, Option<String>)> {
+        pool.get()
+            .unwrap()
+            .query_row(
+                "SELECT endpoint, status, learned_from FROM devices WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .unwrap()
+    }
+
+    fn report_for(id: &str) -> MeshMember {
+        MeshMember {
+            id: id.into(),
+            name: id.into(),
+            endpoint: Some("10.0.0.8".into()),
+            port: 6969,
+            base_path: String::new(),
+            status: "online".into(),
+            last_seen: Some("2024-05-01 12:00:00".into()),
+        }
+    }
+
+    fn pull(into: &DbPool, from: &DbPool, from_id: &str, policy: &MeshConfig) -> usize {
+        let members = firsthand_members(&from.get().unwrap()).unwrap();
+        merge(&mut into.get().unwrap(), policy, from_id, &members).unwrap()
+    }
+
+    #[test]
+    fn third_node_learns_about_a_peer_through_a_shared_one() {
+        let (a, c) = (node("a"), node("c"));
+        peer(&a, "b", "10.0.0.2");
+        peer(&c, "a", "10.0.0.1");
+        let policy = MeshConfig::default();
+
+        assert_eq!(pull(&c, &a, "a", &policy), 1);
+        assert_eq!(
+            row(&c, "b"),
+            Some(("10.0.0.2".into(), "online".into(), Some("a".into())))
+        );
+        // The shared peer's own row is what C saw, not what A says
+        assert_eq!(row(&c, "a").unwrap().0, "10.0.0.1");
+
+        // C doesn't pass on hearsay, so A isn't told about B by C
+        let from_c: Vec<String> = firsthand_members(&c.get().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(from_c, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn archived_devices_are_not_passed_on() {
+        let (a, c) = (node("a"), node("c"));
+        peer(&a, "b", "10.0.0.2");
+        peer(&a, "d", "10.0.0.4");
+        crate::node_archive::archive(&a.get().unwrap(), "b").unwrap();
+
+        assert_eq!(pull(&c, &a, "a", &MeshConfig::default()), 1);
+        assert_eq!(row(&c, "b"), None);
+        assert!(row(&c, "d").is_some());
+    }
+
+    #[test]
+    fn payload_carries_only_addressing_fields() {
+        let a = node("a");
+        peer(&a, "b", "10.0.0.2");
+        let json = serde_json::to_value(firsthand_members(&a.get().unwrap()).unwrap()).unwrap();
+
+        let mut keys: Vec<&str> = json[0]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "base_path",
+                "endpoint",
+                "id",
+                "last_seen",
+                "name",
+                "port",
+                "status"
+            ]
+        );
+    }
+
+    #[test]
+    fn firsthand_devices_and_admission_decisions_are_kept() {
+        let c = node("c");
+        peer(&c, "b", "10.0.0.2");
+        peer(&c, "pending", "10.0.0.5");
+        c.get()
+            .unwrap()
+            .execute(
+                "UPDATE devices SET status = 'rejected' WHERE id = 'pending'",
+                [],
+            )
+            .unwrap();
+
+        let report = vec![
+            MeshMember {
+                id: "b".into(),
+                name: "b".into(),
+                endpoint: Some("10.9.9.9".into()),
+                port: 1,
+                base_path: String::new(),
+                status: "offline".into(),
+                last_seen: Some("2999-01-01 00:00:00".into()),
+            },
+            MeshMember {
+                id: "pending".into(),
+                endpoint: Some("10.0.0.6".into()),
+                ..report_for("pending")
+            },
+        ];
+        assert_eq!(
+            merge(&mut c.get().unwrap(), &MeshConfig::default(), "a", &report).unwrap(),
+            0
+        );
+        assert_eq!(
+            row(&c, "b"),
+            Some(("10.0.0.2".into(), "online".into(), None))
+        );
+        assert_eq!(row(&c, "pending").unwrap().1, "rejected");
+
+        // Under manual approval a learned device waits like a discovered one
+        let manual = MeshConfig {
+            approval: ApprovalMode::Manual,
+            ..Default::default()
+        };
+        merge(&mut c.get().unwrap(), &manual, "a", &[report_for("new")]).unwrap();
+        assert_eq!(row(&c, "new").unwrap().1, admission::PENDING);
+    }
+
+    #[test]
+    fn conflicting_reports_resolve_to_the_freshest() {
+        let c = node("c");
+        let policy = MeshConfig::default();
+        let report = |endpoint: &str, last_seen: &str| MeshMember {
+            endpoint: Some(endpoint.into()),
+            last_seen: Some(last_seen.into()),
+            ..report_for("b")
+        };
+
+        merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "a",
+            &[report("10.0.0.2", "2024-05-01 12:00:00")],
+        )
+        .unwrap();
+        let stale = merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "d",
+            &[report("10.0.0.3", "2024-05-01 11:00:00")],
+        )
+        .unwrap();
+        assert_eq!(stale, 0);
+        assert_eq!(row(&c, "b").unwrap().0, "10.0.0.2");
+
+        merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "d",
+            &[report("10.0.0.4", "2024-05-01T13:00:00Z")],
+        )
+        .unwrap();
+        assert_eq!(
+            row(&c, "b"),
+            Some(("10.0.0.4".into(), "online".into(), Some("d".into())))
+        );
+    }
+
+    #[test]
+    fn going_offline_is_passed_on() {
+        let c = node("c");
+        let policy = MeshConfig::default();
+        let report = |status: &str, last_seen: &str| MeshMember {
+            status: status.into(),
+            last_seen: Some(last_seen.into()),
+            ..report_for("b")
+        };
+
+        merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "a",
+            &[report("online", "2024-05-01 12:00:00")],
+        )
+        .unwrap();
+        // A lost B; its last_seen stays where it was
+        let changed = merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "a",
+            &[report("offline", "2024-05-01 12:00:00")],
+        )
+        .unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(row(&c, "b").unwrap().1, "offline");
+
+        // Only a newer sighting brings it back
+        for (status, last_seen) in [
+            ("online", "2024-05-01 11:00:00"),
+            ("online", "2024-05-01 12:00:00"),
+        ] {
+            merge(
+                &mut c.get().unwrap(),
+                &policy,
+                "d",
+                &[report(status, last_seen)],
+            )
+            .unwrap();
+            assert_eq!(row(&c, "b").unwrap().1, "offline");
+        }
+        merge(
+            &mut c.get().unwrap(),
+            &policy,
+            "d",
+            &[report("online", "2024-05-01 13:00:00")],
+        )
+        .unwrap();
+        assert_eq!(row(&c, "b").unwrap().1, "online");
+    }
+
+    #[test]
+    fn devices_the_source_stops_listing_are_forgotten() {
+        let (a, c) = (node("a"), node("c"));
+        peer(&a, "b", "10.0.0.2");
+        peer(&a, "d", "10.0.0.4");
+        peer(&a, "e", "10.0.0.5");
+        let policy = MeshConfig::default();
+        assert_eq!(pull(&c, &a, "a", &policy), 3);
+        // E was rejected here, and D has been reported by F since
+        c.get()
+            .unwrap()
+            .execute_batch(
+                "UPDATE devices SET status = 'rejected' WHERE id = 'e';
+                 UPDATE devices SET learned_from = 'f' WHERE id = 'd';",
+            )
+            .unwrap();
+
+        a.get()
+            .unwrap()
+            .execute("DELETE FROM devices WHERE id IN ('b', 'd', 'e')", [])
+            .unwrap();
+        assert_eq!(pull(&c, &a, "a", &policy), 1);
+        assert_eq!(row(&c, "b"), None);
+        assert_eq!(row(&c, "d").unwrap().2.as_deref(), Some("f"));
+        // The rejection is kept
+        assert_eq!(row(&c, "e").unwrap().1, "rejected");
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+/// A peer not seen on the network for this many seconds is flagged.
+const UNSEEN_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Catalog sync older than this many seconds counts as stale.
+const SYNC_STALE_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Worst first, so sorting puts the most urgent alert at the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    CorruptFiles,
+    SyncFailing,
+    NotSeen,
+    SyncStale,
+}
+
+/// Something about a device worth showing next to it in a node list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeAlert {
+    pub kind: AlertKind,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// What alerts are derived from, as read from devices, peer_sync_status
+/// and, for this node, content_index.
+#[derive(Debug, Clone, Default)]
+pub struct NodeState {
+    pub is_self: bool,
+    pub seen_age_secs: Option<f64>,
+    pub sync_attempted: bool,
+    pub sync_success_age_secs: Option<f64>,
+    pub sync_error: Option<String>,
+    /// Local files that failed integrity verification; this node only
+    pub corrupt_files: i64,
+}
+
+/// Derive the alerts for one device, worst first.
+pub fn alerts_for(node: &NodeState) -> Vec<NodeAlert> {
+    let mut alerts = Vec::new();
+    if node.corrupt_files > 0 {
+        alerts.push(NodeAlert {
+            kind: AlertKind::CorruptFiles,
+            severity: Severity::Warning,
+            message: format!(
+                "{} local files failed integrity verification",
+                node.corrupt_files
+            ),
+        });
+    }
+    if node.is_self {
+        return alerts;
+    }
+
+    if node.seen_age_secs.is_none_or(|age| age >= UNSEEN_SECS) {
+        alerts.push(NodeAlert {
+            kind: AlertKind::NotSeen,
+            severity: Severity::Warning,
+            message: "Not seen on the network for over a day".to_string(),
+        });
+    }
+    if let Some(ref error) = node.sync_error {
+        alerts.push(NodeAlert {
+            kind: AlertKind::SyncFailing,
+            severity: Severity::Warning,
+            message: format!("Catalog sync is failing: {error}"),
+        });
+    } else if node.sync_attempted
+        && node
+            .sync_success_age_secs
+            .is_none_or(|age| age >= SYNC_STALE_SECS)
+    {
+        alerts.push(NodeAlert {
+            kind: AlertKind::SyncStale,
+            severity: Severity::Info,
+            message: "No catalog updates for over a day".to_string(),
+        });
+    }
+
+    alerts.sort_by_key(|a| (a.severity, a.kind));
+    alerts
+}
+
+/// Alerts for every device, keyed by device id, in a single query.
+pub fn compute_alerts(conn: &Connection) -> rusqlite::Result<HashMap<String, Vec<NodeAlert>>> {
+    let mut stmt = conn.prepare(
+        "SELECT d.id, d.is_self,
+                (julianday('now') - julianday(d.last_seen)) * 86400.0,
+                s.last_attempt_at IS NOT NULL,
+                (julianday('now') - julianday(s.last_success_at)) * 86400.0,
+                s.last_error,
+                CASE WHEN d.is_self THEN
+                  (SELECT COUNT(*) FROM content_index WHERE is_local = 1 AND scan_status = 'corrupt')
+                ELSE 0 END
+         FROM devices d
+         LEFT JOIN peer_sync_status s ON s.node_id = d.id AND s.sync_kind = 'catalog'
+         WHERE d.archived_at IS NULL",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let node = NodeState {
+            is_self: row.get(1)?,
+            seen_age_secs: row.get(2)?,
+            sync_attempted: row.get(3)?,
+            sync_success_age_secs: row.get(4)?,
+            sync_error: row.get(5)?,
+            corrupt_files: row.get(6)?,
+        };
+        Ok((row.get::<_, String>(0)?, alerts_for(&node)))
+    })?;
+
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn kinds(node: &NodeState) -> Vec<AlertKind> {
+        alerts_for(node).iter().map(|a| a.kind).collect()
+    }
+
+    fn seen(age: f64) -> NodeState {
+        NodeState {
+            seen_age_secs: Some(age),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn not_seen_boundary_is_one_day() {
+        assert_eq!(kinds(&seen(UNSEEN_SECS - 1.0)), vec![]);
+        assert_eq!(kinds(&seen(UNSEEN_SECS)), vec![AlertKind::NotSeen]);
+        assert_eq!(kinds(&NodeState::default()), vec![AlertKind::NotSeen]);
+    }
+
+    #[test]
+    fn sync_stale_boundary_is_one_day() {
+        let synced = |age: Option<f64>| NodeState {
+            sync_attempted: true,
+            sync_success_age_secs: age,
+            ..seen(0.0)
+        };
+        assert_eq!(kinds(&synced(Some(SYNC_STALE_SECS - 1.0))), vec![]);
+        assert_eq!(
+            kinds(&synced(Some(SYNC_STALE_SECS))),
+            vec![AlertKind::SyncStale]
+        );
+        assert_eq!(kinds(&synced(None)), vec![AlertKind::SyncStale]);
+        assert_eq!(kinds(&seen(0.0)), vec![]);
+    }
+
+    #[test]
+    fn failing_sync_outranks_the_rest_and_self_has_none() {
+        let node = NodeState {
+            sync_attempted: true,
+            sync_error: Some("connection reset".to_string()),
+            ..Default::default()
+        };
+        let alerts = alerts_for(&node);
+        assert_eq!(
+            alerts.iter().map(|a| a.kind).collect::<Vec<_>>(),
+            vec![AlertKind::SyncFailing, AlertKind::NotSeen]
+        );
+        assert!(alerts[0].message.ends_with("connection reset"));
+
+        let me = NodeState {
+            is_self: true,
+            ..node
+        };
+        assert!(alerts_for(&me).is_empty());
+    }
+
+    #[test]
+    fn compute_alerts_reads_devices_and_sync_status() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+
+        for (id, is_self, seen) in [
+            ("me", 1, "-1 minute"),
+            ("ok", 0, "-1 minute"),
+            ("gone", 0, "-2 days"),
+        ] {
+            conn.execute(
+                "INSERT INTO devices (id, name, is_self, last_seen)
+                 VALUES (?1, ?1, ?2, datetime('now', ?3))",
+                params![id, is_self, seen],
+            )
+            .unwrap();
+        }
+        crate::sync_status::record_failure(
+            &conn,
+            "ok",
+            crate::sync_status::SyncKind::Catalog,
+            "boom",
+        )
+        .unwrap();
+
+        let alerts = compute_alerts(&conn).unwrap();
+        let kinds = |id: &str| alerts[id].iter().map(|a| a.kind).collect::<Vec<_>>();
+        assert_eq!(kinds("me"), vec![]);
+        assert_eq!(kinds("ok"), vec![AlertKind::SyncFailing]);
+        assert_eq!(kinds("gone"), vec![AlertKind::NotSeen]);
+    }
+}
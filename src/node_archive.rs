@@ -0,0 +1,190 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::admission::{PENDING, REJECTED};
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Devices the owner set aside, e.g. a NAS away for repair. They stay
+/// known, with their labels, content settings and sync status, but are
+/// hidden from the device list, left out of membership gossip and alerts,
+/// and their catalog entries are neither listed nor synced in, nor their
+/// content fetched.
+pub const ARCHIVED: &str = "archived_at IS NOT NULL";
+
+/// SQL condition excluding catalog entries (aliased `ci`) synced in from
+/// archived devices.
+pub const NOT_FROM_ARCHIVED: &str = "(ci.origin_node IS NULL OR ci.origin_node NOT IN
+     (SELECT id FROM devices WHERE archived_at IS NOT NULL))";
+
+/// Archive a member device. Returns whether it changed; archiving an
+/// archived device does nothing.
+pub fn archive(conn: &Connection, device_id: &str) -> AppResult<bool> {
+    let (status, archived) = archivable(conn, device_id)?;
+    if status == PENDING || status == REJECTED {
+        return Err(AppError::BadRequest(
+            "Only members of the mesh can be archived".into(),
+        ));
+    }
+    if archived {
+        return Ok(false);
+    }
+    conn.execute(
+        "UPDATE devices SET archived_at = datetime('now') WHERE id = ?1",
+        params![device_id],
+    )?;
+    tracing::info!("Archived device {device_id}");
+    Ok(true)
+}
+
+/// Bring an archived device back exactly as it was. Returns whether it
+/// changed.
+pub fn unarchive(conn: &Connection, device_id: &str) -> AppResult<bool> {
+    let (_, archived) = archivable(conn, device_id)?;
+    if !archived {
+        return Ok(false);
+    }
+    conn.execute(
+        "UPDATE devices SET archived_at = NULL WHERE id = ?1",
+        params![device_id],
+    )?;
+    tracing::info!("Unarchived device {device_id}");
+    Ok(true)
+}
+
+pub fn is_archived(conn: &Connection, device_id: &str) -> rusqlite::Result<bool> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT {ARCHIVED} FROM devices WHERE id = ?1"),
+            params![device_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false))
+}
+
+/// Refuse to contact an archived device, with a `node_archived` conflict.
+pub fn check_active(conn: &Connection, device_id: &str) -> AppResult<()> {
+    if is_archived(conn, device_id)? {
+        return Err(AppError::Conflict {
+            code: ErrorCode::NodeArchived,
+            message: format!("device {device_id} is archived"),
+        });
+    }
+    Ok(())
+}
+
+/// Status and whether it is archived, for a device other than this node.
+fn archivable(conn: &Connection, device_id: &str) -> AppResult<(String, bool)> {
+    conn.query_row(
+        &format!("SELECT status, {ARCHIVED} FROM devices WHERE id = ?1 AND is_self = 0"),
+        params![device_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or(AppError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MeshConfig;
+    use crate::registration::{self, DeviceRegistration};
+
+    fn test_pool() -> crate::db::DbPool {
+        let pool = crate::db::test_pool();
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO devices (id, name, is_self, status) VALUES
+                   ('me', 'laptop', 1, 'online'),
+                   ('nas', 'nas', 0, 'online'),
+                   ('new', 'phone', 0, 'pending');
+                 INSERT INTO peer_sync_status (node_id, sync_kind, last_success_at)
+                 VALUES ('nas', 'catalog', '2026-01-01 00:00:00');
+                 INSERT INTO node_labels (node_id, label) VALUES ('nas', 'storage');",
+            )
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn archive_and_unarchive_leave_the_rest_alone() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+
+        assert!(archive(&conn, "nas").unwrap());
+        assert!(!archive(&conn, "nas").unwrap());
+        assert!(matches!(
+            check_active(&conn, "nas"),
+            Err(AppError::Conflict {
+                code: ErrorCode::NodeArchived,
+                ..
+            })
+        ));
+
+        // Announcing itself while archived doesn't bring it back
+        let nas = DeviceRegistration {
+            id: "nas",
+            name: "nas",
+            endpoint: "10.0.0.2",
+            port: 6969,
+            base_path: "",
+        };
+        registration::register_peer(&mut conn, &MeshConfig::default(), &nas).unwrap();
+        assert!(check_active(&conn, "nas").is_err());
+
+        assert!(unarchive(&conn, "nas").unwrap());
+        assert!(!unarchive(&conn, "nas").unwrap());
+        assert!(check_active(&conn, "nas").is_ok());
+        let (synced, labels): (String, i64) = conn
+            .query_row(
+                "SELECT last_success_at, (SELECT COUNT(*) FROM node_labels WHERE node_id = 'nas')
+                 FROM peer_sync_status WHERE node_id = 'nas'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(synced, "2026-01-01 00:00:00");
+        assert_eq!(labels, 1);
+    }
+
+    #[test]
+    fn entries_from_archived_devices_are_hidden() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO content_index (cid, dir, path, filename, size, is_local, origin_node)
+             VALUES ('mine', 'd', 'a', 'a', 1, 1, NULL),
+                    ('theirs', 'd', 'b', 'b', 1, 0, 'nas');",
+        )
+        .unwrap();
+        let visible = || -> Vec<String> {
+            conn.prepare(&format!(
+                "SELECT cid FROM content_index ci WHERE {NOT_FROM_ARCHIVED} ORDER BY cid"
+            ))
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+        };
+        assert_eq!(visible(), ["mine", "theirs"]);
+
+        archive(&conn, "nas").unwrap();
+        assert!(is_archived(&conn, "nas").unwrap());
+        assert_eq!(visible(), ["mine"]);
+    }
+
+    #[test]
+    fn only_peers_that_are_members_can_be_archived() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        assert!(matches!(archive(&conn, "me"), Err(AppError::NotFound)));
+        assert!(matches!(archive(&conn, "ghost"), Err(AppError::NotFound)));
+        assert!(matches!(
+            archive(&conn, "new"),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(check_active(&conn, "ghost").is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rusqlite::{params, Connection};
+
+use crate::error::{AppError, AppResult};
+
+/// Longest label accepted, in characters.
+const MAX_LABEL_CHARS: usize = 32;
+
+/// Group that devices without any label fall into. Reserved, so a real
+/// label can't be confused with it.
+pub const UNLABELED: &str = "unlabeled";
+
+/// The stored form of a label: trimmed and lowercased, so labels compare
+/// without regard to case in any script.
+pub fn normalize(label: &str) -> String {
+    label.trim().to_lowercase()
+}
+
+/// Normalize a label and check it is 1–32 characters with no control
+/// characters, and not the reserved `UNLABELED`.
+pub fn validate(label: &str) -> AppResult<String> {
+    let label = normalize(label);
+    let chars = label.chars().count();
+    if chars == 0 || chars > MAX_LABEL_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "Labels must be 1 to {MAX_LABEL_CHARS} characters"
+        )));
+    }
+    if label.chars().any(char::is_control) {
+        return Err(AppError::BadRequest(
+            "Labels cannot contain control characters".into(),
+        ));
+    }
+    if label == UNLABELED {
+        return Err(AppError::BadRequest(format!(
+            "\"{UNLABELED}\" is reserved for devices without labels"
+        )));
+    }
+    Ok(label)
+}
+
+/// A device's labels, alphabetically.
+pub fn list(conn: &Connection, node_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT label FROM node_labels WHERE node_id = ?1 ORDER BY label")?;
+    let rows = stmt.query_map(params![node_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Labels of every device that has any, keyed by device id.
+pub fn all(conn: &Connection) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    let mut stmt =
+        conn.prepare("SELECT node_id, label FROM node_labels ORDER BY node_id, label")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+
+    let mut labels: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (node_id, label) = row?;
+        labels.entry(node_id).or_default().push(label);
+    }
+    Ok(labels)
+}
+
+/// Tag a device. Labels are stored normalized so groups don't split on
+/// case. Returns whether a label was added, or `NotFound` for an unknown
+/// device.
+pub fn add(conn: &Connection, node_id: &str, label: &str) -> AppResult<bool> {
+    let label = validate(label)?;
+    let known: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM devices WHERE id = ?1",
+        params![node_id],
+        |row| row.get(0),
+    )?;
+    if !known {
+        return Err(AppError::NotFound);
+    }
+    let added = conn.execute(
+        "INSERT OR IGNORE INTO node_labels (node_id, label) VALUES (?1, ?2)",
+        params![node_id, label],
+    )?;
+    Ok(added > 0)
+}
+
+/// Untag a device, ignoring case. Returns whether a label was removed.
+pub fn remove(conn: &Connection, node_id: &str, label: &str) -> rusqlite::Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM node_labels WHERE node_id = ?1 AND label = ?2",
+        params![node_id, normalize(label)],
+    )?;
+    Ok(removed > 0)
+}
+
+/// Device ids grouped by label for a grouped device list. A device with
+/// several labels appears in each group; unlabeled ones go under
+/// `UNLABELED`. Groups are sorted by label, ids keep the input order.
+pub fn group_devices(
+    device_ids: &[String],
+    labels: &HashMap<String, Vec<String>>,
+) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for id in device_ids {
+        match labels.get(id).filter(|l| !l.is_empty()) {
+            Some(device_labels) => {
+                for label in device_labels {
+                    groups.entry(label.clone()).or_default().push(id.clone());
+                }
+            }
+            None => groups
+                .entry(UNLABELED.to_string())
+                .or_default()
+                .push(id.clone()),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pool() -> crate::db::DbPool {
+        let pool = crate::db::test_pool();
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO devices (id, name)
+                 VALUES ('nas', 'nas'), ('media-box', 'tv'), ('phone-a', 'a'), ('phone-b', 'b')",
+            )
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn validation_normalizes_and_bounds_length() {
+        assert_eq!(validate("  Kids ").unwrap(), "kids");
+        assert_eq!(validate("ÉCOLE").unwrap(), "école");
+        assert_eq!(validate(&"é".repeat(32)).unwrap(), "é".repeat(32));
+        assert!(validate("").is_err());
+        assert!(validate("   ").is_err());
+        assert!(validate(&"a".repeat(33)).is_err());
+        assert!(validate("bad\nlabel").is_err());
+        assert!(validate("Unlabeled").is_err());
+    }
+
+    #[test]
+    fn add_and_remove_dedupe_case_insensitively() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        assert!(add(&conn, "nas", "Infra").unwrap());
+        assert!(!add(&conn, "nas", "infra").unwrap());
+        assert!(add(&conn, "nas", "media").unwrap());
+        assert!(add(&conn, "nas", "Über").unwrap());
+        assert!(!add(&conn, "nas", "über").unwrap());
+        assert!(remove(&conn, "nas", "ÜBER").unwrap());
+        assert_eq!(list(&conn, "nas").unwrap(), vec!["infra", "media"]);
+
+        assert!(remove(&conn, "nas", "INFRA").unwrap());
+        assert!(!remove(&conn, "nas", "infra").unwrap());
+        assert_eq!(list(&conn, "nas").unwrap(), vec!["media"]);
+        assert!(add(&conn, "nas", "").is_err());
+        assert!(matches!(
+            add(&conn, "unknown", "media"),
+            Err(AppError::NotFound)
+        ));
+
+        add(&conn, "media-box", "MEDIA").unwrap();
+        assert_eq!(list(&conn, "media-box").unwrap(), vec!["media"]);
+    }
+
+    #[test]
+    fn devices_are_grouped_by_label() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add(&conn, "phone-a", "phones").unwrap();
+        add(&conn, "phone-a", "kids").unwrap();
+        add(&conn, "phone-b", "phones").unwrap();
+
+        let ids: Vec<String> = ["phone-a", "phone-b", "laptop"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let groups = group_devices(&ids, &all(&conn).unwrap());
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            vec!["kids", "phones", UNLABELED]
+        );
+        assert_eq!(groups["phones"], vec!["phone-a", "phone-b"]);
+        assert_eq!(groups["kids"], vec!["phone-a"]);
+        assert_eq!(groups[UNLABELED], vec!["laptop"]);
+    }
+}
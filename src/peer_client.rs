@@ -1,11 +1,23 @@
+use std::path::Path;
+
+use tokio::io::AsyncWriteExt;
+
 use crate::error::{AppError, AppResult};
 use crate::files::{FileEntry, FileInfo};
+use crate::http::Summary;
+use crate::membership::MeshMember;
 
 /// HTTP client for calling peer node APIs
 pub struct PeerClient {
     client: reqwest::Client,
 }
 
+impl Default for PeerClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PeerClient {
     pub fn new() -> Self {
         Self {
@@ -13,20 +25,21 @@ impl PeerClient {
         }
     }
 
-    fn base_url(endpoint: &str, port: u16) -> String {
-        format!("http://{}:{}", endpoint, port)
+    fn base_url(endpoint: &str, port: u16, base_path: &str) -> String {
+        format!("http://{}:{}{}", endpoint, port, base_path)
     }
 
     pub async fn list_files(
         &self,
         endpoint: &str,
         port: u16,
+        base_path: &str,
         dir: &str,
         path: &str,
     ) -> AppResult<Vec<FileEntry>> {
         let url = format!(
             "{}/api/v1/files?dir={}&path={}",
-            Self::base_url(endpoint, port),
+            Self::base_url(endpoint, port, base_path),
             dir,
             path
         );
@@ -46,12 +59,13 @@ impl PeerClient {
         &self,
         endpoint: &str,
         port: u16,
+        base_path: &str,
         pattern: &str,
         dir: Option<&str>,
     ) -> AppResult<Vec<FileEntry>> {
         let mut url = format!(
             "{}/api/v1/files/search?pattern={}",
-            Self::base_url(endpoint, port),
+            Self::base_url(endpoint, port, base_path),
             pattern
         );
         if let Some(d) = dir {
@@ -74,12 +88,13 @@ impl PeerClient {
         &self,
         endpoint: &str,
         port: u16,
+        base_path: &str,
         dir: &str,
         path: &str,
     ) -> AppResult<String> {
         let url = format!(
             "{}/api/v1/files/read?dir={}&path={}",
-            Self::base_url(endpoint, port),
+            Self::base_url(endpoint, port, base_path),
             dir,
             path
         );
@@ -95,16 +110,80 @@ impl PeerClient {
             .map_err(|e| AppError::Internal(format!("Failed to read peer response: {}", e)))
     }
 
+    /// Download a file by cid into `dest`, hashing it as it arrives and
+    /// giving up once the peer sends more than `max_bytes`. Returns the
+    /// BLAKE3 hash of what was received and the content type the peer
+    /// reported.
+    pub async fn fetch_content(
+        &self,
+        endpoint: &str,
+        port: u16,
+        base_path: &str,
+        cid: &str,
+        dest: &Path,
+        max_bytes: u64,
+    ) -> AppResult<(String, Option<String>)> {
+        let url = format!(
+            "{}/api/v1/content/{}",
+            Self::base_url(endpoint, port, base_path),
+            cid
+        );
+        let mut resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::PeerUnavailable(e.to_string()))?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::NotFound);
+        }
+        if !resp.status().is_success() {
+            return Err(AppError::PeerUnavailable(format!(
+                "peer returned {}",
+                resp.status()
+            )));
+        }
+
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let mut file = tokio::fs::File::create(dest).await?;
+        let mut hasher = blake3::Hasher::new();
+        let mut received = 0u64;
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| AppError::PeerUnavailable(e.to_string()))?
+        {
+            received += chunk.len() as u64;
+            if received > max_bytes {
+                return Err(AppError::PeerUnavailable(format!(
+                    "peer sent more than the expected {max_bytes} bytes for {cid}"
+                )));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok((hasher.finalize().to_hex().to_string(), content_type))
+    }
+
     pub async fn file_info(
         &self,
         endpoint: &str,
         port: u16,
+        base_path: &str,
         dir: &str,
         path: &str,
     ) -> AppResult<FileInfo> {
         let url = format!(
             "{}/api/v1/files/info?dir={}&path={}",
-            Self::base_url(endpoint, port),
+            Self::base_url(endpoint, port, base_path),
             dir,
             path
         );
@@ -119,4 +198,77 @@ impl PeerClient {
             .await
             .map_err(|e| AppError::Internal(format!("Failed to parse peer response: {}", e)))
     }
+
+    /// Fetch a node's status summary: identity, version, uptime and counts.
+    pub async fn summary(&self, endpoint: &str, port: u16, base_path: &str) -> AppResult<Summary> {
+        let url = format!(
+            "{}/api/v1/summary",
+            Self::base_url(endpoint, port, base_path)
+        );
+        let resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::PeerUnavailable(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::PeerUnavailable(format!(
+                "peer returned {}",
+                resp.status()
+            )));
+        }
+
+        resp.json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse peer response: {}", e)))
+    }
+
+    /// Fetch the members a node knows firsthand.
+    pub async fn mesh_members(
+        &self,
+        endpoint: &str,
+        port: u16,
+        base_path: &str,
+    ) -> AppResult<Vec<MeshMember>> {
+        let url = format!(
+            "{}/api/v1/mesh/members",
+            Self::base_url(endpoint, port, base_path)
+        );
+        let resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::PeerUnavailable(e.to_string()))?;
+
+        if !resp.status().is_success() {
+            return Err(AppError::PeerUnavailable(format!(
+                "peer returned {}",
+                resp.status()
+            )));
+        }
+
+        resp.json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse peer response: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_peer_is_reported_as_unavailable() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let err = PeerClient::new()
+            .summary("127.0.0.1", port, "")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::PeerUnavailable(_)));
+    }
 }
@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rusqlite::Connection;
+
+use crate::config::DebugConfig;
+
+/// SQLite's profile hook takes a plain function pointer, so the threshold
+/// it compares against lives here rather than in a closure.
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(50);
+
+/// Install the statement profiler on `conn` when `debug.log_queries` is
+/// set. When it isn't, no hook is installed and queries pay nothing.
+pub fn install(conn: &mut Connection, debug: &DebugConfig) {
+    if !debug.log_queries {
+        return;
+    }
+    SLOW_QUERY_MS.store(debug.slow_query_ms, Ordering::Relaxed);
+    conn.profile(Some(log_statement));
+}
+
+/// Called by SQLite after each statement finishes. The text is the
+/// statement as prepared, with `?N` placeholders rather than bound values,
+/// so parameters such as tokens never reach the log.
+fn log_statement(sql: &str, elapsed: Duration) {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let ms = elapsed.as_secs_f64() * 1000.0;
+    if elapsed >= Duration::from_millis(SLOW_QUERY_MS.load(Ordering::Relaxed)) {
+        tracing::warn!("Slow query ({ms:.1}ms): {sql}");
+    } else {
+        tracing::debug!("Query ({ms:.1}ms): {sql}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::log_buffer::{BufferLayer, LogBuffer};
+
+    #[test]
+    fn slow_statements_are_logged_without_their_parameters() {
+        let logs = Arc::new(LogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry().with(BufferLayer::new(logs.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut conn = Connection::open_in_memory().unwrap();
+            install(
+                &mut conn,
+                &DebugConfig {
+                    log_queries: true,
+                    slow_query_ms: 0,
+                },
+            );
+            conn.query_row(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000)
+                 SELECT COUNT(*) FROM n WHERE ?1 IS NOT NULL",
+                ["secret-token-value"],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap();
+        });
+
+        let slow = logs.snapshot(Level::WARN, 10);
+        assert_eq!(slow.len(), 1);
+        assert!(slow[0].message.starts_with("Slow query"));
+        assert!(slow[0].message.contains("WHERE ?1 IS NOT NULL"));
+        assert!(!slow[0].message.contains("secret-token-value"));
+    }
+
+    #[test]
+    fn disabled_logging_installs_no_hook() {
+        let logs = Arc::new(LogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry().with(BufferLayer::new(logs.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut conn = Connection::open_in_memory().unwrap();
+            install(&mut conn, &DebugConfig::default());
+            conn.execute_batch("SELECT 1").unwrap();
+        });
+
+        assert!(logs.snapshot(Level::INFO, 10).is_empty());
+    }
+}
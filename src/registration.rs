@@ -0,0 +1,238 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::admission::{self, Admission};
+use crate::config::MeshConfig;
+
+/// Where a device can be reached, as announced by it or read from config.
+#[derive(Debug, Clone)]
+pub struct DeviceRegistration<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub endpoint: &'a str,
+    pub port: u16,
+    pub base_path: &'a str,
+}
+
+/// Record this node in the devices table and as the current node.
+///
+/// This node is always a member: the admission policy doesn't apply to
+/// it. Re-registering updates its address but keeps `created_at`.
+pub fn register_self(conn: &mut Connection, device: &DeviceRegistration) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    upsert(&tx, device, "online", true)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO current_node (node_id) VALUES (?1)",
+        params![device.id],
+    )?;
+    tx.commit()
+}
+
+/// Record a peer that announced itself, subject to `mesh` admission.
+///
+/// Rules, applied in one transaction:
+/// - A peer claiming this node's own id is ignored (returned as
+///   `Rejected`); it would otherwise overwrite the self row.
+/// - Members are stored as online; pending peers stay pending while
+///   their address is kept current; rejected peers and peers over the
+///   cap are not written at all.
+/// - Re-registration updates name and address and keeps `created_at`.
+///   A device previously only heard about from other peers becomes one
+///   known firsthand.
+/// - Names need not be unique: two peers with the same name and
+///   different ids are two devices.
+pub fn register_peer(
+    conn: &mut Connection,
+    mesh: &MeshConfig,
+    device: &DeviceRegistration,
+) -> rusqlite::Result<Admission> {
+    let tx = conn.transaction()?;
+
+    let is_self: bool = tx
+        .query_row(
+            "SELECT is_self FROM devices WHERE id = ?1",
+            params![device.id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false);
+    if is_self {
+        return Ok(Admission::Rejected);
+    }
+
+    let admission = admission::admit(&tx, mesh, device.id)?;
+    let status = match admission {
+        Admission::Member => "online",
+        Admission::Pending => admission::PENDING,
+        Admission::Rejected | Admission::MeshFull => return Ok(admission),
+    };
+    upsert(&tx, device, status, false)?;
+    tx.commit()?;
+    Ok(admission)
+}
+
+fn upsert(
+    conn: &Connection,
+    device: &DeviceRegistration,
+    status: &str,
+    is_self: bool,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO devices (id, name, endpoint, port, base_path, status, last_seen, is_self)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'), ?7)
+         ON CONFLICT(id) DO UPDATE SET
+           name = excluded.name,
+           endpoint = excluded.endpoint,
+           port = excluded.port,
+           base_path = excluded.base_path,
+           status = excluded.status,
+           last_seen = datetime('now'),
+           is_self = excluded.is_self,
+           learned_from = NULL",
+        params![
+            device.id,
+            device.name,
+            device.endpoint,
+            device.port,
+            device.base_path,
+            status,
+            is_self
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApprovalMode;
+    use crate::db::test_pool;
+
+
+    fn device<'a>(id: &'a str, name: &'a str, endpoint: &'a str) -> DeviceRegistration<'a> {
+        DeviceRegistration {
+            id,
+            name,
+            endpoint,
+            port: 6969,
+            base_path: "",
+        }
+    }
+
+    fn row(conn: &Connection, id: &str) -> (String, String, String, bool) {
+        conn.query_row(
+            "SELECT endpoint, status, created_at, is_self FROM devices WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn self_registration_sets_current_node_and_keeps_created_at() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        register_self(&mut conn, &device("me", "laptop", "localhost")).unwrap();
+        conn.execute(
+            "UPDATE devices SET created_at = '2020-01-01 00:00:00' WHERE id = 'me'",
+            [],
+        )
+        .unwrap();
+
+        register_self(&mut conn, &device("me", "laptop", "127.0.0.1")).unwrap();
+        let (endpoint, status, created_at, is_self) = row(&conn, "me");
+        assert_eq!(endpoint, "127.0.0.1");
+        assert_eq!(status, "online");
+        assert_eq!(created_at, "2020-01-01 00:00:00");
+        assert!(is_self);
+
+        let current: String = conn
+            .query_row("SELECT node_id FROM current_node", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(current, "me");
+    }
+
+    #[test]
+    fn peer_reregistration_updates_address_and_keeps_created_at() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let mesh = MeshConfig::default();
+
+        let admitted = register_peer(&mut conn, &mesh, &device("nas", "nas", "10.0.0.2")).unwrap();
+        assert_eq!(admitted, Admission::Member);
+        conn.execute(
+            "UPDATE devices SET created_at = '2020-01-01 00:00:00', status = 'offline'",
+            [],
+        )
+        .unwrap();
+
+        register_peer(&mut conn, &mesh, &device("nas", "nas", "10.0.0.3")).unwrap();
+        let (endpoint, status, created_at, is_self) = row(&conn, "nas");
+        assert_eq!(endpoint, "10.0.0.3");
+        assert_eq!(status, "online");
+        assert_eq!(created_at, "2020-01-01 00:00:00");
+        assert!(!is_self);
+    }
+
+    #[test]
+    fn peer_cannot_take_over_the_self_row() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        register_self(&mut conn, &device("me", "laptop", "localhost")).unwrap();
+
+        let admission = register_peer(
+            &mut conn,
+            &MeshConfig::default(),
+            &device("me", "impostor", "10.0.0.9"),
+        )
+        .unwrap();
+        assert_eq!(admission, Admission::Rejected);
+        let (endpoint, _, _, is_self) = row(&conn, "me");
+        assert_eq!(endpoint, "localhost");
+        assert!(is_self);
+    }
+
+    #[test]
+    fn same_name_with_a_different_id_is_a_separate_device() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let mesh = MeshConfig::default();
+        register_peer(&mut conn, &mesh, &device("a", "phone", "10.0.0.4")).unwrap();
+        register_peer(&mut conn, &mesh, &device("b", "phone", "10.0.0.5")).unwrap();
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM devices WHERE name = 'phone'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn pending_peers_stay_pending_and_rejected_ones_are_not_written() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let mesh = MeshConfig {
+            approval: ApprovalMode::Manual,
+            ..Default::default()
+        };
+
+        let first = register_peer(&mut conn, &mesh, &device("tv", "tv", "10.0.0.6")).unwrap();
+        assert_eq!(first, Admission::Pending);
+        register_peer(&mut conn, &mesh, &device("tv", "tv", "10.0.0.7")).unwrap();
+        let (endpoint, status, _, _) = row(&conn, "tv");
+        assert_eq!(
+            (endpoint.as_str(), status.as_str()),
+            ("10.0.0.7", "pending")
+        );
+
+        admission::reject(&conn, "tv").unwrap();
+        register_peer(&mut conn, &mesh, &device("tv", "tv", "10.0.0.8")).unwrap();
+        let (endpoint, status, _, _) = row(&conn, "tv");
+        assert_eq!(
+            (endpoint.as_str(), status.as_str()),
+            ("10.0.0.7", "rejected")
+        );
+    }
+}
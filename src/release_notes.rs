@@ -0,0 +1,221 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::db::format_ts;
+
+const CHANGELOG: &str = include_str!("../CHANGELOG.toml");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    pub notes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Changelog {
+    release: Vec<Release>,
+}
+
+/// The release notes built into this binary, newest first.
+pub fn changelog() -> &'static [Release] {
+    static RELEASES: OnceLock<Vec<Release>> = OnceLock::new();
+    RELEASES.get_or_init(|| match toml::from_str::<Changelog>(CHANGELOG) {
+        Ok(changelog) => changelog.release,
+        Err(e) => {
+            tracing::warn!("Built-in changelog is invalid: {e}");
+            Vec::new()
+        }
+    })
+}
+
+/// Releases newer than `from` up to and including `to`, newest first.
+/// Entries whose version doesn't parse are skipped.
+pub fn releases_between<'a>(releases: &'a [Release], from: &str, to: &str) -> Vec<&'a Release> {
+    let (Ok(from), Ok(to)) = (parse(from), parse(to)) else {
+        return Vec::new();
+    };
+    releases
+        .iter()
+        .filter(|r| parse(&r.version).is_ok_and(|v| v > from && v <= to))
+        .collect()
+}
+
+fn parse(version: &str) -> Result<Version, semver::Error> {
+    Version::parse(version.trim_start_matches('v'))
+}
+
+/// Note that this run's version differs from the one that ran before.
+/// Returns false when nothing changed or there was no previous run.
+pub fn record_upgrade(
+    conn: &Connection,
+    previous: Option<&str>,
+    current: &str,
+    now: DateTime<Utc>,
+) -> rusqlite::Result<bool> {
+    match previous {
+        Some(previous) if previous != current => {
+            conn.execute(
+                "INSERT INTO upgrades (from_version, to_version, at) VALUES (?1, ?2, ?3)",
+                params![previous, current, format_ts(now)],
+            )?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// A migration's note to the owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationNotice {
+    pub migration: String,
+    pub message: String,
+    pub created_at: String,
+}
+
+/// What changed since the owner last looked.
+#[derive(Debug, Serialize)]
+pub struct WhatsNew {
+    /// Version before the earliest undismissed upgrade, if any
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+    pub releases: Vec<Release>,
+    pub notices: Vec<MigrationNotice>,
+}
+
+/// Undismissed upgrades and migration notices, or None if there are none.
+/// Several upgrades since the last dismissal are shown as one span.
+pub fn whats_new(conn: &Connection, releases: &[Release]) -> rusqlite::Result<Option<WhatsNew>> {
+    let span: (Option<String>, Option<String>) = conn.query_row(
+        "SELECT
+           (SELECT from_version FROM upgrades WHERE dismissed_at IS NULL ORDER BY id LIMIT 1),
+           (SELECT to_version FROM upgrades WHERE dismissed_at IS NULL ORDER BY id DESC LIMIT 1)",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT migration, message, created_at FROM migration_notices
+         WHERE dismissed_at IS NULL ORDER BY migration",
+    )?;
+    let notices = stmt
+        .query_map([], |row| {
+            Ok(MigrationNotice {
+                migration: row.get(0)?,
+                message: row.get(1)?,
+                created_at: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if span.1.is_none() && notices.is_empty() {
+        return Ok(None);
+    }
+    let releases = match &span {
+        (Some(from), Some(to)) => releases_between(releases, from, to)
+            .into_iter()
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(Some(WhatsNew {
+        from_version: span.0,
+        to_version: span.1,
+        releases,
+        notices,
+    }))
+}
+
+/// Hide everything `whats_new` currently shows.
+pub fn dismiss(conn: &Connection, now: DateTime<Utc>) -> rusqlite::Result<()> {
+    let now = format_ts(now);
+    conn.execute(
+        "UPDATE upgrades SET dismissed_at = ?1 WHERE dismissed_at IS NULL",
+        params![now],
+    )?;
+    conn.execute(
+        "UPDATE migration_notices SET dismissed_at = ?1 WHERE dismissed_at IS NULL",
+        params![now],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    fn releases() -> Vec<Release> {
+        ["0.4.0", "0.3.1", "0.3.0", "not-a-version", "0.2.0"]
+            .iter()
+            .map(|v| Release {
+                version: v.to_string(),
+                notes: vec![format!("{v} notes")],
+            })
+            .collect()
+    }
+
+    fn versions(releases: &[&Release]) -> Vec<String> {
+        releases.iter().map(|r| r.version.clone()).collect()
+    }
+
+    #[test]
+    fn built_in_changelog_covers_this_version() {
+        let releases = changelog();
+        assert!(releases.iter().all(|r| parse(&r.version).is_ok()));
+        assert_eq!(releases[0].version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn slice_is_after_from_up_to_to() {
+        let releases = releases();
+        assert_eq!(
+            versions(&releases_between(&releases, "0.2.0", "0.3.1")),
+            vec!["0.3.1", "0.3.0"]
+        );
+        assert!(releases_between(&releases, "0.4.0", "0.3.0").is_empty());
+        assert!(releases_between(&releases, "garbage", "0.4.0").is_empty());
+    }
+
+    #[test]
+    fn version_change_is_recorded_until_dismissed() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        let now = Utc::now();
+        let releases = releases();
+
+        assert!(!record_upgrade(&conn, None, "0.2.0", now).unwrap());
+        assert!(!record_upgrade(&conn, Some("0.2.0"), "0.2.0", now).unwrap());
+        assert!(whats_new(&conn, &releases).unwrap().is_none());
+
+        // Two upgrades before anyone looks read as one
+        assert!(record_upgrade(&conn, Some("0.2.0"), "0.3.0", now).unwrap());
+        assert!(record_upgrade(&conn, Some("0.3.0"), "0.3.1", now).unwrap());
+        conn.execute(
+            "INSERT INTO migration_notices (migration, message) VALUES ('099_x', 'Sessions were reset.')",
+            [],
+        )
+        .unwrap();
+        let new = whats_new(&conn, &releases).unwrap().unwrap();
+        assert_eq!(new.from_version.as_deref(), Some("0.2.0"));
+        assert_eq!(new.to_version.as_deref(), Some("0.3.1"));
+        assert_eq!(
+            versions(&new.releases.iter().collect::<Vec<_>>()),
+            vec!["0.3.1", "0.3.0"]
+        );
+        assert_eq!(new.notices[0].message, "Sessions were reset.");
+
+        dismiss(&conn, now).unwrap();
+        assert!(whats_new(&conn, &releases).unwrap().is_none());
+        // Dismissal sticks across later reads and applies only to what was shown
+        assert!(whats_new(&conn, &releases).unwrap().is_none());
+        record_upgrade(&conn, Some("0.3.1"), "0.4.0", now).unwrap();
+        let new = whats_new(&conn, &releases).unwrap().unwrap();
+        assert_eq!(new.from_version.as_deref(), Some("0.3.1"));
+        assert!(new.notices.is_empty());
+    }
+}
@@ -0,0 +1,520 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, OptionalExtension};
+
+use crate::admission::NOT_ADMITTED;
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::node_archive;
+use crate::peer_client::PeerClient;
+use crate::tempfiles;
+
+/// A file opened for streaming to the client, with its size and the
+/// content type to serve it with.
+#[derive(Debug)]
+pub struct CachedContent {
+    pub file: tokio::fs::File,
+    pub size: u64,
+    pub content_type: String,
+}
+
+/// On-disk cache of content fetched from other nodes, bounded by a byte
+/// budget and evicted least recently used first. Files are named by cid;
+/// the remote_cache table tracks size and last access for eviction.
+pub struct RemoteCache {
+    dir: PathBuf,
+    budget: u64,
+    max_item: u64,
+}
+
+impl RemoteCache {
+    pub fn new(dir: PathBuf, budget: u64, max_item: u64) -> Self {
+        Self {
+            dir,
+            budget,
+            max_item,
+        }
+    }
+
+    /// Remove partial downloads left behind by a crash.
+    pub fn sweep_partials(&self) -> std::io::Result<tempfiles::SweepReport> {
+        tempfiles::sweep(&self.dir, tempfiles::STALE_AGE)
+    }
+
+    /// Look up a cached copy, marking it as recently used.
+    pub async fn get(&self, pool: &DbPool, cid: &str) -> AppResult<Option<CachedContent>> {
+        if !is_cid(cid) {
+            return Ok(None);
+        }
+
+        let content_type: Option<String> = {
+            let conn = pool.get()?;
+            conn.execute(
+                "UPDATE remote_cache SET last_access = datetime('now') WHERE cid = ?1",
+                params![cid],
+            )?;
+            conn.query_row(
+                "SELECT content_type FROM remote_cache WHERE cid = ?1",
+                params![cid],
+                |row| row.get(0),
+            )
+            .optional()?
+        };
+        let Some(content_type) = content_type else {
+            return Ok(None);
+        };
+
+        match tokio::fs::File::open(self.dir.join(cid)).await {
+            Ok(file) => {
+                let size = file.metadata().await?.len();
+                Ok(Some(CachedContent {
+                    file,
+                    size,
+                    content_type,
+                }))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // File went missing behind our back; forget the entry
+                let conn = pool.get()?;
+                conn.execute("DELETE FROM remote_cache WHERE cid = ?1", params![cid])?;
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Move a verified download into the cache, then evict older entries
+    /// until the cache fits its budget. Returns false, leaving `partial`
+    /// where it is, if the content is too large to cache.
+    pub async fn admit(
+        &self,
+        pool: &DbPool,
+        cid: &str,
+        partial: &Path,
+        size: u64,
+        content_type: &str,
+    ) -> AppResult<bool> {
+        if !is_cid(cid) || size > self.max_item || size > self.budget {
+            return Ok(false);
+        }
+
+        tokio::fs::rename(partial, self.dir.join(cid)).await?;
+
+        let evicted = {
+            let conn = pool.get()?;
+            conn.execute(
+                "INSERT INTO remote_cache (cid, size, content_type) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(cid) DO UPDATE SET
+                   size = excluded.size,
+                   content_type = excluded.content_type,
+                   last_access = datetime('now')",
+                params![cid, size as i64, content_type],
+            )?;
+            self.evict(&conn)?
+        };
+
+        for cid in evicted {
+            if let Err(e) = tokio::fs::remove_file(self.dir.join(&cid)).await {
+                tracing::debug!("Failed to remove evicted cache file {cid}: {e}");
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drop any cached copies of `cids`, e.g. content from a node that was
+    /// just blocked.
+    pub async fn remove(&self, pool: &DbPool, cids: &[String]) -> AppResult<()> {
+        let removed = {
+            let mut conn = pool.get()?;
+            let tx = conn.transaction()?;
+            let mut removed = Vec::new();
+            for cid in cids {
+                if tx.execute("DELETE FROM remote_cache WHERE cid = ?1", params![cid])? > 0 {
+                    removed.push(cid);
+                }
+            }
+            tx.commit()?;
+            removed
+        };
+
+        for cid in removed {
+            if let Err(e) = tokio::fs::remove_file(self.dir.join(cid)).await {
+                tracing::debug!("Failed to remove cache file {cid}: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the least recently used entries beyond the budget, returning
+    /// the cids whose files should be removed.
+    fn evict(&self, conn: &rusqlite::Connection) -> AppResult<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT cid, size FROM remote_cache
+             ORDER BY last_access DESC, cached_at DESC, rowid DESC",
+        )?;
+        let entries = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut total = 0u64;
+        let mut evicted = Vec::new();
+        for (cid, size) in entries {
+            total += size as u64;
+            if total > self.budget {
+                conn.execute("DELETE FROM remote_cache WHERE cid = ?1", params![cid])?;
+                evicted.push(cid);
+            }
+        }
+        Ok(evicted)
+    }
+}
+
+/// Serve a remote catalog entry from the cache, fetching it from the node
+/// it came from on a miss, unless that node is archived or not a member. The download is
+/// streamed to a partial file, cut off past the size the catalog lists,
+/// and only kept if it hashes to the cid.
+pub async fn fetch_through(
+    cache: &RemoteCache,
+    pool: &DbPool,
+    client: &PeerClient,
+    cid: &str,
+) -> AppResult<CachedContent> {
+    if let Some(hit) = cache.get(pool, cid).await? {
+        return Ok(hit);
+    }
+
+    let (endpoint, port, base_path, size, mime): (String, u16, String, i64, Option<String>) = {
+        let conn = pool.get()?;
+        let (origin, endpoint, port, base_path, size, mime): (String, _, _, _, _, _) = conn
+            .query_row(
+                &format!(
+                    "SELECT d.id, d.endpoint, d.port, d.base_path, c.size, c.mime
+                     FROM content_index c
+                     JOIN devices d ON d.id = c.origin_node
+                     WHERE c.cid = ?1 AND c.is_local = 0 AND d.endpoint IS NOT NULL
+                       AND NOT {NOT_ADMITTED}"
+                ),
+                params![cid],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .optional()?
+            .ok_or(AppError::NotFound)?;
+        node_archive::check_active(&conn, &origin)?;
+        (endpoint, port, base_path, size, mime)
+    };
+
+    // Unique per request, so concurrent misses for one cid don't collide
+    tokio::fs::create_dir_all(&cache.dir).await?;
+    let name = format!("{cid}-{}", uuid::Uuid::now_v7());
+    let partial = tempfiles::temp_path(&cache.dir.join(name));
+    let cap = size.max(0) as u64;
+    let fetched = client
+        .fetch_content(&endpoint, port, &base_path, cid, &partial, cap)
+        .await
+        .and_then(|(hash, content_type)| {
+            if hash != cid {
+                return Err(AppError::PeerUnavailable(format!(
+                    "content from {endpoint} does not match cid {cid}"
+                )));
+            }
+            Ok(content_type)
+        });
+    let content_type = match fetched {
+        Ok(content_type) => content_type
+            .or(mime)
+            .unwrap_or_else(|| "application/octet-stream".to_string()),
+        Err(e) => {
+            discard(&partial).await;
+            return Err(e);
+        }
+    };
+
+    // Opened before it moves, so an immediate eviction can't pull it out
+    // from under the response
+    let file = tokio::fs::File::open(&partial).await?;
+    let size = file.metadata().await?.len();
+    match cache.admit(pool, cid, &partial, size, &content_type).await {
+        Ok(true) => {}
+        Ok(false) => discard(&partial).await,
+        Err(e) => {
+            tracing::warn!("Failed to cache remote content {cid}: {e}");
+            discard(&partial).await;
+        }
+    }
+    Ok(CachedContent {
+        file,
+        size,
+        content_type,
+    })
+}
+
+/// Remove a partial download. One left behind is swept on the next start.
+async fn discard(partial: &Path) {
+    if let Err(e) = tokio::fs::remove_file(partial).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::debug!("Failed to remove {}: {e}", partial.display());
+        }
+    }
+}
+
+/// Cids are hex BLAKE3 hashes; anything else must not become a file name.
+fn is_cid(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+    use crate::error::ErrorCode;
+    use axum::routing::get;
+    use axum::Router;
+
+    fn cid_of(bytes: &[u8]) -> String {
+        blake3::hash(bytes).to_hex().to_string()
+    }
+
+    /// Admit `bytes` to the cache as if just downloaded.
+    async fn admit(cache: &RemoteCache, pool: &DbPool, bytes: &[u8]) -> (String, bool) {
+        let cid = cid_of(bytes);
+        let partial = tempfiles::temp_path(&cache.dir.join(&cid));
+        tokio::fs::write(&partial, bytes).await.unwrap();
+        let admitted = cache
+            .admit(pool, &cid, &partial, bytes.len() as u64, "text/plain")
+            .await
+            .unwrap();
+        (cid, admitted)
+    }
+
+    async fn read_all(content: CachedContent) -> Vec<u8> {
+        use tokio::io::AsyncReadExt;
+        let mut bytes = Vec::new();
+        let mut file = content.file;
+        file.read_to_end(&mut bytes).await.unwrap();
+        assert_eq!(bytes.len() as u64, content.size);
+        bytes
+    }
+
+    fn files_in(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    /// Serve `body` at /api/v1/content/{cid} like a peer would.
+    async fn stub_peer(body: &'static str) -> u16 {
+        let app = Router::new().route("/api/v1/content/{cid}", get(move || async move { body }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        port
+    }
+
+    fn add_remote_entry(pool: &DbPool, cid: &str, port: u16) {
+        let conn = pool.get().unwrap();
+        conn.execute(
+            "INSERT INTO devices (id, name, endpoint, port, status) VALUES ('peer', 'peer', '127.0.0.1', ?1, 'online')",
+            params![port],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO content_index (cid, dir, path, filename, size, origin_node, is_local)
+             VALUES (?1, 'd', 'a.txt', 'a.txt', 5, 'peer', 0)",
+            params![cid],
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn admit_then_get_round_trips() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+
+        assert!(cache.get(&pool, &cid_of(b"hello")).await.unwrap().is_none());
+        let (cid, admitted) = admit(&cache, &pool, b"hello").await;
+        assert!(admitted);
+        let hit = cache.get(&pool, &cid).await.unwrap().unwrap();
+        assert_eq!(hit.content_type, "text/plain");
+        assert_eq!(read_all(hit).await, b"hello");
+        assert_eq!(files_in(tmp.path()), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_items_are_not_cached() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 4);
+
+        let (cid, admitted) = admit(&cache, &pool, b"hello").await;
+        assert!(!admitted);
+        assert!(cache.get(&pool, &cid).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_is_evicted_over_budget() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 10, 10);
+
+        let (old_cid, _) = admit(&cache, &pool, b"aaaa").await;
+        let (used_cid, _) = admit(&cache, &pool, b"bbbb").await;
+        pool.get()
+            .unwrap()
+            .execute(
+                "UPDATE remote_cache SET last_access = datetime('now', '-1 hour') WHERE cid = ?1",
+                params![old_cid],
+            )
+            .unwrap();
+
+        let (new_cid, _) = admit(&cache, &pool, b"cccc").await;
+        assert!(cache.get(&pool, &old_cid).await.unwrap().is_none());
+        assert!(!tmp.path().join(&old_cid).exists());
+        assert!(cache.get(&pool, &used_cid).await.unwrap().is_some());
+        assert!(cache.get(&pool, &new_cid).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn removed_entries_are_gone_from_disk() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let (blocked, _) = admit(&cache, &pool, b"blocked").await;
+        let (kept, _) = admit(&cache, &pool, b"kept").await;
+
+        cache
+            .remove(&pool, &[blocked.clone(), cid_of(b"never cached")])
+            .await
+            .unwrap();
+        assert!(cache.get(&pool, &blocked).await.unwrap().is_none());
+        assert!(!tmp.path().join(&blocked).exists());
+        assert!(cache.get(&pool, &kept).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn miss_fetches_from_origin_then_hits_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let cid = cid_of(b"hello");
+        add_remote_entry(&pool, &cid, stub_peer("hello").await);
+
+        let client = PeerClient::new();
+        let fetched = fetch_through(&cache, &pool, &client, &cid).await.unwrap();
+        assert_eq!(fetched.content_type, "text/plain; charset=utf-8");
+        assert_eq!(read_all(fetched).await, b"hello");
+        assert_eq!(files_in(tmp.path()), 1);
+
+        // Origin goes away; the cached copy still serves
+        pool.get()
+            .unwrap()
+            .execute("UPDATE devices SET port = 1", [])
+            .unwrap();
+        let cached = fetch_through(&cache, &pool, &client, &cid).await.unwrap();
+        assert_eq!(cached.content_type, "text/plain; charset=utf-8");
+        assert_eq!(read_all(cached).await, b"hello");
+    }
+
+    #[tokio::test]
+    async fn offline_origin_is_reported() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let cid = cid_of(b"hello");
+        add_remote_entry(&pool, &cid, 1);
+
+        let err = fetch_through(&cache, &pool, &PeerClient::new(), &cid)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::PeerUnavailable(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn archived_origin_is_not_contacted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let cid = cid_of(b"hello");
+        add_remote_entry(&pool, &cid, stub_peer("hello").await);
+        node_archive::archive(&pool.get().unwrap(), "peer").unwrap();
+
+        let err = fetch_through(&cache, &pool, &PeerClient::new(), &cid)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                AppError::Conflict {
+                    code: ErrorCode::NodeArchived,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn origin_outside_the_mesh_is_not_contacted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let cid = cid_of(b"hello");
+        add_remote_entry(&pool, &cid, stub_peer("hello").await);
+
+        for status in ["pending", "rejected"] {
+            pool.get()
+                .unwrap()
+                .execute("UPDATE devices SET status = ?1", params![status])
+                .unwrap();
+            let err = fetch_through(&cache, &pool, &PeerClient::new(), &cid)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, AppError::NotFound), "{status}: {err}");
+        }
+        assert_eq!(files_in(tmp.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn content_not_matching_cid_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        let cid = cid_of(b"hello");
+        add_remote_entry(&pool, &cid, stub_peer("tampered").await);
+
+        let err = fetch_through(&cache, &pool, &PeerClient::new(), &cid)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::PeerUnavailable(_)), "{err}");
+        assert!(cache.get(&pool, &cid).await.unwrap().is_none());
+        assert_eq!(files_in(tmp.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn download_past_the_catalog_size_is_cut_off() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = test_pool();
+        let cache = RemoteCache::new(tmp.path().to_path_buf(), 1024, 1024);
+        // Listed as 5 bytes, but the peer keeps sending
+        let cid = cid_of(b"hello, and much more");
+        add_remote_entry(&pool, &cid, stub_peer("hello, and much more").await);
+
+        let err = fetch_through(&cache, &pool, &PeerClient::new(), &cid)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, AppError::PeerUnavailable(msg) if msg.contains("more than")),
+            "{err}"
+        );
+        assert_eq!(files_in(tmp.path()), 0);
+    }
+}
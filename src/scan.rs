@@ -0,0 +1,321 @@
+use std::ffi::OsString;
+use std::path::{Component, Path};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::config::StorageConfig;
+use crate::error::{AppError, AppResult, ErrorCode};
+
+/// Indexed but the scanner hasn't finished with it yet.
+pub const PENDING_SCAN: &str = "pending_scan";
+/// Scanned and passed, or indexed while scanning was off.
+pub const CLEAN: &str = "clean";
+/// The scanner objected; the file is quarantined until reviewed.
+pub const FLAGGED: &str = "flagged";
+/// Integrity verification found the file changed without its
+/// modification time moving. Cleared when it verifies again or changes.
+pub const CORRUPT: &str = "corrupt";
+
+/// Placeholder in `scan_command` replaced by the file's path.
+const PATH_PLACEHOLDER: &str = "%f";
+
+/// What to do with a file when the scanner times out or fails to run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanFailurePolicy {
+    /// Treat it as clean
+    Open,
+    /// Quarantine it as if flagged
+    #[default]
+    Closed,
+}
+
+/// Runs the configured scan command against files as they are indexed.
+///
+/// Exit status 0 means clean and 1 means flagged, the convention of
+/// `clamdscan` and most scanners; anything else, a timeout or a command
+/// that won't start is a failure and `on_failure` decides.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+    on_failure: ScanFailurePolicy,
+}
+
+impl Scanner {
+    /// The command is split on whitespace into a program and arguments;
+    /// quoting isn't interpreted and no shell is involved. The path is
+    /// passed in place of `%f`, or appended when `%f` doesn't appear.
+    pub fn new(command: &str, timeout: Duration, on_failure: ScanFailurePolicy) -> Option<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next()?;
+        Some(Self {
+            program,
+            args: words.collect(),
+            timeout,
+            on_failure,
+        })
+    }
+
+    /// The scanner `storage` configures, or None when scanning is off.
+    pub fn from_config(storage: &StorageConfig) -> Option<Self> {
+        Self::new(
+            &storage.scan_command,
+            Duration::from_secs(storage.scan_timeout_secs),
+            storage.scan_failure,
+        )
+    }
+
+    /// Scan one file, returning `CLEAN` or `FLAGGED`.
+    pub fn scan(&self, path: &Path) -> &'static str {
+        match self.run(path) {
+            Ok(Some(0)) => CLEAN,
+            Ok(Some(1)) => {
+                tracing::warn!("Scanner flagged {}", path.display());
+                FLAGGED
+            }
+            outcome => {
+                let reason = match outcome {
+                    Ok(Some(code)) => format!("exited with {code}"),
+                    Ok(None) => format!("timed out after {:?}", self.timeout),
+                    Err(e) => e.to_string(),
+                };
+                tracing::warn!("Scanning {} failed: {reason}", path.display());
+                match self.on_failure {
+                    ScanFailurePolicy::Open => CLEAN,
+                    ScanFailurePolicy::Closed => FLAGGED,
+                }
+            }
+        }
+    }
+
+    /// Run the command, returning its exit code, or None if it timed out
+    /// (or was killed by a signal).
+    fn run(&self, path: &Path) -> std::io::Result<Option<i32>> {
+        let mut command = Command::new(&self.program);
+        let mut substituted = false;
+        for arg in &self.args {
+            if arg == PATH_PLACEHOLDER {
+                command.arg(path);
+                substituted = true;
+            } else if arg.contains(PATH_PLACEHOLDER) {
+                let mut replaced = OsString::new();
+                let mut parts = arg.split(PATH_PLACEHOLDER);
+                replaced.push(parts.next().unwrap_or_default());
+                for part in parts {
+                    replaced.push(path);
+                    replaced.push(part);
+                }
+                command.arg(replaced);
+                substituted = true;
+            } else {
+                command.arg(arg);
+            }
+        }
+        if !substituted {
+            command.arg(path);
+        }
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status.code());
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+/// A quarantined file awaiting review.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlaggedFile {
+    pub cid: String,
+    pub dir: String,
+    pub path: String,
+    pub size: i64,
+    pub indexed_at: String,
+}
+
+/// Refuse a local file unless it scanned clean. With scanning off every
+/// file is available; with it on, a file the indexer hasn't reached yet
+/// counts as pending.
+pub fn check_available(
+    conn: &Connection,
+    scanning: bool,
+    dir: &str,
+    rel_path: &str,
+) -> AppResult<()> {
+    let rel_path = normalize(rel_path);
+    let status: Option<String> = conn
+        .query_row(
+            "SELECT scan_status FROM content_index WHERE dir = ?1 AND path = ?2",
+            params![dir, rel_path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match status.as_deref() {
+        Some(CLEAN) => Ok(()),
+        Some(FLAGGED) => Err(AppError::Forbidden {
+            code: ErrorCode::Quarantined,
+            message: "this file was flagged by the content scanner".into(),
+        }),
+        Some(CORRUPT) => Err(AppError::Forbidden {
+            code: ErrorCode::Corrupt,
+            message: "this file failed integrity verification".into(),
+        }),
+        None if !scanning => Ok(()),
+        _ => Err(AppError::Forbidden {
+            code: ErrorCode::PendingScan,
+            message: "this file hasn't been scanned yet".into(),
+        }),
+    }
+}
+
+/// `rel_path` the way the indexer stores it: no leading slash or `.`.
+fn normalize(rel_path: &str) -> String {
+    Path::new(rel_path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn list_flagged(conn: &Connection) -> rusqlite::Result<Vec<FlaggedFile>> {
+    let mut stmt = conn.prepare(
+        "SELECT cid, dir, path, size, indexed_at FROM content_index
+         WHERE is_local = 1 AND scan_status = ?1
+         ORDER BY indexed_at DESC",
+    )?;
+    let rows = stmt.query_map(params![FLAGGED], |row| {
+        Ok(FlaggedFile {
+            cid: row.get(0)?,
+            dir: row.get(1)?,
+            path: row.get(2)?,
+            size: row.get(3)?,
+            indexed_at: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Mark a flagged file clean after review. Returns false if no flagged
+/// file has this cid.
+pub fn release(conn: &Connection, cid: &str) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        "UPDATE content_index SET scan_status = ?2 WHERE cid = ?1 AND scan_status = ?3",
+        params![cid, CLEAN, FLAGGED],
+    )?;
+    Ok(updated > 0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Write an executable shell script standing in for a scanner.
+    fn stub(dir: &Path, name: &str, body: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn scanner(program: &str, args: &str, on_failure: ScanFailurePolicy) -> Scanner {
+        Scanner::new(
+            &format!("{program} {args}"),
+            Duration::from_millis(500),
+            on_failure,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn exit_status_decides_clean_or_flagged() {
+        let tmp = tempfile::tempdir().unwrap();
+        // Flags any file whose contents mention EICAR
+        let program = stub(
+            tmp.path(),
+            "scan",
+            r#"grep -q EICAR "$2" && exit 1; exit 0"#,
+        );
+        let clean = tmp.path().join("clean.txt");
+        let infected = tmp.path().join("it's $(bad).txt");
+        std::fs::write(&clean, "hello").unwrap();
+        std::fs::write(&infected, "EICAR test").unwrap();
+
+        let scanner = scanner(&program, "--no-summary %f", ScanFailurePolicy::Closed);
+        assert_eq!(scanner.scan(&clean), CLEAN);
+        // The name reaches the script as one argument, unexpanded
+        assert_eq!(scanner.scan(&infected), FLAGGED);
+    }
+
+    #[test]
+    fn failures_follow_the_policy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.jpg");
+        std::fs::write(&file, "x").unwrap();
+        let slow = stub(tmp.path(), "slow", "sleep 5");
+        let broken = stub(tmp.path(), "broken", "exit 2");
+
+        for program in [slow.as_str(), broken.as_str(), "/nonexistent/scanner"] {
+            let open = scanner(program, "", ScanFailurePolicy::Open);
+            let closed = scanner(program, "", ScanFailurePolicy::Closed);
+            assert_eq!(open.scan(&file), CLEAN, "{program}");
+            assert_eq!(closed.scan(&file), FLAGGED, "{program}");
+        }
+    }
+
+    #[test]
+    fn empty_command_disables_scanning() {
+        assert!(Scanner::from_config(&StorageConfig::default()).is_none());
+        assert!(Scanner::new("   ", Duration::from_secs(1), ScanFailurePolicy::Open).is_none());
+    }
+
+    #[test]
+    fn availability_follows_scan_status() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+        conn.execute_batch(
+            "INSERT INTO content_index (cid, dir, path, filename, size, is_local, scan_status)
+             VALUES ('ok', 'd', 'sub/ok.jpg', 'ok.jpg', 1, 1, 'clean'),
+                    ('bad', 'd', 'bad.jpg', 'bad.jpg', 1, 1, 'flagged'),
+                    ('new', 'd', 'new.jpg', 'new.jpg', 1, 1, 'pending_scan')",
+        )
+        .unwrap();
+
+        assert!(check_available(&conn, true, "d", "/sub/./ok.jpg").is_ok());
+        assert!(matches!(
+            check_available(&conn, true, "d", "bad.jpg"),
+            Err(AppError::Forbidden {
+                code: ErrorCode::Quarantined,
+                ..
+            })
+        ));
+        assert!(check_available(&conn, true, "d", "new.jpg").is_err());
+        assert!(check_available(&conn, true, "d", "unindexed.jpg").is_err());
+        assert!(check_available(&conn, false, "d", "unindexed.jpg").is_ok());
+
+        assert_eq!(list_flagged(&conn).unwrap().len(), 1);
+        assert!(release(&conn, "bad").unwrap());
+        assert!(!release(&conn, "ok").unwrap());
+        assert!(check_available(&conn, true, "d", "bad.jpg").is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+/// Longest error message kept in peer_sync_status.last_error.
+const MAX_ERROR_LEN: usize = 500;
+
+/// A successful exchange within this many seconds counts as fresh.
+const FRESH_SECS: f64 = 15.0 * 60.0;
+
+/// Past this many seconds without a successful exchange a peer is stale.
+const STALE_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// What kind of data a sync run moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncKind {
+    Catalog,
+}
+
+impl SyncKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SyncKind::Catalog => "catalog",
+        }
+    }
+}
+
+/// Traffic-light freshness of a peer's last successful exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Freshness {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Classify a peer by seconds since its last successful exchange.
+pub fn classify(age_secs: Option<f64>) -> Freshness {
+    match age_secs {
+        Some(age) if age < FRESH_SECS => Freshness::Green,
+        Some(age) if age < STALE_SECS => Freshness::Yellow,
+        _ => Freshness::Red,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerSyncStatus {
+    pub node_id: String,
+    pub node_name: Option<String>,
+    pub sync_kind: String,
+    pub last_attempt_at: Option<String>,
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    pub items_transferred: i64,
+    pub freshness: Freshness,
+}
+
+/// Record a successful exchange of `items` entries with `node_id`.
+pub fn record_success(
+    conn: &Connection,
+    node_id: &str,
+    kind: SyncKind,
+    items: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO peer_sync_status
+           (node_id, sync_kind, last_attempt_at, last_success_at, last_error, items_transferred)
+         VALUES (?1, ?2, datetime('now'), datetime('now'), NULL, ?3)
+         ON CONFLICT(node_id, sync_kind) DO UPDATE SET
+           last_attempt_at = excluded.last_attempt_at,
+           last_success_at = excluded.last_success_at,
+           last_error = NULL,
+           items_transferred = items_transferred + excluded.items_transferred",
+        params![node_id, kind.as_str(), items],
+    )?;
+    Ok(())
+}
+
+/// Record a failed exchange with `node_id`, keeping the previous success time.
+pub fn record_failure(
+    conn: &Connection,
+    node_id: &str,
+    kind: SyncKind,
+    error: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO peer_sync_status (node_id, sync_kind, last_attempt_at, last_error)
+         VALUES (?1, ?2, datetime('now'), ?3)
+         ON CONFLICT(node_id, sync_kind) DO UPDATE SET
+           last_attempt_at = excluded.last_attempt_at,
+           last_error = excluded.last_error",
+        params![node_id, kind.as_str(), sanitize_error(error)],
+    )?;
+    Ok(())
+}
+
+/// Strip control characters and truncate so peer-supplied error text
+/// can't bloat the table or smuggle terminal escapes into the UI.
+fn sanitize_error(error: &str) -> String {
+    let cleaned: String = error
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.chars().count() <= MAX_ERROR_LEN {
+        cleaned.to_string()
+    } else {
+        let mut truncated: String = cleaned.chars().take(MAX_ERROR_LEN - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// List sync status for every peer, most recently attempted first.
+pub fn list_status(conn: &Connection) -> rusqlite::Result<Vec<PeerSyncStatus>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.node_id, d.name, s.sync_kind, s.last_attempt_at, s.last_success_at,
+                s.last_error, s.items_transferred,
+                (julianday('now') - julianday(s.last_success_at)) * 86400.0
+         FROM peer_sync_status s
+         LEFT JOIN devices d ON d.id = s.node_id
+         ORDER BY s.last_attempt_at DESC",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok(PeerSyncStatus {
+            node_id: row.get(0)?,
+            node_name: row.get(1)?,
+            sync_kind: row.get(2)?,
+            last_attempt_at: row.get(3)?,
+            last_success_at: row.get(4)?,
+            last_error: row.get(5)?,
+            items_transferred: row.get(6)?,
+            freshness: classify(row.get(7)?),
+        })
+    })?;
+
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    #[test]
+    fn classify_thresholds() {
+        assert_eq!(classify(Some(0.0)), Freshness::Green);
+        assert_eq!(classify(Some(FRESH_SECS - 1.0)), Freshness::Green);
+        assert_eq!(classify(Some(FRESH_SECS)), Freshness::Yellow);
+        assert_eq!(classify(Some(STALE_SECS - 1.0)), Freshness::Yellow);
+        assert_eq!(classify(Some(STALE_SECS)), Freshness::Red);
+        assert_eq!(classify(None), Freshness::Red);
+    }
+
+    #[test]
+    fn success_accumulates_and_clears_error() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        record_success(&conn, "peer", SyncKind::Catalog, 3).unwrap();
+        record_failure(&conn, "peer", SyncKind::Catalog, "connection reset").unwrap();
+
+        let status = list_status(&conn).unwrap();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].items_transferred, 3);
+        assert_eq!(status[0].last_error.as_deref(), Some("connection reset"));
+        assert!(status[0].last_success_at.is_some());
+        assert_eq!(status[0].freshness, Freshness::Green);
+
+        record_success(&conn, "peer", SyncKind::Catalog, 2).unwrap();
+        let status = list_status(&conn).unwrap();
+        assert_eq!(status[0].items_transferred, 5);
+        assert_eq!(status[0].last_error, None);
+    }
+
+    #[test]
+    fn failure_without_success_is_red() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+
+        record_failure(&conn, "peer", SyncKind::Catalog, "boom").unwrap();
+        let status = list_status(&conn).unwrap();
+        assert_eq!(status[0].last_success_at, None);
+        assert_eq!(status[0].freshness, Freshness::Red);
+    }
+
+    #[test]
+    fn sanitize_error_strips_control_chars_and_truncates() {
+        assert_eq!(sanitize_error("bad\x1b[31m\nthing"), "bad [31m thing");
+
+        let long = "x".repeat(MAX_ERROR_LEN * 2);
+        let sanitized = sanitize_error(&long);
+        assert_eq!(sanitized.chars().count(), MAX_ERROR_LEN);
+        assert!(sanitized.ends_with('…'));
+    }
+}
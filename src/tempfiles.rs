@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Marks a file that is still being written. A crash mid-write leaves one
+/// of these behind instead of a truncated file under the real name.
+const TEMP_SUFFIX: &str = ".partial";
+
+/// Partial files older than this are assumed abandoned by `sweep`.
+pub const STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where to write `target` before moving it into place. The temp file is a
+/// hidden sibling, so the final rename never crosses filesystems and the
+/// indexer skips it like any other dot file.
+pub fn temp_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{name}{TEMP_SUFFIX}"))
+}
+
+/// Files and bytes removed by a sweep.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SweepReport {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Delete partial files in `dir` (not its subdirectories) that haven't
+/// been modified for `max_age`.
+pub fn sweep(dir: &Path, max_age: Duration) -> std::io::Result<SweepReport> {
+    let mut report = SweepReport::default();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+        Err(e) => return Err(e),
+    };
+
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with('.') || !name.ends_with(TEMP_SUFFIX) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|m| now.duration_since(m).ok())
+            .unwrap_or_default();
+        if !metadata.is_file() || age < max_age {
+            continue;
+        }
+
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => {
+                report.files += 1;
+                report.bytes += metadata.len();
+            }
+            Err(e) => tracing::warn!("Failed to remove {}: {e}", entry.path().display()),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_aged(path: &Path, contents: &str, age: Duration) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[test]
+    fn temp_path_is_a_hidden_sibling() {
+        assert_eq!(
+            temp_path(Path::new("/data/cache/abc")),
+            PathBuf::from("/data/cache/.abc.partial")
+        );
+    }
+
+    #[test]
+    fn sweep_removes_only_old_partials() {
+        let tmp = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let old = temp_path(&tmp.path().join("old"));
+        let fresh = temp_path(&tmp.path().join("fresh"));
+        let kept = tmp.path().join("old.jpg");
+        write_aged(&old, "abandoned", 2 * day);
+        write_aged(&fresh, "in flight", Duration::from_secs(60));
+        write_aged(&kept, "real file", 2 * day);
+
+        let report = sweep(tmp.path(), STALE_AGE).unwrap();
+        assert_eq!(report, SweepReport { files: 1, bytes: 9 });
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        assert!(kept.exists());
+    }
+
+    #[test]
+    fn sweep_of_missing_dir_is_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        let report = sweep(&tmp.path().join("missing"), STALE_AGE).unwrap();
+        assert_eq!(report, SweepReport::default());
+    }
+}
@@ -0,0 +1,209 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::scan;
+
+/// Days a remote delete is remembered. Long enough for any peer that was
+/// offline to have caught up on the catalog document.
+const RETENTION_DAYS: i64 = 30;
+
+/// Queue the catalog entries of local files at `dir`/`path` other than
+/// `cid` for deletion from the catalog document; the file changed, so
+/// they describe content this node no longer has. Call before the stale
+/// rows are removed.
+pub fn queue_stale(conn: &Connection, dir: &str, path: &str, cid: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO catalog_retractions (cid)
+         SELECT cid FROM content_index
+         WHERE dir = ?1 AND path = ?2 AND cid != ?3 AND is_local = 1 AND scan_status = ?4",
+        params![dir, path, cid, scan::CLEAN],
+    )?;
+    Ok(())
+}
+
+/// The local file at `dir`/`path` is gone from disk, deleted or moved
+/// away: drop its entry and queue it for deletion from the catalog
+/// document, in one transaction. Returns whether an entry was removed.
+pub fn remove_vanished(conn: &mut Connection, dir: &str, path: &str) -> rusqlite::Result<bool> {
+    let tx = conn.transaction()?;
+    // Corrupt files were published before they were found corrupt
+    tx.execute(
+        "INSERT OR IGNORE INTO catalog_retractions (cid)
+         SELECT cid FROM content_index
+         WHERE dir = ?1 AND path = ?2 AND is_local = 1 AND scan_status IN (?3, ?4)",
+        params![dir, path, scan::CLEAN, scan::CORRUPT],
+    )?;
+    let removed = tx.execute(
+        "DELETE FROM content_index WHERE dir = ?1 AND path = ?2 AND is_local = 1",
+        params![dir, path],
+    )?;
+    tx.commit()?;
+    Ok(removed > 0)
+}
+
+/// Cids waiting to be deleted from the catalog document. One that is
+/// indexed locally again (the old contents came back) is dropped instead.
+pub fn pending_retractions(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.execute(
+        "DELETE FROM catalog_retractions
+         WHERE cid IN (SELECT cid FROM content_index WHERE is_local = 1)",
+        [],
+    )?;
+    let mut stmt = conn.prepare("SELECT cid FROM catalog_retractions ORDER BY created_at")?;
+    let cids = stmt.query_map([], |row| row.get(0))?;
+    cids.collect()
+}
+
+/// The catalog entry for `cid` was deleted from the document.
+pub fn retracted(conn: &Connection, cid: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM catalog_retractions WHERE cid = ?1",
+        params![cid],
+    )?;
+    Ok(())
+}
+
+/// Apply a delete `author` made at `timestamp` (catalog document
+/// microseconds): remember it, and drop the remote entry with its
+/// thumbnail and preview. Local files are never touched. Returns whether
+/// an entry was removed.
+pub fn apply_remote_delete(
+    conn: &Connection,
+    cid: &str,
+    author: &str,
+    timestamp: u64,
+) -> rusqlite::Result<bool> {
+    conn.execute(
+        "INSERT INTO catalog_tombstones (cid, author, deleted_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(cid, author) DO UPDATE SET
+           deleted_at = MAX(deleted_at, excluded.deleted_at),
+           recorded_at = datetime('now')",
+        params![cid, author, timestamp as i64],
+    )?;
+    let removed = conn.execute(
+        "DELETE FROM content_index WHERE cid = ?1 AND is_local = 0",
+        params![cid],
+    )?;
+    prune(conn)?;
+    Ok(removed > 0)
+}
+
+/// Whether an entry `author` wrote at `timestamp` has since been deleted,
+/// e.g. a create that arrived after its delete. A delete and an edit with
+/// the same timestamp resolve in favour of the delete.
+pub fn is_deleted(
+    conn: &Connection,
+    cid: &str,
+    author: &str,
+    timestamp: u64,
+) -> rusqlite::Result<bool> {
+    let deleted_at: Option<i64> = conn
+        .query_row(
+            "SELECT deleted_at FROM catalog_tombstones WHERE cid = ?1 AND author = ?2",
+            params![cid, author],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(deleted_at.is_some_and(|at| at >= timestamp as i64))
+}
+
+/// Forget deletes older than the retention period.
+pub fn prune(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM catalog_tombstones WHERE recorded_at < datetime('now', ?1)",
+        params![format!("-{RETENTION_DAYS} days")],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_pool;
+
+
+    fn add_entry(conn: &Connection, cid: &str, is_local: bool) {
+        conn.execute(
+            "INSERT INTO content_index (cid, dir, path, filename, size, is_local)
+             VALUES (?1, 'd', ?1, ?1, 1, ?2)",
+            params![cid, is_local],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO content_thumbnails (cid, thumbnail, width, height)
+             VALUES (?1, x'00', 1, 1)",
+            params![cid],
+        )
+        .unwrap();
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn remote_delete_removes_the_entry_and_its_thumbnail() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add_entry(&conn, "remote", false);
+        add_entry(&conn, "mine", true);
+
+        assert!(apply_remote_delete(&conn, "remote", "laptop", 100).unwrap());
+        assert!(!apply_remote_delete(&conn, "mine", "laptop", 100).unwrap());
+        // Applying the same delete again is harmless
+        assert!(!apply_remote_delete(&conn, "remote", "laptop", 100).unwrap());
+
+        assert_eq!(count(&conn, "content_index"), 1);
+        assert_eq!(count(&conn, "content_thumbnails"), 1);
+    }
+
+    #[test]
+    fn entries_older_than_the_delete_stay_deleted() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        apply_remote_delete(&conn, "a", "laptop", 100).unwrap();
+        // An older delete doesn't move the tombstone back
+        apply_remote_delete(&conn, "a", "laptop", 50).unwrap();
+
+        // The create arriving after its delete, and an edit racing it
+        assert!(is_deleted(&conn, "a", "laptop", 90).unwrap());
+        assert!(is_deleted(&conn, "a", "laptop", 100).unwrap());
+        // Re-added later, or still published by another node
+        assert!(!is_deleted(&conn, "a", "laptop", 101).unwrap());
+        assert!(!is_deleted(&conn, "a", "nas", 90).unwrap());
+    }
+
+    #[test]
+    fn old_tombstones_are_pruned() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        apply_remote_delete(&conn, "old", "laptop", 1).unwrap();
+        conn.execute(
+            "UPDATE catalog_tombstones SET recorded_at = datetime('now', '-31 days')",
+            [],
+        )
+        .unwrap();
+        apply_remote_delete(&conn, "new", "laptop", 2).unwrap();
+
+        assert!(!is_deleted(&conn, "old", "laptop", 1).unwrap());
+        assert!(is_deleted(&conn, "new", "laptop", 2).unwrap());
+    }
+
+    #[test]
+    fn retractions_skip_contents_that_came_back() {
+        let pool = test_pool();
+        let conn = pool.get().unwrap();
+        add_entry(&conn, "v1", true);
+        add_entry(&conn, "v2", true);
+        queue_stale(&conn, "d", "v1", "v3").unwrap();
+        queue_stale(&conn, "d", "v2", "v3").unwrap();
+        conn.execute("DELETE FROM content_index WHERE cid = 'v1'", [])
+            .unwrap();
+
+        // v2 is still indexed locally, so its entry must stay published
+        assert_eq!(pending_retractions(&conn).unwrap(), vec!["v1"]);
+        retracted(&conn, "v1").unwrap();
+        assert!(pending_retractions(&conn).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use iroh::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+use crate::config::UpdatesConfig;
+
+/// Minimum time between two fetches of the release manifest.
+const CHECK_INTERVAL_SECS: i64 = 24 * 60 * 60;
+
+/// How often the background task wakes to see whether a check is due.
+const WAKE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const STATE_FILE: &str = "update_check.json";
+
+/// Hex ed25519 public key of the maintainers' release signing key. Built
+/// in rather than read from the config, so anyone able to edit the config
+/// can't also vouch for a manifest of their own.
+///
+/// The project hasn't published a release key yet, so there is nothing
+/// to verify against and the check stays off even when enabled. Once the
+/// key exists, set it here; each manifest is then published as a
+/// `SignedManifest` whose signature is made with its secret half over the
+/// exact bytes of the payload.
+const RELEASE_PUBLIC_KEY: Option<&str> = None;
+
+/// What the release endpoint serves: the manifest JSON as a string,
+/// exactly as signed, plus a hex ed25519 signature over its bytes.
+#[derive(Debug, Deserialize)]
+struct SignedManifest {
+    payload: String,
+    signature: String,
+}
+
+/// Latest release as announced by the release endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub notes_url: Option<String>,
+}
+
+/// Persisted between runs so restarts don't re-fetch the manifest.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CheckState {
+    checked_at: Option<DateTime<Utc>>,
+    latest: Option<UpdateManifest>,
+}
+
+/// The newer release, if the last check found one.
+#[derive(Default)]
+pub struct UpdateStatus {
+    available: Mutex<Option<UpdateManifest>>,
+}
+
+impl UpdateStatus {
+    pub fn available(&self) -> Option<UpdateManifest> {
+        self.available.lock().ok().and_then(|a| a.clone())
+    }
+
+    pub(crate) fn set(&self, manifest: Option<UpdateManifest>) {
+        if let Ok(mut available) = self.available.lock() {
+            *available = manifest;
+        }
+    }
+}
+
+/// Verify a signed manifest and parse the payload it covers.
+pub fn verify_manifest(body: &[u8], key: &PublicKey) -> anyhow::Result<UpdateManifest> {
+    let signed: SignedManifest = serde_json::from_slice(body)?;
+
+    let signature: [u8; Signature::LENGTH] = hex::decode(signed.signature.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Signature has the wrong length"))?;
+    key.verify(
+        signed.payload.as_bytes(),
+        &Signature::from_bytes(&signature),
+    )
+    .map_err(|_| anyhow::anyhow!("Manifest signature does not verify"))?;
+
+    Ok(serde_json::from_str(&signed.payload)?)
+}
+
+/// Whether `manifest` announces a release newer than `current`.
+pub fn is_newer(manifest: &UpdateManifest, current: &str) -> anyhow::Result<bool> {
+    let latest = semver::Version::parse(manifest.version.trim_start_matches('v'))?;
+    Ok(latest > semver::Version::parse(current)?)
+}
+
+/// Whether a day has passed since the last successful check.
+pub fn check_due(checked_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    checked_at.is_none_or(|at| (now - at).num_seconds() >= CHECK_INTERVAL_SECS)
+}
+
+/// Spawn the opt-in background check. Never blocks startup, and network
+/// failures are only logged at debug level.
+pub fn spawn_update_check(config: &UpdatesConfig, data_dir: &Path, status: Arc<UpdateStatus>) {
+    if !config.check {
+        return;
+    }
+    let Some(key) = RELEASE_PUBLIC_KEY else {
+        tracing::info!("Update check unavailable: this build has no release key");
+        return;
+    };
+    let key: PublicKey = match key.parse() {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::warn!("Update check disabled: invalid release key: {e}");
+            return;
+        }
+    };
+    if config.manifest_url.is_empty() {
+        tracing::warn!("Update check disabled: updates.manifest_url is not set");
+        return;
+    }
+
+    let url = config.manifest_url.clone();
+    let state_path = data_dir.join(STATE_FILE);
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        loop {
+            run_check(&client, &url, &key, &state_path, &status).await;
+            tokio::time::sleep(WAKE_INTERVAL).await;
+        }
+    });
+}
+
+async fn run_check(
+    client: &reqwest::Client,
+    url: &str,
+    key: &PublicKey,
+    state_path: &Path,
+    status: &UpdateStatus,
+) {
+    let mut state = load_state(state_path);
+
+    if check_due(state.checked_at, Utc::now()) {
+        match fetch_manifest(client, url, key).await {
+            Ok(manifest) => {
+                state = CheckState {
+                    checked_at: Some(Utc::now()),
+                    latest: Some(manifest),
+                };
+                if let Err(e) = save_state(state_path, &state) {
+                    tracing::debug!("Failed to save update check state: {e}");
+                }
+            }
+            Err(e) => tracing::debug!("Update check failed: {e}"),
+        }
+    }
+
+    let current = env!("CARGO_PKG_VERSION");
+    let available = state
+        .latest
+        .filter(|m| is_newer(m, current).unwrap_or(false));
+    if let Some(ref manifest) = available {
+        tracing::info!(
+            "Salita {} is available (running {current})",
+            manifest.version
+        );
+    }
+    status.set(available);
+}
+
+async fn fetch_manifest(
+    client: &reqwest::Client,
+    url: &str,
+    key: &PublicKey,
+) -> anyhow::Result<UpdateManifest> {
+    let body = client
+        .get(url)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    verify_manifest(&body, key)
+}
+
+fn load_state(path: &Path) -> CheckState {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Written to a temp file and renamed into place, so a crash mid-write
+/// can't leave a truncated state behind.
+fn save_state(path: &Path, state: &CheckState) -> anyhow::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(serde_json::to_string_pretty(state)?.as_bytes())?;
+    tmp.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn signed(secret: &SecretKey, payload: &str) -> Vec<u8> {
+        let signature = hex::encode(secret.sign(payload.as_bytes()).to_bytes());
+        serde_json::to_vec(&serde_json::json!({
+            "payload": payload,
+            "signature": signature,
+        }))
+        .unwrap()
+    }
+
+    const PAYLOAD: &str = r#"{"version":"0.3.0","notes_url":"https://example.com/0.3.0"}"#;
+
+    #[test]
+    fn valid_signature_verifies() {
+        let secret = SecretKey::from_bytes(&[7u8; 32]);
+        let manifest = verify_manifest(&signed(&secret, PAYLOAD), &secret.public()).unwrap();
+        assert_eq!(manifest.version, "0.3.0");
+        assert_eq!(
+            manifest.notes_url.as_deref(),
+            Some("https://example.com/0.3.0")
+        );
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let secret = SecretKey::from_bytes(&[7u8; 32]);
+        let other = SecretKey::from_bytes(&[8u8; 32]);
+        assert!(verify_manifest(&signed(&other, PAYLOAD), &secret.public()).is_err());
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let secret = SecretKey::from_bytes(&[7u8; 32]);
+        let body = String::from_utf8(signed(&secret, PAYLOAD)).unwrap();
+        let tampered = body.replace("0.3.0", "9.9.9");
+        assert!(verify_manifest(tampered.as_bytes(), &secret.public()).is_err());
+    }
+
+    #[test]
+    fn public_key_parses_from_hex() {
+        let secret = SecretKey::from_bytes(&[7u8; 32]);
+        let hex_key = hex::encode(secret.public().as_bytes());
+        assert_eq!(hex_key.parse::<PublicKey>().unwrap(), secret.public());
+        if let Some(key) = RELEASE_PUBLIC_KEY {
+            assert!(key.parse::<PublicKey>().is_ok());
+        }
+    }
+
+    #[test]
+    fn state_survives_a_save_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(STATE_FILE);
+        assert!(load_state(&path).checked_at.is_none());
+
+        let state = CheckState {
+            checked_at: Some(Utc::now()),
+            latest: Some(UpdateManifest {
+                version: "0.3.0".to_string(),
+                notes_url: None,
+            }),
+        };
+        save_state(&path, &state).unwrap();
+        save_state(&path, &state).unwrap();
+        let loaded = load_state(&path);
+        assert_eq!(loaded.checked_at, state.checked_at);
+        assert_eq!(loaded.latest, state.latest);
+        // Only the state file; no temp files left beside it
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn version_comparison_handles_prereleases() {
+        let manifest = |v: &str| UpdateManifest {
+            version: v.to_string(),
+            notes_url: None,
+        };
+        assert!(is_newer(&manifest("0.3.0"), "0.2.0").unwrap());
+        assert!(is_newer(&manifest("v0.2.1"), "0.2.0").unwrap());
+        assert!(!is_newer(&manifest("0.2.0"), "0.2.0").unwrap());
+        assert!(!is_newer(&manifest("0.3.0-rc.1"), "0.3.0").unwrap());
+        assert!(is_newer(&manifest("0.3.0"), "0.3.0-rc.1").unwrap());
+        assert!(is_newer(&manifest("not a version"), "0.2.0").is_err());
+    }
+
+    #[test]
+    fn checks_at_most_once_a_day() {
+        let now = Utc::now();
+        assert!(check_due(None, now));
+        assert!(!check_due(Some(now - chrono::Duration::hours(23)), now));
+        assert!(check_due(Some(now - chrono::Duration::hours(24)), now));
+    }
+}